@@ -0,0 +1,558 @@
+use crate::diagnostics::{
+    mask_secret, write_diagnostics_bundle, DiagnosticsSources, DEFAULT_LOG_DAYS,
+};
+use crate::encrypted_storage::DeviceKey;
+use crate::get_api_config;
+use crate::local_time::{local_timezone, local_today};
+use crate::log_reader::{query_logs, query_tail, LogQuery, MAX_PAGE_SIZE};
+use crate::log_shipper::ShipperConfig;
+use crate::log_store::{with_store, LogStorage};
+use crate::log_throttle::RateLimit;
+use crate::logging::{
+    current_log_config, enable_log_encryption, format_log_entry, prune_log_dir, read_log_settings,
+    LogEntry, LOG_DIR_NAME, LOG_SETTINGS_FILE,
+};
+use crate::session_storage::{
+    clear_last_session_in, read_settings_file, SessionAgeLimits, SESSION_SETTINGS_FILE,
+};
+use crate::storage_guard::StorageThresholds;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Must match `identifier` in `tauri.conf.json`, which names the app data directory
+const APP_IDENTIFIER: &str = "com.pyreportal.app";
+
+/// Log lines printed by `logs tail` when `-n` is not given
+const DEFAULT_TAIL_LINES: usize = 20;
+
+/// How often `logs tail --follow` looks for new entries
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+const USAGE: &str = "\
+Usage: pyreportal admin [--data-dir <dir>] <command>
+
+Commands:
+  config                       Show the effective configuration
+  session show                 Print session-settings.json
+  session clear                Forget the last session and turn the toggle off
+  logs tail [-n <lines>] [--follow] [--json]
+                               Print the newest log entries
+  logs grep <regex> [--level <level>] [--source <source>] [--session <id>]
+            [--user <id>] [--since <time>] [--until <time>] [--json]
+                               Print entries whose message or data match
+  logs prune                   Apply the log retention policy now
+  export <dir> [--days <days>] Write a diagnostics bundle into <dir>
+
+Times are RFC 3339, e.g. 2024-06-15T08:00:00Z.";
+
+/// A parsed `pyreportal admin` invocation.
+#[derive(Debug)]
+enum Command {
+    Help,
+    Config,
+    SessionShow,
+    SessionClear,
+    LogsTail {
+        lines: usize,
+        follow: bool,
+        json: bool,
+    },
+    LogsGrep {
+        pattern: Regex,
+        query: LogQuery,
+        json: bool,
+    },
+    LogsPrune,
+    Export {
+        target_dir: PathBuf,
+        days: u32,
+    },
+}
+
+/// Files of the app, found without a running Tauri app.
+struct AdminPaths {
+    data_dir: PathBuf,
+}
+
+impl AdminPaths {
+    /// The app data directory the GUI uses, or `data_dir` if given.
+    fn resolve(data_dir: Option<PathBuf>) -> Result<Self, String> {
+        let data_dir = match data_dir {
+            Some(dir) => dir,
+            None => dirs::data_dir()
+                .ok_or("Failed to get app data directory")?
+                .join(APP_IDENTIFIER),
+        };
+        Ok(Self { data_dir })
+    }
+
+    fn log_dir(&self) -> PathBuf {
+        self.data_dir.join(LOG_DIR_NAME)
+    }
+
+    fn session_settings(&self) -> PathBuf {
+        self.data_dir.join(SESSION_SETTINGS_FILE)
+    }
+
+    fn log_settings(&self) -> PathBuf {
+        self.data_dir.join(LOG_SETTINGS_FILE)
+    }
+}
+
+/// Split arguments into positionals, `--option value` pairs and switches.
+struct Args<'a> {
+    positional: Vec<&'a str>,
+    options: BTreeMap<&'a str, &'a str>,
+    switches: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    const SWITCHES: [&'static str; 4] = ["--follow", "--json", "--help", "-h"];
+
+    fn split(args: &'a [String]) -> Result<Self, String> {
+        let mut parsed = Self {
+            positional: Vec::new(),
+            options: BTreeMap::new(),
+            switches: Vec::new(),
+        };
+        let mut args = args.iter().map(String::as_str);
+        while let Some(arg) = args.next() {
+            if Self::SWITCHES.contains(&arg) {
+                parsed.switches.push(arg);
+            } else if arg.starts_with('-') {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {arg}"))?;
+                parsed.options.insert(arg, value);
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&mut self, name: &str) -> Option<&'a str> {
+        self.options.remove(name)
+    }
+
+    fn parsed_option<T: std::str::FromStr>(&mut self, name: &str) -> Result<Option<T>, String> {
+        self.option(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {name}: {value}"))
+            })
+            .transpose()
+    }
+
+    fn switch(&mut self, name: &str) -> bool {
+        let before = self.switches.len();
+        self.switches.retain(|switch| *switch != name);
+        self.switches.len() != before
+    }
+
+    fn time_option(&mut self, name: &str) -> Result<Option<DateTime<Utc>>, String> {
+        self.option(name)
+            .map(|value| {
+                DateTime::parse_from_rfc3339(value)
+                    .map(|time| time.with_timezone(&Utc))
+                    .map_err(|e| format!("Invalid time for {name} '{value}': {e}"))
+            })
+            .transpose()
+    }
+
+    /// Fail on anything the command didn't take.
+    fn finish(self) -> Result<(), String> {
+        match (
+            self.options.keys().next(),
+            self.switches.first(),
+            self.positional.first(),
+        ) {
+            (Some(option), _, _) => Err(format!("Unknown option: {option}")),
+            (_, Some(switch), _) => Err(format!("Unknown option: {switch}")),
+            (_, _, Some(arg)) => Err(format!("Unexpected argument: {arg}")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parse the arguments after `admin` into the data directory override and a command.
+fn parse_args(args: &[String]) -> Result<(Option<PathBuf>, Command), String> {
+    let mut args = Args::split(args)?;
+    let data_dir = args.option("--data-dir").map(PathBuf::from);
+    if args.switch("--help") || args.switch("-h") || args.positional.is_empty() {
+        return Ok((data_dir, Command::Help));
+    }
+
+    let words: Vec<&str> = args.positional.drain(..).collect();
+    let command = match words.as_slice() {
+        ["config"] => Command::Config,
+        ["session", "show"] => Command::SessionShow,
+        ["session", "clear"] => Command::SessionClear,
+        ["logs", "tail"] => Command::LogsTail {
+            lines: args.parsed_option("-n")?.unwrap_or(DEFAULT_TAIL_LINES),
+            follow: args.switch("--follow"),
+            json: args.switch("--json"),
+        },
+        ["logs", "grep", pattern] => Command::LogsGrep {
+            pattern: Regex::new(pattern).map_err(|e| format!("Invalid pattern: {e}"))?,
+            query: LogQuery {
+                level: args.parsed_option("--level")?,
+                source: args.option("--source").map(str::to_string),
+                session_id: args.option("--session").map(str::to_string),
+                user_id: args.option("--user").map(str::to_string),
+                from: args.time_option("--since")?,
+                to: args.time_option("--until")?,
+                ..LogQuery::default()
+            },
+            json: args.switch("--json"),
+        },
+        ["logs", "prune"] => Command::LogsPrune,
+        ["export", target_dir] => Command::Export {
+            target_dir: PathBuf::from(target_dir),
+            days: args
+                .parsed_option("--days")?
+                .unwrap_or(DEFAULT_LOG_DAYS)
+                .max(1),
+        },
+        _ => return Err(format!("Unknown command: {}", words.join(" "))),
+    };
+    args.finish()?;
+    Ok((data_dir, command))
+}
+
+fn print(out: &mut impl Write, text: &str) -> Result<(), String> {
+    writeln!(out, "{text}").map_err(|e| format!("Failed to write output: {e}"))
+}
+
+fn print_entry(out: &mut impl Write, entry: &LogEntry, json: bool) -> Result<(), String> {
+    if json {
+        let line =
+            serde_json::to_string(entry).map_err(|e| format!("Failed to serialize entry: {e}"))?;
+        print(out, &line)
+    } else {
+        print(out, &format_log_entry(entry))
+    }
+}
+
+/// Page through all entries matching `query`, starting at its cursor. Leaves the cursor
+/// after the last entry, so the query can be polled for new ones.
+fn for_each_entry(
+    log_dir: &Path,
+    query: &mut LogQuery,
+    mut visit: impl FnMut(LogEntry) -> Result<(), String>,
+) -> Result<(), String> {
+    query.limit = Some(MAX_PAGE_SIZE);
+    loop {
+        let page = query_logs(log_dir, query)?;
+        query.cursor = page.next_cursor;
+        for entry in page.entries {
+            visit(entry)?;
+        }
+        if !page.has_more {
+            return Ok(());
+        }
+    }
+}
+
+fn show_config(paths: &AdminPaths, out: &mut impl Write) -> Result<(), String> {
+    let config = current_log_config();
+    let rate_limit = RateLimit::from_env();
+    let thresholds = StorageThresholds::from_env();
+    let shipping = ShipperConfig::from_env();
+    let session_limits = SessionAgeLimits::from_env();
+
+    let effective = json!({
+        "dataDir": paths.data_dir,
+        "logDir": paths.log_dir(),
+        "api": match get_api_config() {
+            Ok(api) => json!({
+                "apiBaseUrl": api.api_base_url,
+                "deviceApiKey": mask_secret(&api.device_api_key),
+            }),
+            Err(e) => json!({ "error": e }),
+        },
+        "localTimezone": local_timezone().name(),
+        "logging": {
+            // A level chosen through set_log_level overrides LOG_PERSIST_LEVEL
+            "persistLevel": read_log_settings(&paths.log_settings())?
+                .unwrap_or(config.persist_level),
+            "storage": config.storage.to_string(),
+            "auditChain": config.audit_chain,
+            "retentionDays": config.retention.retention_days,
+            "maxTotalBytes": config.retention.max_total_bytes,
+            "maxFileBytes": config.retention.max_file_bytes,
+            "maxClockSkewSecs": config.max_clock_skew.num_seconds(),
+            "rateLimitBurst": rate_limit.burst,
+            "rateLimitWindowSecs": rate_limit.window.num_seconds(),
+        },
+        "logShipping": match shipping {
+            Ok(shipping) => shipping.map(|shipping| json!({
+                "endpoint": shipping.endpoint,
+                "batchSize": shipping.batch_size,
+                "intervalSecs": shipping.interval.as_secs(),
+            })),
+            Err(e) => Some(json!({ "error": e })),
+        },
+        "storage": {
+            "lowBytes": thresholds.low_bytes,
+            "criticalBytes": thresholds.critical_bytes,
+            "availableBytes": fs4::available_space(&paths.data_dir).ok(),
+        },
+        "lastSession": {
+            "staleAfterDays": session_limits.stale_after.num_days(),
+            "maxAgeDays": session_limits.max_age.num_days(),
+        },
+    });
+    let text = serde_json::to_string_pretty(&effective)
+        .map_err(|e| format!("Failed to serialize config: {e}"))?;
+    print(out, &text)
+}
+
+fn tail_logs(
+    log_dir: &Path,
+    lines: usize,
+    follow: bool,
+    json: bool,
+    out: &mut impl Write,
+) -> Result<(), String> {
+    let recent = query_tail(log_dir, lines)?;
+    for entry in &recent.entries {
+        print_entry(out, entry, json)?;
+    }
+
+    if !follow {
+        return Ok(());
+    }
+    let mut query = LogQuery {
+        cursor: recent.next_cursor,
+        ..LogQuery::default()
+    };
+    loop {
+        out.flush()
+            .map_err(|e| format!("Failed to write output: {e}"))?;
+        thread::sleep(FOLLOW_INTERVAL);
+        for_each_entry(log_dir, &mut query, |entry| print_entry(out, &entry, json))?;
+    }
+}
+
+fn grep_logs(
+    log_dir: &Path,
+    pattern: &Regex,
+    mut query: LogQuery,
+    json: bool,
+    out: &mut impl Write,
+) -> Result<(), String> {
+    for_each_entry(log_dir, &mut query, |entry| {
+        let data = entry.data.as_ref().map(ToString::to_string);
+        if pattern.is_match(&entry.message) || data.is_some_and(|data| pattern.is_match(&data)) {
+            print_entry(out, &entry, json)?;
+        }
+        Ok(())
+    })
+}
+
+fn prune(log_dir: &Path, out: &mut impl Write) -> Result<(), String> {
+    let config = current_log_config();
+    let deleted = prune_log_dir(log_dir)?;
+    print(out, &format!("Deleted {deleted} expired log files"))?;
+
+    if config.storage == LogStorage::Sqlite {
+        let deleted = with_store(log_dir, |store| {
+            store.apply_retention(&config.retention, local_today())
+        })?;
+        print(out, &format!("Deleted {deleted} expired database entries"))?;
+    }
+    Ok(())
+}
+
+fn execute(command: Command, paths: &AdminPaths, out: &mut impl Write) -> Result<(), String> {
+    // Logs written by the app are sealed with its device key; this never creates one
+    if let Some(key) = DeviceKey::load_existing(&paths.data_dir)? {
+        enable_log_encryption(&paths.log_dir(), key);
+    }
+    match command {
+        Command::Help => print(out, USAGE),
+        Command::Config => show_config(paths, out),
+        Command::SessionShow => match read_settings_file(&paths.session_settings())? {
+            Some(settings) => {
+                let text = serde_json::to_string_pretty(&settings)
+                    .map_err(|e| format!("Failed to serialize session settings: {e}"))?;
+                print(out, &text)
+            }
+            None => print(out, "No session settings saved"),
+        },
+        Command::SessionClear => {
+            clear_last_session_in(&paths.session_settings())?;
+            print(out, "Last session cleared")
+        }
+        Command::LogsTail {
+            lines,
+            follow,
+            json,
+        } => tail_logs(&paths.log_dir(), lines, follow, json, out),
+        Command::LogsGrep {
+            pattern,
+            query,
+            json,
+        } => grep_logs(&paths.log_dir(), &pattern, query, json, out),
+        Command::LogsPrune => prune(&paths.log_dir(), out),
+        Command::Export { target_dir, days } => {
+            let bundle = write_diagnostics_bundle(
+                &target_dir,
+                DiagnosticsSources {
+                    log_dir: &paths.log_dir(),
+                    log_storage: current_log_config().storage,
+                    settings_path: &paths.session_settings(),
+                    redactor: current_log_config().redactor,
+                    api_config: get_api_config(),
+                    log_days: days,
+                },
+                Utc::now(),
+            )?;
+            print(out, &bundle.to_string_lossy())
+        }
+    }
+}
+
+/// Run `pyreportal admin <args>` without starting the webview. Returns the exit code.
+#[must_use]
+pub fn run_admin(args: &[String]) -> i32 {
+    dotenvy::dotenv().ok();
+
+    let (data_dir, command) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return 2;
+        }
+    };
+    let result = AdminPaths::resolve(data_dir)
+        .and_then(|paths| execute(command, &paths, &mut io::stdout().lock()));
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::entry;
+    use crate::logging::LogLevel;
+    use std::fs;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn run(paths: &AdminPaths, line: &str) -> Result<String, String> {
+        let (_, command) = parse_args(&args(line))?;
+        let mut out = Vec::new();
+        execute(command, paths, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn sample_paths() -> (tempfile::TempDir, AdminPaths) {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = AdminPaths {
+            data_dir: tmp.path().to_path_buf(),
+        };
+        fs::create_dir_all(paths.log_dir()).unwrap();
+        fs::write(
+            paths.log_dir().join("pyre-portal-2024-06-15.log"),
+            [
+                entry("Started").at("2024-06-15T08:00:00Z").line(),
+                entry("Reader timeout")
+                    .at("2024-06-15T08:01:00Z")
+                    .level(LogLevel::Warn)
+                    .source("RFID")
+                    .line(),
+                entry("Reader lost")
+                    .at("2024-06-15T08:02:00Z")
+                    .level(LogLevel::Error)
+                    .source("RFID")
+                    .line(),
+            ]
+            .join("\n")
+                + "\n",
+        )
+        .unwrap();
+        (tmp, paths)
+    }
+
+    #[test]
+    fn app_identifier_matches_tauri_config() {
+        let config: serde_json::Value =
+            serde_json::from_str(include_str!("../tauri.conf.json")).unwrap();
+        assert_eq!(config["identifier"], APP_IDENTIFIER);
+    }
+
+    #[test]
+    fn parse_args_rejects_unknown_commands_and_options() {
+        assert!(matches!(parse_args(&[]).unwrap().1, Command::Help));
+        assert!(parse_args(&args("logs frobnicate")).is_err());
+        assert!(parse_args(&args("config --verbose yes")).is_err());
+        assert!(parse_args(&args("logs tail -n many")).is_err());
+
+        let (data_dir, command) =
+            parse_args(&args("--data-dir /tmp/x logs tail -n 5 --json")).unwrap();
+        assert_eq!(data_dir, Some(PathBuf::from("/tmp/x")));
+        assert!(matches!(
+            command,
+            Command::LogsTail {
+                lines: 5,
+                follow: false,
+                json: true
+            }
+        ));
+    }
+
+    #[test]
+    fn logs_tail_prints_newest_entries() {
+        let (_tmp, paths) = sample_paths();
+        let output = run(&paths, "logs tail -n 2").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("[WARN] [RFID] Reader timeout"));
+        assert!(lines[1].contains("Reader lost"));
+    }
+
+    #[test]
+    fn logs_grep_combines_pattern_and_filters() {
+        let (_tmp, paths) = sample_paths();
+        let output = run(&paths, "logs grep ^Reader --level ERROR --json").unwrap();
+        let entry: LogEntry = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(entry.message, "Reader lost");
+
+        let output = run(&paths, "logs grep (?i)started").unwrap();
+        assert_eq!(output.lines().count(), 1);
+    }
+
+    #[test]
+    fn session_show_and_clear_use_settings_file() {
+        let (_tmp, paths) = sample_paths();
+        assert_eq!(
+            run(&paths, "session show").unwrap().trim(),
+            "No session settings saved"
+        );
+
+        fs::write(
+            paths.session_settings(),
+            r#"{"use_last_session":true,"auto_save_enabled":true,"last_session":null}"#,
+        )
+        .unwrap();
+        run(&paths, "session clear").unwrap();
+        let output = run(&paths, "session show").unwrap();
+        assert!(output.contains(r#""use_last_session": false"#));
+    }
+}
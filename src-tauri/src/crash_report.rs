@@ -0,0 +1,311 @@
+use crate::encrypted_storage::{is_sealed, DeviceKey};
+use crate::log_store::{try_with_store, LogStorage};
+use crate::logging::{
+    current_log_config, get_log_directory, list_log_files, log_key, open_log_line,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::borrow::Cow;
+use std::fs;
+use std::io::BufRead;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Instant;
+use tauri::{AppHandle, Runtime};
+
+const CRASH_FILE_PREFIX: &str = "crash-";
+const CRASH_FILE_EXTENSION: &str = ".json";
+/// What crash reports are encrypted as
+const CRASH_REPORT_CONTEXT: &str = "crash-report";
+/// Log lines included in a report
+const RECENT_LOG_LINES: usize = 100;
+/// How far back from the end of a log file the recent lines are looked for
+const TAIL_BYTES: u64 = 256 * 1024;
+/// Older reports are deleted when a new one is written
+const MAX_CRASH_REPORTS: usize = 20;
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+/// Set once the app knows its log directory, with the storage the recent log lines are
+/// read from and the key of the logs; panics before that only reach stderr.
+static CRASH_DIR: OnceLock<(PathBuf, LogStorage, Option<DeviceKey>)> = OnceLock::new();
+
+/// A panic of the Rust backend, written as `crash-<timestamp>.json` next to the daily logs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub timestamp: String,
+    pub app_version: String,
+    /// Seconds since the panic hook was installed at startup
+    pub uptime_secs: u64,
+    pub thread: Option<String>,
+    pub message: String,
+    /// `file:line:column` of the panic
+    pub location: Option<String>,
+    pub backtrace: String,
+    /// Last persisted log lines; entries still queued in the log writer are not included
+    pub recent_logs: Vec<String>,
+}
+
+impl CrashReport {
+    fn capture(
+        info: &PanicHookInfo<'_>,
+        log_dir: &Path,
+        storage: LogStorage,
+        key: Option<&DeviceKey>,
+    ) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| (*s).to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_secs: STARTED
+                .get()
+                .map_or(0, |started| started.elapsed().as_secs()),
+            thread: thread::current().name().map(str::to_string),
+            message,
+            location: info
+                .location()
+                .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+            backtrace: Backtrace::force_capture().to_string(),
+            recent_logs: match storage {
+                LogStorage::Jsonl => recent_log_lines(log_dir, key, RECENT_LOG_LINES),
+                // Left out if the panic hit while the database was in use
+                LogStorage::Sqlite => try_with_store(log_dir, |store| {
+                    store.recent_lines(RECENT_LOG_LINES).unwrap_or_default()
+                })
+                .unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// Install a panic hook that writes a crash report before the default hook runs.
+///
+/// Call as early as possible; reports are only written once `set_crash_report_dir` ran.
+pub fn install_panic_hook() {
+    STARTED.get_or_init(Instant::now);
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Some((log_dir, storage, key)) = CRASH_DIR.get() {
+            let report = CrashReport::capture(info, log_dir, *storage, key.as_ref());
+            match write_crash_report(log_dir, key.as_ref(), &report) {
+                Ok(path) => eprintln!("Crash report written to {}", path.display()),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+        previous(info);
+    }));
+}
+
+/// Let the panic hook write its reports into the log directory, encrypted like the logs.
+pub fn set_crash_report_dir<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let log_dir = get_log_directory(app).map_err(|e| e.to_string())?;
+    // Read now: the panic hook must not wait for the config lock
+    let storage = current_log_config().storage;
+    let key = log_key(&log_dir);
+    let _ = CRASH_DIR.set((log_dir, storage, key));
+    Ok(())
+}
+
+/// The last `count` lines of the log stream, read from the end of the newest files and
+/// decrypted with `key`.
+fn recent_log_lines(log_dir: &Path, key: Option<&DeviceKey>, count: usize) -> Vec<String> {
+    let Ok(files) = list_log_files(log_dir) else {
+        return Vec::new();
+    };
+
+    let mut lines = Vec::new();
+    for file in files.iter().rev() {
+        let start = file.content_size.saturating_sub(TAIL_BYTES);
+        let Ok(reader) = file.open_at(start) else {
+            continue;
+        };
+        let mut file_lines: Vec<String> = reader.lines().map_while(Result::ok).collect();
+        // Reading started in the middle of a line
+        if start > 0 && !file_lines.is_empty() {
+            file_lines.remove(0);
+        }
+        let mut file_lines: Vec<String> = file_lines
+            .iter()
+            .filter_map(|line| open_log_line(key, line).map(Cow::into_owned))
+            .collect();
+        file_lines.append(&mut lines);
+        lines = file_lines;
+        if lines.len() >= count {
+            break;
+        }
+    }
+    lines.split_off(lines.len().saturating_sub(count))
+}
+
+/// Paths of the crash reports in `log_dir`, oldest first.
+pub(crate) fn list_crash_report_files(log_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(log_dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry.file_name().to_str().is_some_and(|name| {
+                name.starts_with(CRASH_FILE_PREFIX) && name.ends_with(CRASH_FILE_EXTENSION)
+            })
+        })
+        .map(|entry| entry.path())
+        .collect();
+    // File names embed a sortable timestamp
+    files.sort();
+    files
+}
+
+/// Write `report` into `log_dir`, sealed with `key` if given, and delete the oldest
+/// reports beyond the limit.
+pub(crate) fn write_crash_report(
+    log_dir: &Path,
+    key: Option<&DeviceKey>,
+    report: &CrashReport,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(log_dir).map_err(|e| format!("Failed to create log directory: {e}"))?;
+
+    let path = log_dir.join(format!(
+        "{CRASH_FILE_PREFIX}{}{CRASH_FILE_EXTENSION}",
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    let mut json = serde_json::to_vec_pretty(report)
+        .map_err(|e| format!("Failed to serialize crash report: {e}"))?;
+    if let Some(key) = key {
+        json = key.seal(CRASH_REPORT_CONTEXT, &json)?;
+    }
+    fs::write(&path, json).map_err(|e| format!("Failed to write crash report: {e}"))?;
+
+    let files = list_crash_report_files(log_dir);
+    for old in &files[..files.len().saturating_sub(MAX_CRASH_REPORTS)] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+/// The JSON of the crash report at `path`, decrypted with `key`.
+pub(crate) fn read_crash_report_file(
+    path: &Path,
+    key: Option<&DeviceKey>,
+) -> Result<Vec<u8>, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read crash report: {e}"))?;
+    match key {
+        Some(key) => key.open(CRASH_REPORT_CONTEXT, data),
+        None if is_sealed(&data) => Err("Failed to decrypt crash report: no storage key".into()),
+        None => Ok(data),
+    }
+}
+
+/// Read the crash reports in `log_dir`, newest first. Unreadable reports are skipped.
+pub(crate) fn read_crash_reports(log_dir: &Path) -> Vec<CrashReport> {
+    let key = log_key(log_dir);
+    list_crash_report_files(log_dir)
+        .iter()
+        .rev()
+        .filter_map(|path| read_crash_report_file(path, key.as_ref()).ok())
+        .filter_map(|data| serde_json::from_slice(&data).ok())
+        .collect()
+}
+
+/// List crash reports of earlier runs, newest first
+#[tauri::command]
+pub async fn list_crash_reports<R: Runtime>(app: AppHandle<R>) -> Result<Vec<CrashReport>, String> {
+    let log_dir = get_log_directory(&app).map_err(|e| e.to_string())?;
+    Ok(read_crash_reports(&log_dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{enable_log_encryption, LOG_LINE_CONTEXT};
+
+    fn report(message: &str) -> CrashReport {
+        CrashReport {
+            timestamp: "2024-06-15T08:00:00.000Z".to_string(),
+            app_version: "1.0.0".to_string(),
+            uptime_secs: 5,
+            thread: Some("main".to_string()),
+            message: message.to_string(),
+            location: None,
+            backtrace: String::new(),
+            recent_logs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reports_are_listed_newest_first() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_crash_report(tmp.path(), None, &report("first")).unwrap();
+        thread::sleep(std::time::Duration::from_millis(5));
+        write_crash_report(tmp.path(), None, &report("second")).unwrap();
+        fs::write(tmp.path().join("crash-broken.json"), "{").unwrap();
+
+        let messages: Vec<String> = read_crash_reports(tmp.path())
+            .into_iter()
+            .map(|r| r.message)
+            .collect();
+        assert_eq!(messages, ["second", "first"]);
+    }
+
+    #[test]
+    fn old_reports_are_pruned() {
+        let tmp = tempfile::tempdir().unwrap();
+        for i in 0..MAX_CRASH_REPORTS + 2 {
+            fs::write(tmp.path().join(format!("crash-2024{i:04}.json")), "{}").unwrap();
+        }
+        write_crash_report(tmp.path(), None, &report("latest")).unwrap();
+
+        let files = list_crash_report_files(tmp.path());
+        assert_eq!(files.len(), MAX_CRASH_REPORTS);
+        assert!(!tmp.path().join("crash-20240000.json").exists());
+    }
+
+    #[test]
+    fn recent_log_lines_span_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("pyre-portal-2024-06-14.log"), "a\nb\n").unwrap();
+        fs::write(tmp.path().join("pyre-portal-2024-06-15.log.1"), "c\n").unwrap();
+        fs::write(tmp.path().join("pyre-portal-2024-06-15.log"), "d\ne\n").unwrap();
+
+        assert_eq!(recent_log_lines(tmp.path(), None, 4), ["b", "c", "d", "e"]);
+        assert_eq!(recent_log_lines(tmp.path(), None, 10).len(), 5);
+    }
+
+    #[test]
+    fn sealed_reports_and_log_lines_are_read_with_the_key() {
+        let tmp = tempfile::tempdir().unwrap();
+        let key = DeviceKey::load_or_create(tmp.path()).unwrap();
+        let log_dir = tmp.path().join("logs");
+        let path = write_crash_report(&log_dir, Some(&key), &report("Herr Müller")).unwrap();
+        assert!(is_sealed(&fs::read(&path).unwrap()));
+
+        // Unreadable without the key
+        assert!(read_crash_reports(&log_dir).is_empty());
+        enable_log_encryption(&log_dir, key.clone());
+        let messages: Vec<String> = read_crash_reports(&log_dir)
+            .into_iter()
+            .map(|r| r.message)
+            .collect();
+        assert_eq!(messages, ["Herr Müller"]);
+
+        let sealed = key.seal_line(LOG_LINE_CONTEXT, "secret").unwrap();
+        fs::write(
+            log_dir.join("pyre-portal-2024-06-15.log"),
+            format!("plain\n{sealed}\n"),
+        )
+        .unwrap();
+        assert_eq!(
+            recent_log_lines(&log_dir, Some(&key), 10),
+            ["plain", "secret"]
+        );
+    }
+}
@@ -0,0 +1,540 @@
+use crate::crash_report::{list_crash_report_files, read_crash_report_file};
+use crate::encrypted_storage::DeviceKey;
+use crate::local_time::{local_date, local_timezone};
+use crate::log_store::{with_store, LogStorage};
+use crate::logging::{
+    current_log_config, flush_log_writer, get_log_directory, list_log_files, log_key,
+    open_log_line, COMPRESSED_EXTENSION,
+};
+use crate::redaction::Redactor;
+use crate::session_storage::{get_session_settings_path, read_settings_plaintext};
+use crate::{get_api_config, ApiConfig};
+use chrono::{DateTime, Duration, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, Runtime};
+
+/// Number of days of log files included when the caller doesn't specify it
+pub(crate) const DEFAULT_LOG_DAYS: u32 = 7;
+
+/// Description of one file in the bundle
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestFile {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+/// `manifest.json`, written last so it can list every other file
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    created_at: String,
+    app_version: &'static str,
+    files: Vec<ManifestFile>,
+}
+
+/// `environment.json`: what the bundle was produced on
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Environment {
+    app_version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    family: &'static str,
+}
+
+/// Everything a diagnostic bundle is built from
+pub(crate) struct DiagnosticsSources<'a> {
+    pub log_dir: &'a Path,
+    /// Where the log entries are read from
+    pub log_storage: LogStorage,
+    pub settings_path: &'a Path,
+    /// Applied to the session settings, which are stored encrypted but leave the device here
+    pub redactor: Arc<Redactor>,
+    pub api_config: Result<ApiConfig, String>,
+    /// Days of log files to include, counting today
+    pub log_days: u32,
+}
+
+/// Replace all but the last four characters of a secret with `*`.
+pub(crate) fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    let visible = if chars.len() > 8 { 4 } else { 0 };
+    let masked = "*".repeat(chars.len() - visible);
+    masked + &chars[chars.len() - visible..].iter().collect::<String>()
+}
+
+/// Counts and hashes everything written through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Appends files to the archive and records their checksums for the manifest.
+struct BundleWriter {
+    archive: tar::Builder<GzEncoder<File>>,
+    files: Vec<ManifestFile>,
+    mtime: u64,
+    /// Where streamed files are spooled before they are appended (see `add_streamed`)
+    spool_path: PathBuf,
+}
+
+impl BundleWriter {
+    fn append(&mut self, name: &str, size: u64, data: impl Read) -> Result<(), String> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(self.mtime);
+        header.set_cksum();
+        self.archive
+            .append_data(&mut header, name, data)
+            .map_err(|e| format!("Failed to add {name} to diagnostics bundle: {e}"))
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        self.append(name, data.len() as u64, data)?;
+        self.files.push(ManifestFile {
+            name: name.to_string(),
+            size: data.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(data)),
+        });
+        Ok(())
+    }
+
+    /// Add what `write` produces as `name` without holding it in memory. It is spooled to
+    /// a file next to the bundle first: the archive needs the size up front, and the logs
+    /// are decrypted (and may still grow) while they are read.
+    fn add_streamed(
+        &mut self,
+        name: &str,
+        write: impl FnOnce(&mut HashingWriter<BufWriter<File>>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let spool_error = |e| format!("Failed to add {name} to diagnostics bundle: {e}");
+        let result = File::create(&self.spool_path)
+            .map_err(spool_error)
+            .and_then(|file| {
+                let mut spool = HashingWriter {
+                    inner: BufWriter::new(file),
+                    hasher: Sha256::new(),
+                    size: 0,
+                };
+                write(&mut spool)?;
+                spool.flush().map_err(spool_error)?;
+                let spooled = File::open(&self.spool_path).map_err(spool_error)?;
+                self.append(name, spool.size, spooled)?;
+                self.files.push(ManifestFile {
+                    name: name.to_string(),
+                    size: spool.size,
+                    sha256: format!("{:x}", spool.hasher.finalize()),
+                });
+                Ok(())
+            });
+        let _ = fs::remove_file(&self.spool_path);
+        result
+    }
+
+    fn add_json<T: Serialize>(&mut self, name: &str, value: &T) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(value)
+            .map_err(|e| format!("Failed to serialize {name}: {e}"))?;
+        self.add(name, &json)
+    }
+}
+
+/// Copy the lines of a log file to `out` decrypted with `key`, so the bundle can be read
+/// on other devices. Lines that can't be decrypted are left out.
+fn copy_plaintext_lines(
+    mut reader: impl BufRead,
+    key: Option<&DeviceKey>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&line);
+        if let Some(text) = open_log_line(key, text.trim_end_matches('\n')) {
+            out.write_all(text.as_bytes())?;
+            out.write_all(b"\n")?;
+        }
+    }
+}
+
+/// Write a `.tar.gz` diagnostic bundle into `target_dir` and return its path.
+pub(crate) fn write_diagnostics_bundle(
+    target_dir: &Path,
+    sources: DiagnosticsSources,
+    now: DateTime<Utc>,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(target_dir)
+        .map_err(|e| format!("Failed to create export directory: {e}"))?;
+
+    let bundle_path = target_dir.join(format!(
+        "pyreportal-diagnostics-{}.tar.gz",
+        now.with_timezone(&local_timezone()).format("%Y%m%d-%H%M%S")
+    ));
+    let file = File::create(&bundle_path)
+        .map_err(|e| format!("Failed to create diagnostics bundle: {e}"))?;
+
+    let mut bundle = BundleWriter {
+        archive: tar::Builder::new(GzEncoder::new(file, Compression::default())),
+        files: Vec::new(),
+        mtime: u64::try_from(now.timestamp()).unwrap_or_default(),
+        spool_path: target_dir.join(".pyreportal-diagnostics.part"),
+    };
+
+    let key = log_key(sources.log_dir);
+    let oldest = local_date(now) - Duration::days(i64::from(sources.log_days) - 1);
+    if sources.log_storage == LogStorage::Sqlite {
+        // The database holds the daily files too, they are imported when it is opened
+        let since = oldest
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| midnight.and_local_timezone(local_timezone()).earliest())
+            .map(|midnight| midnight.with_timezone(&Utc));
+        bundle.add_streamed("logs/pyre-portal-logs.jsonl", |out| {
+            with_store(sources.log_dir, |store| store.export_jsonl(out, since)).map(|_| ())
+        })?;
+    } else if sources.log_dir.exists() {
+        // Recent log files (a file may disappear through rollover while we're exporting)
+        for log_file in list_log_files(sources.log_dir)? {
+            if log_file.date < oldest {
+                continue;
+            }
+            let Ok(reader) = log_file.open_at(0) else {
+                continue;
+            };
+            // Completed days are stored gzipped; the bundle holds them as plain JSONL
+            let name = log_file
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let name = name.strip_suffix(COMPRESSED_EXTENSION).unwrap_or(&name);
+            bundle.add_streamed(&format!("logs/{name}"), |out| {
+                copy_plaintext_lines(reader, key.as_ref(), out)
+                    .map_err(|e| format!("Failed to export log file: {e}"))
+            })?;
+        }
+    }
+
+    for report in list_crash_report_files(sources.log_dir) {
+        let Ok(data) = read_crash_report_file(&report, key.as_ref()) else {
+            continue;
+        };
+        // Panic messages and backtraces never went through the log redaction
+        let data = sources
+            .redactor
+            .redact_text(&String::from_utf8_lossy(&data));
+        let name = report.file_name().unwrap_or_default().to_string_lossy();
+        bundle.add(&format!("crash-reports/{name}"), data.as_bytes())?;
+    }
+
+    // Supervisor names and IDs are redacted like in the logs; the raw file is never included
+    if let Ok(data) = read_settings_plaintext(sources.settings_path) {
+        match serde_json::from_slice::<Value>(&data) {
+            Ok(mut settings) => {
+                sources.redactor.redact_json(&mut settings);
+                bundle.add_json("session-settings.json", &settings)?;
+            }
+            Err(e) => bundle.add_json(
+                "session-settings.json",
+                &serde_json::json!({ "error": format!("Failed to parse session settings: {e}") }),
+            )?,
+        }
+    }
+
+    match sources.api_config {
+        Ok(config) => bundle.add_json(
+            "api-config.json",
+            &ApiConfig {
+                device_api_key: mask_secret(&config.device_api_key),
+                ..config
+            },
+        )?,
+        Err(e) => bundle.add_json("api-config.json", &serde_json::json!({ "error": e }))?,
+    }
+
+    bundle.add_json(
+        "environment.json",
+        &Environment {
+            app_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            family: std::env::consts::FAMILY,
+        },
+    )?;
+
+    let manifest = Manifest {
+        created_at: now.to_rfc3339(),
+        app_version: env!("CARGO_PKG_VERSION"),
+        files: std::mem::take(&mut bundle.files),
+    };
+    bundle.add_json("manifest.json", &manifest)?;
+
+    bundle
+        .archive
+        .into_inner()
+        .and_then(GzEncoder::finish)
+        .map_err(|e| format!("Failed to finish diagnostics bundle: {e}"))?;
+
+    Ok(bundle_path)
+}
+
+/// Export logs, session settings and the redacted config as a `.tar.gz` into `target_dir`
+///
+/// Returns the path of the created bundle.
+#[tauri::command]
+pub async fn export_diagnostics<R: Runtime>(
+    app: AppHandle<R>,
+    target_dir: String,
+    log_days: Option<u32>,
+) -> Result<String, String> {
+    let log_dir = get_log_directory(&app).map_err(|e| e.to_string())?;
+    let settings_path = get_session_settings_path(&app)?;
+    flush_log_writer(&app);
+
+    let bundle_path = write_diagnostics_bundle(
+        Path::new(&target_dir),
+        DiagnosticsSources {
+            log_dir: &log_dir,
+            log_storage: current_log_config().storage,
+            settings_path: &settings_path,
+            redactor: current_log_config().redactor,
+            api_config: get_api_config(),
+            log_days: log_days.unwrap_or(DEFAULT_LOG_DAYS).max(1),
+        },
+        Utc::now(),
+    )?;
+
+    Ok(bundle_path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::entry;
+    use flate2::read::GzDecoder;
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    fn now() -> DateTime<Utc> {
+        "2024-06-15T10:30:00Z".parse().unwrap()
+    }
+
+    fn sample_config() -> ApiConfig {
+        ApiConfig {
+            api_base_url: "http://localhost:8080".to_string(),
+            device_api_key: "secret-device-key-1234".to_string(),
+        }
+    }
+
+    fn read_bundle(path: &Path) -> HashMap<String, Vec<u8>> {
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(path).unwrap()));
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let name = entry.path().unwrap().to_string_lossy().into_owned();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                (name, data)
+            })
+            .collect()
+    }
+
+    fn export(tmp: &Path, api_config: Result<ApiConfig, String>) -> HashMap<String, Vec<u8>> {
+        let log_dir = tmp.join("logs");
+        fs::create_dir_all(&log_dir).unwrap();
+        fs::write(log_dir.join("pyre-portal-2024-06-15.log"), "today\n").unwrap();
+        fs::write(log_dir.join("pyre-portal-2024-06-14.log.1"), "yesterday\n").unwrap();
+        fs::write(log_dir.join("pyre-portal-2024-06-01.log"), "old\n").unwrap();
+        let settings_path = tmp.join("session-settings.json");
+        let session = serde_json::json!({
+            "activity_id": 7,
+            "room_id": 3,
+            "supervisor_ids": [4711, 4712],
+            "saved_at": "2024-06-15T08:00:00Z",
+            "activity_name": "Fußball",
+            "room_name": "Turnhalle",
+            "supervisor_names": ["Herr Müller", "Frau Schmidt"],
+        });
+        let settings = serde_json::json!({
+            "schema_version": 3,
+            "use_last_session": true,
+            "auto_save_enabled": true,
+            "last_session": session,
+            "presets": [{"id": 1, "name": "Fußball", "session": session}],
+            "session_history": [session],
+        });
+        fs::write(&settings_path, settings.to_string()).unwrap();
+
+        let bundle = write_diagnostics_bundle(
+            &tmp.join("export"),
+            DiagnosticsSources {
+                log_dir: &log_dir,
+                log_storage: LogStorage::Jsonl,
+                settings_path: &settings_path,
+                redactor: Arc::default(),
+                api_config,
+                log_days: 7,
+            },
+            now(),
+        )
+        .unwrap();
+        assert!(bundle.ends_with("pyreportal-diagnostics-20240615-123000.tar.gz"));
+        read_bundle(&bundle)
+    }
+
+    #[test]
+    fn mask_secret_keeps_only_last_four_characters() {
+        assert_eq!(
+            mask_secret("secret-device-key-1234"),
+            "******************1234"
+        );
+        assert_eq!(mask_secret("short"), "*****");
+        assert_eq!(mask_secret(""), "");
+    }
+
+    #[test]
+    fn bundle_contains_recent_logs_settings_and_metadata() {
+        let tmp = tempfile::tempdir().unwrap();
+        let files = export(tmp.path(), Ok(sample_config()));
+
+        assert_eq!(files["logs/pyre-portal-2024-06-15.log"], b"today\n");
+        assert_eq!(files["logs/pyre-portal-2024-06-14.log.1"], b"yesterday\n");
+        assert!(!files.contains_key("logs/pyre-portal-2024-06-01.log"));
+        assert!(files.contains_key("environment.json"));
+
+        let settings = String::from_utf8(files["session-settings.json"].clone()).unwrap();
+        for personal in ["Herr Müller", "Frau Schmidt", "4711", "4712"] {
+            assert!(!settings.contains(personal), "{personal} in {settings}");
+        }
+        let settings: serde_json::Value = serde_json::from_str(&settings).unwrap();
+        assert_eq!(settings["last_session"]["room_name"], "Turnhalle");
+        assert_eq!(
+            settings["session_history"][0]["supervisor_ids"][0],
+            "[REDACTED]"
+        );
+
+        let env: serde_json::Value = serde_json::from_slice(&files["environment.json"]).unwrap();
+        assert_eq!(env["appVersion"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn bundle_exports_recent_database_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        fs::create_dir_all(&log_dir).unwrap();
+        let line = |timestamp| entry("hello").at(timestamp).line();
+        fs::write(
+            log_dir.join("pyre-portal-2024-06-01.log"),
+            format!("{}\n", line("2024-06-01T08:00:00Z")),
+        )
+        .unwrap();
+        fs::write(
+            log_dir.join("pyre-portal-2024-06-15.log"),
+            format!("{}\n", line("2024-06-15T08:00:00Z")),
+        )
+        .unwrap();
+
+        let bundle = write_diagnostics_bundle(
+            &tmp.path().join("export"),
+            DiagnosticsSources {
+                log_dir: &log_dir,
+                log_storage: LogStorage::Sqlite,
+                settings_path: &tmp.path().join("session-settings.json"),
+                redactor: Arc::default(),
+                api_config: Ok(sample_config()),
+                log_days: 7,
+            },
+            now(),
+        )
+        .unwrap();
+        let files = read_bundle(&bundle);
+
+        let exported = String::from_utf8(files["logs/pyre-portal-logs.jsonl"].clone()).unwrap();
+        assert_eq!(exported, format!("{}\n", line("2024-06-15T08:00:00Z")));
+        assert!(!files.contains_key("logs/pyre-portal-2024-06-15.log"));
+    }
+
+    #[test]
+    fn bundle_redacts_crash_reports() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        fs::create_dir_all(&log_dir).unwrap();
+        fs::write(
+            log_dir.join("crash-20240615T083000.000Z.json"),
+            r#"{"message": "panicked at 'Unknown tag 04:D6:94:82:97:6A:80'"}"#,
+        )
+        .unwrap();
+        let files = export(tmp.path(), Ok(sample_config()));
+
+        let report =
+            String::from_utf8(files["crash-reports/crash-20240615T083000.000Z.json"].clone())
+                .unwrap();
+        assert!(report.starts_with(r#"{"message": "panicked at 'Unknown tag "#));
+        assert!(!report.contains("04:D6:94:82:97:6A:80"), "{report}");
+    }
+
+    #[test]
+    fn bundle_masks_device_api_key() {
+        let tmp = tempfile::tempdir().unwrap();
+        let files = export(tmp.path(), Ok(sample_config()));
+
+        let config: serde_json::Value = serde_json::from_slice(&files["api-config.json"]).unwrap();
+        assert_eq!(config["api_base_url"], "http://localhost:8080");
+        assert_eq!(config["device_api_key"], "******************1234");
+        for data in files.values() {
+            assert!(!String::from_utf8_lossy(data).contains("secret-device-key"));
+        }
+    }
+
+    #[test]
+    fn bundle_records_config_error() {
+        let tmp = tempfile::tempdir().unwrap();
+        let files = export(tmp.path(), Err("API key not found".to_string()));
+
+        let config: serde_json::Value = serde_json::from_slice(&files["api-config.json"]).unwrap();
+        assert_eq!(config["error"], "API key not found");
+    }
+
+    #[test]
+    fn manifest_lists_checksums_of_all_other_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let files = export(tmp.path(), Ok(sample_config()));
+
+        let manifest: serde_json::Value = serde_json::from_slice(&files["manifest.json"]).unwrap();
+        let listed = manifest["files"].as_array().unwrap();
+        assert_eq!(listed.len(), files.len() - 1);
+        for file in listed {
+            let data = &files[file["name"].as_str().unwrap()];
+            assert_eq!(file["size"], data.len());
+            assert_eq!(file["sha256"], format!("{:x}", Sha256::digest(data)));
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Device key in the app data directory; files sealed with it can't be read on other devices
+pub(crate) const STORAGE_KEY_FILE: &str = "storage.key";
+
+/// Start of every sealed file, followed by the nonce and the ciphertext
+const MAGIC: &[u8] = b"PYRE-AEAD-1\n";
+/// Start of every sealed line, followed by the base64 nonce and ciphertext
+const LINE_MAGIC: &str = "PYRE-AEAD-1:";
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Create the device key on first run, so it exists before any personal data is stored.
+pub fn init_encrypted_storage<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {e}"))?;
+    fs::create_dir_all(&app_data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {e}"))?;
+    load_or_create_key(&app_data_dir).map(|_| ())
+}
+
+/// Whether `data` was written by `seal` (anything else is plaintext from before encryption).
+pub(crate) fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Read the device key stored in `dir`.
+fn load_key(dir: &Path) -> Result<Key, String> {
+    let path = dir.join(STORAGE_KEY_FILE);
+    let existing = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read storage key {}: {e}", path.display()))?;
+    let key = hex::decode(existing.trim())
+        .ok()
+        .and_then(|bytes| <[u8; KEY_LEN]>::try_from(bytes).ok())
+        .ok_or_else(|| format!("Invalid storage key in {}", path.display()))?;
+    Ok(Key::from(key))
+}
+
+/// Read the device key stored in `dir`, creating a random one if there is none yet.
+///
+/// An existing but unreadable key is never replaced: data sealed with it would be lost.
+fn load_or_create_key(dir: &Path) -> Result<Key, String> {
+    let path = dir.join(STORAGE_KEY_FILE);
+    if path.exists() {
+        return load_key(dir);
+    }
+
+    let mut key = [0u8; KEY_LEN];
+    getrandom::fill(&mut key).map_err(|e| format!("Failed to generate storage key: {e}"))?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let created = options.open(&path).and_then(|mut file| {
+        file.write_all(hex::encode(key).as_bytes())?;
+        file.sync_all()
+    });
+    match created {
+        Ok(()) => {
+            tracing::info!("Created storage key");
+            Ok(Key::from(key))
+        }
+        // Created concurrently; use that one
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => load_key(dir),
+        Err(e) => Err(format!("Failed to write storage key: {e}")),
+    }
+}
+
+/// The device key, loaded once for stores that seal many records (e.g. every log line).
+#[derive(Clone)]
+pub(crate) struct DeviceKey(Key);
+
+impl fmt::Debug for DeviceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DeviceKey(..)")
+    }
+}
+
+impl DeviceKey {
+    /// The key in `dir`, created on first use.
+    pub(crate) fn load_or_create(dir: &Path) -> Result<Self, String> {
+        load_or_create_key(dir).map(Self)
+    }
+
+    /// The key in `dir`, `None` if none was created yet.
+    pub(crate) fn load_existing(dir: &Path) -> Result<Option<Self>, String> {
+        if !dir.join(STORAGE_KEY_FILE).exists() {
+            return Ok(None);
+        }
+        load_key(dir).map(|key| Some(Self(key)))
+    }
+
+    /// Encrypt `plaintext`.
+    ///
+    /// `context` names what the data is (e.g. `session-settings`) and must be passed to
+    /// `open` again, so one kind of sealed data can't be substituted for another.
+    pub(crate) fn seal(&self, context: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::fill(&mut nonce).map_err(|e| format!("Failed to generate nonce: {e}"))?;
+        let payload = Payload {
+            msg: plaintext,
+            aad: context.as_bytes(),
+        };
+        let ciphertext = ChaCha20Poly1305::new(&self.0)
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| format!("Failed to encrypt {context}"))?;
+
+        let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt `data` written by `seal`; plaintext from before encryption is returned as is.
+    pub(crate) fn open(&self, context: &str, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let Some(sealed) = data.strip_prefix(MAGIC) else {
+            return Ok(data);
+        };
+        self.decrypt(context, sealed)
+    }
+
+    /// Encrypt one line of a line-based store into a single line of text (without the
+    /// line break), so the store can still be appended to and read line by line.
+    pub(crate) fn seal_line(&self, context: &str, line: &str) -> Result<String, String> {
+        let sealed = self.seal(context, line.as_bytes())?;
+        Ok(format!(
+            "{LINE_MAGIC}{}",
+            BASE64.encode(&sealed[MAGIC.len()..])
+        ))
+    }
+
+    /// A keyed digest of `value`: equal values give equal digests, so sealed records can
+    /// still be looked up by it, but the value can't be recovered without the key.
+    pub(crate) fn fingerprint(&self, context: &str, value: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.0);
+        hasher.update(context.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+        hex::encode(&hasher.finalize()[..16])
+    }
+
+    fn decrypt(&self, context: &str, sealed: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < NONCE_LEN {
+            return Err(format!("Failed to decrypt {context}: data is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: context.as_bytes(),
+        };
+        ChaCha20Poly1305::new(&self.0)
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| format!("Failed to decrypt {context}: damaged or sealed with another key"))
+    }
+}
+
+/// Decrypt a line written by `DeviceKey::seal_line`; lines from before encryption are
+/// returned as is. Sealed lines can't be read without `key`.
+pub(crate) fn open_line<'a>(
+    key: Option<&DeviceKey>,
+    context: &str,
+    line: &'a str,
+) -> Result<Cow<'a, str>, String> {
+    let Some(encoded) = line.strip_prefix(LINE_MAGIC) else {
+        return Ok(Cow::Borrowed(line));
+    };
+    let key = key.ok_or_else(|| format!("Failed to decrypt {context}: no storage key"))?;
+    let sealed = BASE64
+        .decode(encoded.trim_end())
+        .map_err(|_| format!("Failed to decrypt {context}: data is damaged"))?;
+    let plaintext = key.decrypt(context, &sealed)?;
+    String::from_utf8(plaintext)
+        .map(Cow::Owned)
+        .map_err(|_| format!("Failed to decrypt {context}: data is damaged"))
+}
+
+/// Encrypt `plaintext` with the device key in `dir` (created on first use); see
+/// `DeviceKey::seal`.
+pub(crate) fn seal(dir: &Path, context: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    DeviceKey::load_or_create(dir)?.seal(context, plaintext)
+}
+
+/// Decrypt `data` written by `seal`; plaintext from before encryption is returned as is.
+pub(crate) fn open(dir: &Path, context: &str, data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !is_sealed(&data) {
+        return Ok(data);
+    }
+    DeviceKey(load_key(dir)?).open(context, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_data_roundtrips() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sealed = seal(tmp.path(), "session-settings", b"Herr M\xc3\xbcller").unwrap();
+
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(6).any(|w| w == b"Herr M"));
+        assert_eq!(
+            open(tmp.path(), "session-settings", sealed).unwrap(),
+            "Herr Müller".as_bytes()
+        );
+    }
+
+    #[test]
+    fn plaintext_is_passed_through() {
+        let tmp = tempfile::tempdir().unwrap();
+        let plain = br#"{"use_last_session":true}"#.to_vec();
+        assert!(!is_sealed(&plain));
+        assert_eq!(
+            open(tmp.path(), "session-settings", plain.clone()).unwrap(),
+            plain
+        );
+        // Reading plaintext doesn't need a key
+        assert!(!tmp.path().join(STORAGE_KEY_FILE).exists());
+    }
+
+    #[test]
+    fn tampered_or_misplaced_data_is_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sealed = seal(tmp.path(), "session-settings", b"secret").unwrap();
+
+        assert!(open(tmp.path(), "student-cache", sealed.clone()).is_err());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(tmp.path(), "session-settings", tampered).is_err());
+
+        let truncated = sealed[..MAGIC.len() + 4].to_vec();
+        assert!(open(tmp.path(), "session-settings", truncated).is_err());
+
+        // Another device's key can't open it
+        let other = tempfile::tempdir().unwrap();
+        seal(other.path(), "session-settings", b"").unwrap();
+        assert!(open(other.path(), "session-settings", sealed).is_err());
+    }
+
+    #[test]
+    fn sealed_lines_roundtrip_as_single_lines() {
+        let tmp = tempfile::tempdir().unwrap();
+        let key = DeviceKey::load_or_create(tmp.path()).unwrap();
+        let line = r#"{"message":"Herr Müller scanned"}"#;
+
+        let sealed = key.seal_line("log-line", line).unwrap();
+        assert!(sealed.starts_with(LINE_MAGIC));
+        assert!(!sealed.contains('\n') && !sealed.contains("Müller"));
+        assert_eq!(open_line(Some(&key), "log-line", &sealed).unwrap(), line);
+        assert!(open_line(Some(&key), "crash-report", &sealed).is_err());
+        assert!(open_line(None, "log-line", &sealed).is_err());
+
+        // Lines from before encryption pass through, even without a key
+        assert_eq!(open_line(None, "log-line", line).unwrap(), line);
+    }
+
+    #[test]
+    fn fingerprints_depend_on_value_context_and_key() {
+        let tmp = tempfile::tempdir().unwrap();
+        let key = DeviceKey::load_or_create(tmp.path()).unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let other = DeviceKey::load_or_create(other.path()).unwrap();
+
+        let fingerprint = key.fingerprint("log-user", "staff-42");
+        assert_eq!(key.fingerprint("log-user", "staff-42"), fingerprint);
+        assert_ne!(key.fingerprint("log-user", "staff-43"), fingerprint);
+        assert_ne!(key.fingerprint("log-message", "staff-42"), fingerprint);
+        assert_ne!(other.fingerprint("log-user", "staff-42"), fingerprint);
+        assert!(!fingerprint.contains("staff"));
+    }
+
+    #[test]
+    fn key_is_created_once_and_never_replaced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let key_path = tmp.path().join(STORAGE_KEY_FILE);
+
+        let key = load_or_create_key(tmp.path()).unwrap();
+        assert_eq!(load_or_create_key(tmp.path()).unwrap(), key);
+        assert!(DeviceKey::load_existing(tmp.path()).unwrap().is_some());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::write(&key_path, "not a key").unwrap();
+        assert!(load_or_create_key(tmp.path()).is_err());
+        assert_eq!(fs::read_to_string(&key_path).unwrap(), "not a key");
+    }
+}
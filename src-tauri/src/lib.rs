@@ -1,10 +1,29 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+mod admin;
+mod crash_report;
+mod diagnostics;
+mod encrypted_storage;
+mod local_time;
+mod log_audit;
+mod log_compression;
+mod log_reader;
+mod log_shipper;
+mod log_stats;
+mod log_store;
+mod log_stream;
+mod log_throttle;
+mod log_tracing;
+mod log_writer;
 mod logging;
+mod redaction;
 mod session_storage;
+mod storage_guard;
+
+pub use admin::run_admin;
 
 use serde::{Deserialize, Serialize};
 use std::env;
-use tauri::{WebviewUrl, WebviewWindowBuilder};
+use tauri::{AppHandle, RunEvent, Runtime, WebviewUrl, WebviewWindowBuilder};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ApiConfig {
@@ -21,7 +40,10 @@ fn get_api_config() -> Result<ApiConfig, String> {
 
     let device_api_key = env::var("DEVICE_API_KEY")
         .or_else(|_| env::var("VITE_DEVICE_API_KEY"))
-        .map_err(|_| "API key not found. Please set DEVICE_API_KEY or VITE_DEVICE_API_KEY environment variable")?;
+        .map_err(|_| {
+            tracing::error!("Device API key is not configured");
+            "API key not found. Please set DEVICE_API_KEY or VITE_DEVICE_API_KEY environment variable"
+        })?;
 
     Ok(ApiConfig {
         api_base_url,
@@ -38,7 +60,10 @@ fn parse_fullscreen_env() -> bool {
 }
 
 #[tauri::command]
-fn restart_app() {
+#[allow(clippy::needless_pass_by_value)] // Tauri passes the handle by value
+fn restart_app<R: Runtime>(app: AppHandle<R>) {
+    // Don't lose log entries still queued in the background writer
+    logging::shutdown_log_writer(&app);
     // Exit with code 0 - the local Mac/mock app simply exits
     // (the retired Balena deployment relied on a container restart policy here)
     std::process::exit(0);
@@ -51,6 +76,9 @@ fn restart_app() {
 /// Panics if the Tauri application fails to start (e.g., window creation fails).
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Record panics (including the `expect` below) as crash reports in the log directory
+    crash_report::install_panic_hook();
+
     // Load environment variables from .env file
     dotenvy::dotenv().ok();
 
@@ -63,11 +91,78 @@ pub fn run() {
             get_api_config,
             restart_app,
             logging::write_log,
+            logging::write_logs,
+            logging::set_log_level,
+            log_reader::read_logs,
+            log_store::export_log_store,
+            log_stats::get_log_stats,
+            log_stream::subscribe_logs,
+            log_stream::unsubscribe_logs,
+            log_audit::verify_logs,
+            diagnostics::export_diagnostics,
+            crash_report::list_crash_reports,
             session_storage::save_session_settings,
             session_storage::load_session_settings,
-            session_storage::clear_last_session
+            session_storage::clear_last_session,
+            session_storage::list_session_presets,
+            session_storage::create_session_preset,
+            session_storage::rename_session_preset,
+            session_storage::delete_session_preset,
+            session_storage::apply_session_preset,
+            session_storage::suggest_sessions,
+            storage_guard::get_storage_status
         ])
         .setup(move |app| {
+            // The key must exist before the first log line or crash report is written
+            if let Err(e) = encrypted_storage::init_encrypted_storage(app.handle()) {
+                eprintln!("Failed to create storage key: {e}");
+            }
+            if let Err(e) = logging::init_log_encryption(app.handle()) {
+                eprintln!("Failed to enable log encryption: {e}");
+            }
+            if let Err(e) = crash_report::set_crash_report_dir(app.handle()) {
+                eprintln!("Failed to enable crash reports: {e}");
+            }
+            // Without the writer, entries are written synchronously by each command
+            if let Err(e) = logging::start_log_writer(app.handle()) {
+                eprintln!("Failed to start log writer: {e}");
+            }
+            if let Err(e) = log_tracing::init_tracing(app.handle()) {
+                eprintln!("{e}");
+            }
+            tracing::info!(version = env!("CARGO_PKG_VERSION"), "Backend started");
+
+            if let Err(e) = logging::load_log_settings(app.handle()) {
+                tracing::warn!(error = %e, "Failed to load log settings");
+            }
+            if let Err(e) = storage_guard::init_storage_guard(app.handle()) {
+                tracing::warn!(error = %e, "Failed to start storage guard");
+            }
+            if let Err(e) = session_storage::init_session_storage(app.handle()) {
+                tracing::warn!(error = %e, "Failed to prepare session settings");
+            }
+            // Without the salt, personal identifiers are masked instead of hashed
+            if let Err(e) = logging::init_redaction(app.handle()) {
+                tracing::warn!(error = %e, "Failed to initialize log redaction");
+            }
+
+            // Import the daily files into the log database before retention prunes them
+            if let Err(e) = log_store::init_log_store(app.handle()) {
+                tracing::warn!(error = %e, "Failed to open log database");
+            }
+            // Prune old log files before the frontend starts writing new ones
+            match logging::prune_logs(app.handle()) {
+                Ok(0) => {}
+                Ok(deleted) => tracing::info!(deleted, "Pruned old log files"),
+                Err(e) => tracing::warn!(error = %e, "Failed to apply log retention"),
+            }
+            if let Err(e) = log_compression::compress_logs(app.handle()) {
+                tracing::warn!(error = %e, "Failed to compress log files");
+            }
+            if let Err(e) = log_shipper::start_log_shipper(app.handle()) {
+                tracing::warn!(error = %e, "Failed to start log shipping");
+            }
+
             // Create the main window with dynamic fullscreen setting
             let _window = WebviewWindowBuilder::new(app, "main", WebviewUrl::default())
                 .title("pyreportal")
@@ -76,12 +171,19 @@ pub fn run() {
                 .fullscreen(fullscreen)
                 .center()
                 .decorations(!fullscreen) // No decorations in fullscreen, decorations in windowed mode
-                .build()?;
+                .build()
+                .inspect_err(|e| tracing::error!(error = %e, "Failed to create main window"))?;
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                log_shipper::stop_log_shipper(app);
+                logging::shutdown_log_writer(app);
+            }
+        });
 }
 
 #[cfg(test)]
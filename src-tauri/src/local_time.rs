@@ -0,0 +1,92 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use std::env;
+use std::sync::OnceLock;
+
+/// Timezone of the school day when `LOCAL_TIMEZONE` is not set
+const DEFAULT_TIMEZONE: Tz = chrono_tz::Europe::Berlin;
+
+/// Timezone used for daily log files and other date-based logic (`LOCAL_TIMEZONE`, an
+/// IANA name such as `Europe/Berlin`). Read once; invalid names fall back to the default.
+pub(crate) fn local_timezone() -> Tz {
+    static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+    *TIMEZONE.get_or_init(|| match env::var("LOCAL_TIMEZONE") {
+        Ok(name) => name.trim().parse().unwrap_or_else(|_| {
+            eprintln!("Unknown LOCAL_TIMEZONE '{name}', using {DEFAULT_TIMEZONE}");
+            DEFAULT_TIMEZONE
+        }),
+        Err(_) => DEFAULT_TIMEZONE,
+    })
+}
+
+/// Calendar day of `at` in `timezone`.
+fn date_in(timezone: Tz, at: DateTime<Utc>) -> NaiveDate {
+    at.with_timezone(&timezone).date_naive()
+}
+
+/// Local calendar day of `at`.
+pub(crate) fn local_date(at: DateTime<Utc>) -> NaiveDate {
+    date_in(local_timezone(), at)
+}
+
+/// The current local time.
+pub(crate) fn local_now() -> DateTime<Tz> {
+    Utc::now().with_timezone(&local_timezone())
+}
+
+/// The current local calendar day.
+pub(crate) fn local_today() -> NaiveDate {
+    local_date(Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn berlin_date(at: &str) -> NaiveDate {
+        date_in(chrono_tz::Europe::Berlin, at.parse().unwrap())
+    }
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    #[test]
+    fn day_starts_at_local_midnight_in_winter() {
+        assert_eq!(berlin_date("2024-01-15T22:59:59Z"), date(1, 15));
+        assert_eq!(berlin_date("2024-01-15T23:00:00Z"), date(1, 16));
+    }
+
+    #[test]
+    fn day_starts_at_local_midnight_in_summer() {
+        assert_eq!(berlin_date("2024-06-15T21:59:59Z"), date(6, 15));
+        assert_eq!(berlin_date("2024-06-15T22:00:00Z"), date(6, 16));
+    }
+
+    #[test]
+    fn spring_forward_day_is_23_hours_long() {
+        // 2024-03-31 02:00 CET jumps to 03:00 CEST
+        assert_eq!(berlin_date("2024-03-30T22:59:59Z"), date(3, 30));
+        assert_eq!(berlin_date("2024-03-30T23:00:00Z"), date(3, 31));
+        assert_eq!(berlin_date("2024-03-31T01:00:00Z"), date(3, 31));
+        assert_eq!(berlin_date("2024-03-31T21:59:59Z"), date(3, 31));
+        assert_eq!(berlin_date("2024-03-31T22:00:00Z"), date(4, 1));
+    }
+
+    #[test]
+    fn fall_back_day_is_25_hours_long() {
+        // 2024-10-27 03:00 CEST falls back to 02:00 CET
+        assert_eq!(berlin_date("2024-10-26T21:59:59Z"), date(10, 26));
+        assert_eq!(berlin_date("2024-10-26T22:00:00Z"), date(10, 27));
+        assert_eq!(berlin_date("2024-10-27T00:30:00Z"), date(10, 27));
+        assert_eq!(berlin_date("2024-10-27T01:30:00Z"), date(10, 27));
+        assert_eq!(berlin_date("2024-10-27T22:59:59Z"), date(10, 27));
+        assert_eq!(berlin_date("2024-10-27T23:00:00Z"), date(10, 28));
+    }
+
+    #[test]
+    fn default_timezone_is_berlin() {
+        assert_eq!(DEFAULT_TIMEZONE.name(), "Europe/Berlin");
+        assert!(env::var("LOCAL_TIMEZONE").is_ok() || local_timezone() == DEFAULT_TIMEZONE);
+    }
+}
@@ -0,0 +1,489 @@
+use crate::encrypted_storage::DeviceKey;
+use crate::logging::{
+    flush_log_writer, get_log_directory, list_log_files, log_key, open_log_line, LogEntry,
+    LogFileInfo, LogLevel,
+};
+use chrono::{NaiveDate, SecondsFormat, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Runtime};
+
+/// `prevHash` of the first anchor when no earlier chained day exists
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// `source` of the anchor record that starts each day's chain
+pub(crate) const AUDIT_SOURCE: &str = "AuditChain";
+
+const PREV_HASH_KEY: &str = r#","prevHash":""#;
+const HASH_KEY: &str = r#"","hash":""#;
+const HASH_HEX_LEN: usize = 64;
+/// Length of the `,"prevHash":"…","hash":"…"}` suffix appended to a chained line
+const CHAIN_SUFFIX_LEN: usize = PREV_HASH_KEY.len() + HASH_KEY.len() + 2 * HASH_HEX_LEN + 2;
+
+/// Hash of the last chained line written, so appends don't have to re-read the file.
+#[derive(Debug)]
+pub(crate) struct ChainHead {
+    log_dir: PathBuf,
+    date: NaiveDate,
+    hash: String,
+}
+
+/// A line split into the entry as originally rendered and its chain fields.
+struct ChainedLine<'a> {
+    original: String,
+    prev_hash: &'a str,
+    hash: &'a str,
+}
+
+fn hash_line(prev_hash: &str, original: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    hasher.update(original.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Append `prevHash` and `hash` to a rendered JSON line. Returns the chained line and
+/// its hash.
+fn chain_line(prev_hash: &str, line: &str) -> (String, String) {
+    let original = line.trim_end_matches('\n');
+    let hash = hash_line(prev_hash, original);
+    let body = original.strip_suffix('}').unwrap_or(original);
+    let chained = format!("{body}{PREV_HASH_KEY}{prev_hash}{HASH_KEY}{hash}\"}}\n");
+    (chained, hash)
+}
+
+/// Split a line written by `chain_line`; `None` if the line carries no chain fields.
+fn parse_chained_line(line: &str) -> Option<ChainedLine<'_>> {
+    let split = line.len().checked_sub(CHAIN_SUFFIX_LEN)?;
+    let (body, suffix) = (line.get(..split)?, line.get(split..)?);
+    let rest = suffix.strip_prefix(PREV_HASH_KEY)?;
+    let (prev_hash, rest) = rest.split_at_checked(HASH_HEX_LEN)?;
+    let (hash, rest) = rest
+        .strip_prefix(HASH_KEY)?
+        .split_at_checked(HASH_HEX_LEN)?;
+    let is_hex = |s: &str| s.bytes().all(|b| b.is_ascii_hexdigit());
+    if rest != "\"}" || !is_hex(prev_hash) || !is_hex(hash) {
+        return None;
+    }
+    Some(ChainedLine {
+        original: format!("{body}}}"),
+        prev_hash,
+        hash,
+    })
+}
+
+fn is_anchor(original: &str) -> bool {
+    serde_json::from_str::<LogEntry>(original).is_ok_and(|entry| entry.source == AUDIT_SOURCE)
+}
+
+/// The anchor record opening the chain of `date`, linked to the previous day's chain.
+fn anchor_line(date: NaiveDate, prev_hash: &str) -> String {
+    let entry = LogEntry {
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        level: LogLevel::Info,
+        source: AUDIT_SOURCE.to_string(),
+        message: "Daily audit chain anchor".to_string(),
+        data: Some(serde_json::json!({
+            "date": date.format("%Y-%m-%d").to_string(),
+            "previousHash": prev_hash,
+        })),
+        session_id: AUDIT_SOURCE.to_string(),
+        user_id: None,
+        received_at: None,
+        clock_skew_ms: None,
+        invalid_timestamp: None,
+    };
+    serde_json::to_string(&entry).expect("log entries always serialize")
+}
+
+/// Hash of the last line of a day's files, or `None` if that line is not chained (or
+/// can't be decrypted with `key`) or the day has no lines.
+fn last_chain_hash(
+    key: Option<&DeviceKey>,
+    files: &[&LogFileInfo],
+) -> Result<Option<String>, String> {
+    let Some(file) = files.last() else {
+        return Ok(None);
+    };
+    let content = file.read_to_string()?;
+    Ok(content
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .and_then(|line| open_log_line(key, line))
+        .and_then(|line| parse_chained_line(&line).map(|line| line.hash.to_string())))
+}
+
+/// Chain `lines` for appending to the file of `today`, starting with an anchor record if
+/// today's chain has not been started yet. Returns the chained lines and the new head.
+pub(crate) fn chain_lines(
+    cached: Option<ChainHead>,
+    log_dir: &Path,
+    today: NaiveDate,
+    lines: &[String],
+) -> Result<(Vec<String>, ChainHead), String> {
+    let mut chained = Vec::with_capacity(lines.len() + 1);
+
+    let cached = cached
+        .filter(|head| head.log_dir == log_dir && head.date == today)
+        .map(|head| head.hash);
+    let mut prev_hash = if let Some(hash) = cached {
+        hash
+    } else {
+        let key = log_key(log_dir);
+        let files = list_log_files(log_dir)?;
+        let todays: Vec<_> = files.iter().filter(|f| f.date == today).collect();
+        if let Some(hash) = last_chain_hash(key.as_ref(), &todays)? {
+            hash
+        } else {
+            let previous_day = files
+                .iter()
+                .map(|f| f.date)
+                .filter(|date| *date < today)
+                .max();
+            let previous: Vec<_> = files
+                .iter()
+                .filter(|f| Some(f.date) == previous_day)
+                .collect();
+            let previous_hash = last_chain_hash(key.as_ref(), &previous)?
+                .unwrap_or_else(|| GENESIS_HASH.to_string());
+            let (anchor, hash) = chain_line(&previous_hash, &anchor_line(today, &previous_hash));
+            chained.push(anchor);
+            hash
+        }
+    };
+
+    for line in lines {
+        let (line, hash) = chain_line(&prev_hash, line);
+        chained.push(line);
+        prev_hash = hash;
+    }
+
+    Ok((
+        chained,
+        ChainHead {
+            log_dir: log_dir.to_path_buf(),
+            date: today,
+            hash: prev_hash,
+        },
+    ))
+}
+
+/// Verification result for one log file.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileVerification {
+    pub file: String,
+    /// Number of lines carrying chain fields
+    pub chained_lines: u64,
+    /// The first line at which the chain does not hold, if any
+    pub broken_link: Option<BrokenLink>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokenLink {
+    /// 1-based line number within the file
+    pub line: u64,
+    pub reason: String,
+}
+
+/// Chain state while walking through one day's files.
+struct DayChain<'a> {
+    /// Hash of the last chained line so far
+    prev_hash: Option<String>,
+    /// Hash of the last line so far, `None` if it was not chained
+    tail_hash: Option<String>,
+    /// Where the day's anchor must link to, if the previous day is present
+    previous_day: Option<&'a str>,
+}
+
+impl DayChain<'_> {
+    /// Check the next non-empty line. Returns whether it is chained and, if the chain is
+    /// broken at this line, why.
+    fn check(&mut self, line: &str) -> (bool, Option<&'static str>) {
+        let Some(chained) = parse_chained_line(line) else {
+            self.tail_hash = None;
+            let problem = self
+                .prev_hash
+                .is_some()
+                .then_some("Line has no chain hash (inserted or edited)");
+            return (false, problem);
+        };
+
+        let problem = if hash_line(chained.prev_hash, &chained.original) == chained.hash {
+            match self.prev_hash.as_deref() {
+                Some(prev) if prev != chained.prev_hash => Some(
+                    "Previous hash does not match the preceding line (lines removed or reordered)",
+                ),
+                Some(_) => None,
+                None if !is_anchor(&chained.original) => {
+                    Some("Chain does not start with an anchor record (earlier lines missing)")
+                }
+                None => self
+                    .previous_day
+                    .filter(|expected| *expected != chained.prev_hash)
+                    .map(|_| "Anchor does not match the end of the previous day"),
+            }
+        } else {
+            Some("Line content does not match its hash (edited)")
+        };
+        // Resume from this line so later breaks are reported independently
+        self.prev_hash = Some(chained.hash.to_string());
+        self.tail_hash = Some(chained.hash.to_string());
+        (true, problem)
+    }
+}
+
+/// Check the hash chain of every log file in `log_dir`.
+///
+/// A day's files (`.N` ... `.1`, current) form one chain that must open with an anchor
+/// linked to the end of the previous day present. Lines written before chaining was
+/// enabled are accepted until the first chained line of the day. Sealed lines that can't
+/// be decrypted break the chain.
+pub(crate) fn verify_log_dir(log_dir: &Path) -> Result<Vec<FileVerification>, String> {
+    if !log_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut days: BTreeMap<NaiveDate, Vec<LogFileInfo>> = BTreeMap::new();
+    for file in list_log_files(log_dir)? {
+        days.entry(file.date).or_default().push(file);
+    }
+    let key = log_key(log_dir);
+
+    let mut results = Vec::new();
+    let mut previous_day: Option<String> = None;
+
+    for files in days.values() {
+        let mut chain = DayChain {
+            prev_hash: None,
+            tail_hash: None,
+            previous_day: previous_day.as_deref(),
+        };
+
+        for file in files {
+            let content = file.read_to_string()?;
+            let mut result = FileVerification {
+                file: file
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                chained_lines: 0,
+                broken_link: None,
+            };
+
+            for (number, line) in (1u64..).zip(content.lines()) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let Some(line) = open_log_line(key.as_ref(), line) else {
+                    chain.tail_hash = None;
+                    result.broken_link.get_or_insert(BrokenLink {
+                        line: number,
+                        reason: "Line can't be decrypted (edited or sealed with another key)"
+                            .to_string(),
+                    });
+                    continue;
+                };
+                let (chained, problem) = chain.check(&line);
+                if chained {
+                    result.chained_lines += 1;
+                }
+                if let Some(reason) = problem {
+                    result.broken_link.get_or_insert(BrokenLink {
+                        line: number,
+                        reason: reason.to_string(),
+                    });
+                }
+            }
+            results.push(result);
+        }
+
+        // The writer links the next anchor to the genesis hash if the day ended unchained
+        previous_day = Some(chain.tail_hash.unwrap_or_else(|| GENESIS_HASH.to_string()));
+    }
+
+    Ok(results)
+}
+
+/// Verify the audit hash chain of all log files
+#[tauri::command]
+pub async fn verify_logs<R: Runtime>(app: AppHandle<R>) -> Result<Vec<FileVerification>, String> {
+    flush_log_writer(&app);
+    let log_dir = get_log_directory(&app).map_err(|e| e.to_string())?;
+    verify_log_dir(&log_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::entry;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap()
+    }
+
+    fn file_name(day: u32) -> String {
+        format!("pyre-portal-2024-06-{day:02}.log")
+    }
+
+    /// Chain `messages` onto the current file of `day`, like `append_log_lines` does.
+    fn append(dir: &Path, day: u32, messages: &[&str], cached: Option<ChainHead>) -> ChainHead {
+        fs::create_dir_all(dir).unwrap();
+        let lines: Vec<String> = messages
+            .iter()
+            .map(|m| format!("{}\n", entry(*m).line()))
+            .collect();
+        let (chained, head) = chain_lines(cached, dir, date(day), &lines).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(dir.join(file_name(day)))
+            .unwrap();
+        file.write_all(chained.concat().as_bytes()).unwrap();
+        head
+    }
+
+    fn edit_line(path: &Path, number: usize, edit: impl FnOnce(&str) -> Option<String>) {
+        let content = fs::read_to_string(path).unwrap();
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        match edit(&lines[number - 1]) {
+            Some(line) => lines[number - 1] = line,
+            None => {
+                lines.remove(number - 1);
+            }
+        }
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    fn broken(results: &[FileVerification]) -> Vec<(&str, u64, &str)> {
+        results
+            .iter()
+            .filter_map(|r| {
+                let link = r.broken_link.as_ref()?;
+                Some((r.file.as_str(), link.line, link.reason.as_str()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chained_lines_are_still_log_entries() {
+        let (line, hash) = chain_line(GENESIS_HASH, &format!("{}\n", entry("hello").line()));
+        let parsed: LogEntry = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.message, "hello");
+
+        let parsed = parse_chained_line(line.trim_end()).unwrap();
+        assert_eq!(parsed.original, entry("hello").line());
+        assert_eq!(parsed.prev_hash, GENESIS_HASH);
+        assert_eq!(parsed.hash, hash);
+        assert!(parse_chained_line(&entry("hello").line()).is_none());
+    }
+
+    #[test]
+    fn intact_chain_verifies_across_rollover_and_days() {
+        let tmp = tempfile::tempdir().unwrap();
+        let head = append(tmp.path(), 14, &["a", "b"], None);
+        fs::rename(
+            tmp.path().join(file_name(14)),
+            tmp.path().join(format!("{}.1", file_name(14))),
+        )
+        .unwrap();
+        append(tmp.path(), 14, &["c"], Some(head));
+        // No cached head: today's chain and the previous day are read back from disk
+        append(tmp.path(), 15, &["d"], None);
+        let head = append(tmp.path(), 15, &["e"], None);
+        append(tmp.path(), 15, &["f"], Some(head));
+
+        let results = verify_log_dir(tmp.path()).unwrap();
+        assert!(broken(&results).is_empty(), "{:?}", broken(&results));
+        let counts: Vec<_> = results.iter().map(|r| r.chained_lines).collect();
+        assert_eq!(counts, [3, 1, 4]);
+    }
+
+    #[test]
+    fn reports_first_edited_line() {
+        let tmp = tempfile::tempdir().unwrap();
+        append(tmp.path(), 15, &["present", "late", "present"], None);
+        let path = tmp.path().join(file_name(15));
+        edit_line(&path, 3, |line| Some(line.replace("late", "on time")));
+        edit_line(&path, 4, |line| Some(line.replace("present", "absent")));
+
+        let results = verify_log_dir(tmp.path()).unwrap();
+        assert_eq!(
+            broken(&results),
+            [(
+                file_name(15).as_str(),
+                3,
+                "Line content does not match its hash (edited)"
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_removed_lines_and_unchained_inserts() {
+        let tmp = tempfile::tempdir().unwrap();
+        append(tmp.path(), 14, &["a", "b", "c"], None);
+        append(tmp.path(), 15, &["d", "e"], None);
+        edit_line(&tmp.path().join(file_name(14)), 3, |_| None);
+        edit_line(&tmp.path().join(file_name(15)), 2, |_| {
+            Some(entry("inserted").line())
+        });
+
+        let results = verify_log_dir(tmp.path()).unwrap();
+        assert_eq!(
+            broken(&results),
+            [
+                (
+                    file_name(14).as_str(),
+                    3,
+                    "Previous hash does not match the preceding line (lines removed or reordered)"
+                ),
+                (
+                    file_name(15).as_str(),
+                    2,
+                    "Line has no chain hash (inserted or edited)"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_deleted_day_at_next_anchor() {
+        let tmp = tempfile::tempdir().unwrap();
+        append(tmp.path(), 13, &["a"], None);
+        append(tmp.path(), 14, &["b"], None);
+        append(tmp.path(), 15, &["c"], None);
+        fs::remove_file(tmp.path().join(file_name(14))).unwrap();
+
+        let results = verify_log_dir(tmp.path()).unwrap();
+        assert_eq!(
+            broken(&results),
+            [(
+                file_name(15).as_str(),
+                1,
+                "Anchor does not match the end of the previous day"
+            )]
+        );
+    }
+
+    #[test]
+    fn accepts_unchained_lines_written_before_chaining_was_enabled() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(
+            tmp.path().join(file_name(15)),
+            format!("{}\n", entry("legacy").line()),
+        )
+        .unwrap();
+        append(tmp.path(), 15, &["chained"], None);
+
+        let results = verify_log_dir(tmp.path()).unwrap();
+        assert!(broken(&results).is_empty());
+        assert_eq!(results[0].chained_lines, 2);
+    }
+}
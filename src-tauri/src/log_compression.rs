@@ -0,0 +1,184 @@
+use crate::local_time::local_today;
+use crate::logging::{
+    get_log_directory, list_log_files, lock_log_dir, LogFileInfo, COMPRESSED_EXTENSION,
+};
+use chrono::NaiveDate;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use tauri::{AppHandle, Runtime};
+
+/// Suffix of a compressed file while it is being written
+const TEMP_EXTENSION: &str = ".tmp";
+
+/// Only one compression pass runs at a time.
+static COMPRESS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Gzip every plain log file of the days before `today`.
+///
+/// Returns the number of compressed files.
+pub(crate) fn compress_completed_logs(log_dir: &Path, today: NaiveDate) -> Result<usize, String> {
+    let _pass = COMPRESS_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    if !log_dir.exists() {
+        return Ok(0);
+    }
+
+    // Leftovers of a pass that was interrupted
+    let entries =
+        fs::read_dir(log_dir).map_err(|e| format!("Failed to read log directory: {e}"))?;
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name();
+        if name
+            .to_str()
+            .is_some_and(|n| n.ends_with(&format!("{COMPRESSED_EXTENSION}{TEMP_EXTENSION}")))
+        {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+
+    let mut compressed = 0;
+    for file in list_log_files(log_dir)? {
+        if file.compressed || file.date >= today {
+            continue;
+        }
+        if compress_file(&file)? {
+            compressed += 1;
+        }
+    }
+    Ok(compressed)
+}
+
+/// Replace `file` with `file.gz`. Returns `false` if the file was deleted or written to
+/// while it was being compressed.
+fn compress_file(file: &LogFileInfo) -> Result<bool, String> {
+    let target = PathBuf::from(format!("{}{COMPRESSED_EXTENSION}", file.path.display()));
+    let temp = PathBuf::from(format!("{}{TEMP_EXTENSION}", target.display()));
+
+    let copied = match write_compressed(&file.path, &temp) {
+        Ok(copied) => copied,
+        // Pruned by retention in the meantime
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return Err(format!("Failed to compress log file: {e}"));
+        }
+    };
+
+    // Swap the files without a write, rollover or retention run in between
+    let _guard = lock_log_dir();
+    let unchanged = fs::metadata(&file.path).is_ok_and(|meta| meta.len() == copied);
+    if !unchanged {
+        let _ = fs::remove_file(&temp);
+        return Ok(false);
+    }
+    fs::rename(&temp, &target).map_err(|e| format!("Failed to compress log file: {e}"))?;
+    fs::remove_file(&file.path).map_err(|e| format!("Failed to delete log file: {e}"))?;
+    Ok(true)
+}
+
+/// Gzip `source` into `target` and sync it to disk. Returns the number of bytes read.
+fn write_compressed(source: &Path, target: &Path) -> io::Result<u64> {
+    let mut source = File::open(source)?;
+    let mut encoder = GzEncoder::new(File::create(target)?, Compression::default());
+    let copied = io::copy(&mut source, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    Ok(copied)
+}
+
+/// Compress completed log files of `log_dir` on a background thread.
+pub(crate) fn spawn_compression(log_dir: PathBuf) {
+    let spawned = thread::Builder::new()
+        .name("log-compression".to_string())
+        .spawn(
+            move || match compress_completed_logs(&log_dir, local_today()) {
+                Ok(0) => {}
+                Ok(compressed) => tracing::info!(compressed, "Compressed completed log files"),
+                Err(e) => tracing::warn!(error = %e, "Failed to compress log files"),
+            },
+        );
+    if let Err(e) = spawned {
+        tracing::warn!(error = %e, "Failed to start log compression");
+    }
+}
+
+/// Compress the log files of earlier days in the background (called at startup; the log
+/// writer repeats it on each day change).
+pub fn compress_logs<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let log_dir = get_log_directory(app).map_err(|e| e.to_string())?;
+    spawn_compression(log_dir);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_lines(log_dir: &Path, name: &str, lines: &[&str]) {
+        fs::create_dir_all(log_dir).unwrap();
+        fs::write(log_dir.join(name), lines.join("\n") + "\n").unwrap();
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap()
+    }
+
+    #[test]
+    fn compresses_all_files_except_today() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_lines(tmp.path(), "pyre-portal-2024-06-14.log.1", &["a"]);
+        write_lines(tmp.path(), "pyre-portal-2024-06-14.log", &["b"]);
+        write_lines(tmp.path(), "pyre-portal-2024-06-15.log", &["c"]);
+
+        assert_eq!(compress_completed_logs(tmp.path(), date(15)).unwrap(), 2);
+
+        assert!(tmp.path().join("pyre-portal-2024-06-14.log.1.gz").exists());
+        assert!(tmp.path().join("pyre-portal-2024-06-14.log.gz").exists());
+        assert!(!tmp.path().join("pyre-portal-2024-06-14.log").exists());
+        assert!(tmp.path().join("pyre-portal-2024-06-15.log").exists());
+        assert_eq!(compress_completed_logs(tmp.path(), date(15)).unwrap(), 0);
+    }
+
+    #[test]
+    fn compressed_files_are_listed_and_read_transparently() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-14.log",
+            &["first", "second"],
+        );
+        compress_completed_logs(tmp.path(), date(15)).unwrap();
+
+        let files = list_log_files(tmp.path()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].compressed);
+        assert_eq!(files[0].content_size, "first\nsecond\n".len() as u64);
+        assert_eq!(files[0].read_to_string().unwrap(), "first\nsecond\n");
+
+        let mut rest = String::new();
+        std::io::Read::read_to_string(&mut files[0].open_at(6).unwrap(), &mut rest).unwrap();
+        assert_eq!(rest, "second\n");
+    }
+
+    #[test]
+    fn removes_leftovers_of_interrupted_pass() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_lines(tmp.path(), "pyre-portal-2024-06-14.log", &["a"]);
+        write_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-14.log.gz.tmp",
+            &["partial"],
+        );
+
+        compress_completed_logs(tmp.path(), date(15)).unwrap();
+
+        assert!(!tmp
+            .path()
+            .join("pyre-portal-2024-06-14.log.gz.tmp")
+            .exists());
+        assert!(tmp.path().join("pyre-portal-2024-06-14.log.gz").exists());
+    }
+}
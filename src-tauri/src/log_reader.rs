@@ -0,0 +1,657 @@
+use crate::encrypted_storage::DeviceKey;
+use crate::local_time::local_date;
+use crate::log_store::{with_store, LogStorage};
+use crate::logging::{
+    current_log_config, flush_log_writer, get_log_directory, list_log_files, log_key,
+    open_log_line, LogEntry, LogFileInfo, LogLevel,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::Path;
+use tauri::{AppHandle, Runtime};
+
+const DEFAULT_PAGE_SIZE: usize = 200;
+pub(crate) const MAX_PAGE_SIZE: usize = 1000;
+/// Bytes read at a time when reading a log file backwards
+const TAIL_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Filter and pagination options for `read_logs`. All filters are optional and combined with AND.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogQuery {
+    /// Level to match
+    pub level: Option<LogLevel>,
+    /// Exact `source` to match
+    pub source: Option<String>,
+    /// Only entries at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only entries before this time
+    pub to: Option<DateTime<Utc>>,
+    pub session_id: Option<String>,
+    pub user_id: Option<String>,
+    /// `nextCursor` of the previous page; starts at the oldest entry when absent
+    pub cursor: Option<String>,
+    /// Page size (default 200, capped at 1000)
+    pub limit: Option<usize>,
+}
+
+/// One page of log entries, oldest first.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogPage {
+    pub entries: Vec<LogEntry>,
+    /// Position after the last scanned line. Passing it back continues the query; once
+    /// `hasMore` is false it can be polled to pick up entries written in the meantime.
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+/// Position in the log stream: a day and a byte offset into that day's files.
+///
+/// Offsets count across the day's rollover files (`.N` ... `.1`, current) in order.
+/// Since rollover only renames files, the offset stays valid while the day is written.
+/// Offsets refer to decompressed content, so they also survive compression of the day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Cursor {
+    pub date: NaiveDate,
+    pub offset: u64,
+}
+
+impl Cursor {
+    fn parse(cursor: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid log cursor: {cursor}");
+        let (date, offset) = cursor.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid())?,
+            offset: offset.parse().map_err(|_| invalid())?,
+        })
+    }
+
+    fn encode(self) -> String {
+        format!("{}:{}", self.date.format("%Y-%m-%d"), self.offset)
+    }
+}
+
+impl LogQuery {
+    /// Entries per page: `limit`, or the default, capped at the maximum
+    pub(crate) fn page_size(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        if self.level.is_some_and(|level| level != entry.level)
+            || self.source.as_ref().is_some_and(|s| *s != entry.source)
+            || self
+                .session_id
+                .as_ref()
+                .is_some_and(|s| *s != entry.session_id)
+            || self
+                .user_id
+                .as_ref()
+                .is_some_and(|u| entry.user_id.as_ref() != Some(u))
+        {
+            return false;
+        }
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        let Ok(timestamp) = DateTime::parse_from_rfc3339(&entry.timestamp) else {
+            return false;
+        };
+        let timestamp = timestamp.with_timezone(&Utc);
+        self.from.is_none_or(|from| timestamp >= from) && self.to.is_none_or(|to| timestamp < to)
+    }
+
+    /// Whether a day's file can contain matching entries. File days are local days of the
+    /// backend clock, so one day of slack is allowed on both sides.
+    fn may_contain(&self, date: NaiveDate) -> bool {
+        self.from
+            .is_none_or(|from| date >= local_date(from) - Duration::days(1))
+            && self
+                .to
+                .is_none_or(|to| date <= local_date(to) + Duration::days(1))
+    }
+}
+
+/// Read a page of log entries from `log_dir`.
+///
+/// Only complete lines are consumed, so a line `write_log` is still appending is picked
+/// up by the next call. Lines that are not valid log entries are skipped.
+pub(crate) fn read_logs_from_dir(log_dir: &Path, query: &LogQuery) -> Result<LogPage, String> {
+    let limit = query.page_size();
+    let start = query.cursor.as_deref().map(Cursor::parse).transpose()?;
+
+    let mut entries = Vec::new();
+    let mut next_cursor = start;
+
+    if !log_dir.exists() {
+        return Ok(LogPage {
+            entries,
+            next_cursor: next_cursor.map(Cursor::encode),
+            has_more: false,
+        });
+    }
+
+    let mut days: BTreeMap<NaiveDate, Vec<LogFileInfo>> = BTreeMap::new();
+    for file in list_log_files(log_dir)? {
+        days.entry(file.date).or_default().push(file);
+    }
+    let key = log_key(log_dir);
+
+    for (date, files) in days {
+        let skip = match start {
+            Some(cursor) if date < cursor.date => continue,
+            Some(cursor) if date == cursor.date => cursor.offset,
+            _ => 0,
+        };
+        let mut position = Cursor { date, offset: skip };
+
+        if query.may_contain(date) {
+            let full = read_day(&files, key.as_ref(), &mut position, |entry| {
+                if query.matches(&entry) {
+                    entries.push(entry);
+                }
+                entries.len() < limit
+            })?;
+            if !full {
+                return Ok(LogPage {
+                    entries,
+                    next_cursor: Some(position.encode()),
+                    has_more: true,
+                });
+            }
+        } else {
+            position.offset = skip.max(files.iter().map(|f| f.content_size).sum());
+        }
+        next_cursor = Some(position);
+    }
+
+    Ok(LogPage {
+        entries,
+        next_cursor: next_cursor.map(Cursor::encode),
+        has_more: false,
+    })
+}
+
+/// Feed the complete lines of one day after `position` to `visit`, advancing `position`.
+/// Sealed lines are decrypted with `key`; lines it can't decrypt are skipped.
+///
+/// Returns `false` as soon as `visit` asks to stop.
+pub(crate) fn read_day(
+    files: &[LogFileInfo],
+    key: Option<&DeviceKey>,
+    position: &mut Cursor,
+    mut visit: impl FnMut(LogEntry) -> bool,
+) -> Result<bool, String> {
+    let mut file_start = 0;
+
+    for info in files {
+        let file_end = file_start + info.content_size;
+        if position.offset >= file_end {
+            file_start = file_end;
+            continue;
+        }
+
+        // The file may have been rolled over or pruned since it was listed
+        let Ok(mut reader) = info.open_at(position.offset.saturating_sub(file_start)) else {
+            file_start = file_end;
+            continue;
+        };
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .map_err(|e| format!("Failed to read log file: {e}"))?;
+            if read == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            position.offset += read as u64;
+
+            if let Some(entry) = parse_line(key, &line) {
+                if !visit(entry) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        // Only step into the next file once this one has been read to its listed size
+        if position.offset < file_end {
+            return Ok(true);
+        }
+        file_start = file_end;
+    }
+
+    Ok(true)
+}
+
+/// The log entry in a line, decrypted with `key`; `None` for lines that aren't one.
+fn parse_line(key: Option<&DeviceKey>, line: &[u8]) -> Option<LogEntry> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| open_log_line(key, line))
+        .and_then(|line| serde_json::from_str(&line).ok())
+}
+
+/// The newest `count` entries in `log_dir`, oldest first. The cursor polls for later
+/// entries.
+///
+/// Files are read from their end, newest first, so only the lines returned are parsed.
+fn tail_logs_from_dir(log_dir: &Path, count: usize) -> Result<LogPage, String> {
+    let mut days: BTreeMap<NaiveDate, Vec<LogFileInfo>> = BTreeMap::new();
+    if log_dir.exists() {
+        for file in list_log_files(log_dir)? {
+            days.entry(file.date).or_default().push(file);
+        }
+    }
+    let key = log_key(log_dir);
+
+    let mut entries = Vec::new();
+    let mut next_cursor = None;
+    'days: for (&date, files) in days.iter().rev() {
+        let mut file_end: u64 = files.iter().map(|f| f.content_size).sum();
+        for info in files.iter().rev() {
+            let file_start = file_end - info.content_size;
+            file_end = file_start;
+            let complete = match visit_lines_backwards(info, |line| {
+                if entries.len() < count {
+                    entries.extend(parse_line(key.as_ref(), line));
+                }
+                entries.len() < count
+            }) {
+                Ok(complete) => complete,
+                // Rolled over or pruned since it was listed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to read log file: {e}")),
+            };
+            // Like `read_day`, stop before a line that is still being written
+            next_cursor.get_or_insert(Cursor {
+                date,
+                offset: file_start + complete,
+            });
+            if entries.len() >= count {
+                break 'days;
+            }
+        }
+    }
+
+    entries.reverse();
+    Ok(LogPage {
+        entries,
+        next_cursor: next_cursor.map(Cursor::encode),
+        has_more: false,
+    })
+}
+
+/// Feed the complete lines of a log file to `visit`, last line first, until it asks to
+/// stop. Returns the length of the complete lines, i.e. where a partly written last line
+/// starts.
+fn visit_lines_backwards(
+    info: &LogFileInfo,
+    mut visit: impl FnMut(&[u8]) -> bool,
+) -> io::Result<u64> {
+    let mut file = match File::open(&info.path) {
+        Ok(file) if !info.compressed => file,
+        // Gzipped files (also ones compressed since they were listed) are read from the start
+        _ => {
+            let mut reader = info.open_at(0)?;
+            let mut lines = Vec::new();
+            let mut complete = 0;
+            loop {
+                let mut line = Vec::new();
+                let read = reader.read_until(b'\n', &mut line)?;
+                if read == 0 || line.last() != Some(&b'\n') {
+                    break;
+                }
+                complete += read as u64;
+                lines.push(line);
+            }
+            for line in lines.iter().rev() {
+                if !visit(line) {
+                    break;
+                }
+            }
+            return Ok(complete);
+        }
+    };
+
+    // `pending` holds the bytes from `start` to the end of the lines not visited yet
+    let mut start = info.content_size.min(file.metadata()?.len());
+    let mut pending = Vec::new();
+    let mut complete = None;
+    while start > 0 {
+        let block_start = start.saturating_sub(TAIL_BLOCK_SIZE);
+        let mut block = vec![0; usize::try_from(start - block_start).unwrap_or_default()];
+        file.seek(SeekFrom::Start(block_start))?;
+        file.read_exact(&mut block)?;
+        block.append(&mut pending);
+        pending = block;
+        start = block_start;
+
+        if complete.is_none() {
+            let Some(last) = pending.iter().rposition(|&b| b == b'\n') else {
+                continue;
+            };
+            pending.truncate(last + 1);
+            complete = Some(start + pending.len() as u64);
+        }
+        // Every line after a newline in `pending` is complete; the first one may start
+        // in an earlier block
+        let mut end = pending.len();
+        while let Some(newline) = pending[..end - 1].iter().rposition(|&b| b == b'\n') {
+            if !visit(&pending[newline + 1..end]) {
+                return Ok(complete.unwrap_or_default());
+            }
+            end = newline + 1;
+        }
+        pending.truncate(end);
+    }
+    if complete.is_some() {
+        visit(&pending);
+    }
+    Ok(complete.unwrap_or_default())
+}
+
+/// Read a page of log entries from the configured storage of `log_dir`.
+pub(crate) fn query_logs(log_dir: &Path, query: &LogQuery) -> Result<LogPage, String> {
+    query_storage(current_log_config().storage, log_dir, query)
+}
+
+/// Read a page of log entries from `storage` in `log_dir`. Cursors of one storage are
+/// meaningless in the other.
+pub(crate) fn query_storage(
+    storage: LogStorage,
+    log_dir: &Path,
+    query: &LogQuery,
+) -> Result<LogPage, String> {
+    match storage {
+        LogStorage::Sqlite => with_store(log_dir, |store| store.query(query)),
+        LogStorage::Jsonl => read_logs_from_dir(log_dir, query),
+    }
+}
+
+/// The newest `count` entries in the configured storage of `log_dir`, oldest first.
+pub(crate) fn query_tail(log_dir: &Path, count: usize) -> Result<LogPage, String> {
+    match current_log_config().storage {
+        LogStorage::Sqlite => with_store(log_dir, |store| store.tail(count)),
+        LogStorage::Jsonl => tail_logs_from_dir(log_dir, count),
+    }
+}
+
+/// Query persisted log entries for the on-device log viewer
+#[tauri::command]
+pub async fn read_logs<R: Runtime>(app: AppHandle<R>, query: LogQuery) -> Result<LogPage, String> {
+    let log_dir = get_log_directory(&app).map_err(|e| e.to_string())?;
+    flush_log_writer(&app);
+    query_logs(&log_dir, &query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::{append_lines, entry};
+    use crate::logging::LogLevel;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    /// Two days of logs, the first one rolled over once.
+    fn sample_dir() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-14.log.1",
+            &[
+                entry("Started").at("2024-06-14T08:00:00Z").line(),
+                entry("Reader lost")
+                    .at("2024-06-14T09:00:00Z")
+                    .level(LogLevel::Error)
+                    .source("RFID")
+                    .user("staff-1")
+                    .line(),
+            ],
+        );
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-14.log",
+            &[entry("Reader timeout")
+                .at("2024-06-14T10:00:00Z")
+                .level(LogLevel::Warn)
+                .source("RFID")
+                .session("s2")
+                .user("staff-2")
+                .line()],
+        );
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-15.log",
+            &[
+                // Levels are read case-insensitively
+                entry("Started")
+                    .at("2024-06-15T08:00:00Z")
+                    .session("s2")
+                    .line()
+                    .replace(r#""level":"INFO""#, r#""level":"info""#),
+                "not json".to_string(),
+                entry("Request failed")
+                    .at("2024-06-15T09:00:00Z")
+                    .level(LogLevel::Error)
+                    .source("Api")
+                    .session("s3")
+                    .user("staff-1")
+                    .line(),
+            ],
+        );
+        tmp
+    }
+
+    fn timestamps(page: &LogPage) -> Vec<&str> {
+        page.entries.iter().map(|e| e.timestamp.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_all_days_in_order_skipping_malformed_lines() {
+        let tmp = sample_dir();
+        let page = read_logs_from_dir(tmp.path(), &LogQuery::default()).unwrap();
+        assert_eq!(
+            timestamps(&page),
+            [
+                "2024-06-14T08:00:00Z",
+                "2024-06-14T09:00:00Z",
+                "2024-06-14T10:00:00Z",
+                "2024-06-15T08:00:00Z",
+                "2024-06-15T09:00:00Z",
+            ]
+        );
+        assert!(!page.has_more);
+    }
+
+    #[test]
+    fn filters_by_level_regardless_of_stored_case() {
+        let tmp = sample_dir();
+        let query = LogQuery {
+            level: Some(LogLevel::Info),
+            ..LogQuery::default()
+        };
+        let page = read_logs_from_dir(tmp.path(), &query).unwrap();
+        assert_eq!(
+            timestamps(&page),
+            ["2024-06-14T08:00:00Z", "2024-06-15T08:00:00Z"]
+        );
+    }
+
+    #[test]
+    fn filters_by_source_session_and_user() {
+        let tmp = sample_dir();
+        let query = LogQuery {
+            source: Some("RFID".to_string()),
+            user_id: Some("staff-2".to_string()),
+            session_id: Some("s2".to_string()),
+            ..LogQuery::default()
+        };
+        let page = read_logs_from_dir(tmp.path(), &query).unwrap();
+        assert_eq!(timestamps(&page), ["2024-06-14T10:00:00Z"]);
+    }
+
+    #[test]
+    fn filters_by_time_range() {
+        let tmp = sample_dir();
+        let query = LogQuery {
+            from: Some("2024-06-14T09:00:00Z".parse().unwrap()),
+            to: Some("2024-06-15T09:00:00Z".parse().unwrap()),
+            ..LogQuery::default()
+        };
+        let page = read_logs_from_dir(tmp.path(), &query).unwrap();
+        assert_eq!(
+            timestamps(&page),
+            [
+                "2024-06-14T09:00:00Z",
+                "2024-06-14T10:00:00Z",
+                "2024-06-15T08:00:00Z",
+            ]
+        );
+    }
+
+    #[test]
+    fn paginates_with_cursor() {
+        let tmp = sample_dir();
+        let mut query = LogQuery {
+            limit: Some(2),
+            ..LogQuery::default()
+        };
+        let mut seen = Vec::new();
+        loop {
+            let page = read_logs_from_dir(tmp.path(), &query).unwrap();
+            assert!(page.entries.len() <= 2);
+            seen.extend(page.entries.into_iter().map(|e| e.timestamp));
+            query.cursor = page.next_cursor;
+            if !page.has_more {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 5);
+        assert_eq!(seen.first().unwrap(), "2024-06-14T08:00:00Z");
+        assert_eq!(seen.last().unwrap(), "2024-06-15T09:00:00Z");
+    }
+
+    #[test]
+    fn cursor_survives_rollover_of_current_file() {
+        let tmp = sample_dir();
+        let page = read_logs_from_dir(tmp.path(), &LogQuery::default()).unwrap();
+
+        // Roll the current file over and keep writing, as the logger would
+        fs::rename(
+            tmp.path().join("pyre-portal-2024-06-15.log"),
+            tmp.path().join("pyre-portal-2024-06-15.log.1"),
+        )
+        .unwrap();
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-15.log",
+            &[entry("Started")
+                .at("2024-06-15T10:00:00Z")
+                .session("s3")
+                .line()],
+        );
+
+        let query = LogQuery {
+            cursor: page.next_cursor,
+            ..LogQuery::default()
+        };
+        let page = read_logs_from_dir(tmp.path(), &query).unwrap();
+        assert_eq!(timestamps(&page), ["2024-06-15T10:00:00Z"]);
+    }
+
+    #[test]
+    fn tail_reads_newest_entries_and_polls_from_there() {
+        let tmp = sample_dir();
+        // A line still being written is neither shown nor skipped
+        let late = entry("Late").at("2024-06-15T10:00:00Z").line();
+        let (head, rest) = late.split_at(10);
+        let current = tmp.path().join("pyre-portal-2024-06-15.log");
+        OpenOptions::new()
+            .append(true)
+            .open(&current)
+            .unwrap()
+            .write_all(head.as_bytes())
+            .unwrap();
+
+        let page = tail_logs_from_dir(tmp.path(), 3).unwrap();
+        assert_eq!(
+            timestamps(&page),
+            [
+                "2024-06-14T10:00:00Z",
+                "2024-06-15T08:00:00Z",
+                "2024-06-15T09:00:00Z"
+            ]
+        );
+        assert_eq!(
+            timestamps(&tail_logs_from_dir(tmp.path(), 10).unwrap()).len(),
+            5
+        );
+        assert!(tail_logs_from_dir(tmp.path(), 0)
+            .unwrap()
+            .entries
+            .is_empty());
+
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-15.log",
+            &[rest.to_string()],
+        );
+        let query = LogQuery {
+            cursor: page.next_cursor,
+            ..LogQuery::default()
+        };
+        let polled = read_logs_from_dir(tmp.path(), &query).unwrap();
+        assert_eq!(timestamps(&polled), ["2024-06-15T10:00:00Z"]);
+    }
+
+    #[test]
+    fn partial_trailing_line_is_left_for_next_call() {
+        let tmp = tempfile::tempdir().unwrap();
+        let line = entry("Started").at("2024-06-15T08:00:00Z").line();
+        let (head, tail) = line.split_at(10);
+        fs::write(tmp.path().join("pyre-portal-2024-06-15.log"), head).unwrap();
+
+        let page = read_logs_from_dir(tmp.path(), &LogQuery::default()).unwrap();
+        assert!(page.entries.is_empty());
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(tmp.path().join("pyre-portal-2024-06-15.log"))
+            .unwrap();
+        writeln!(file, "{tail}").unwrap();
+
+        let query = LogQuery {
+            cursor: page.next_cursor,
+            ..LogQuery::default()
+        };
+        let page = read_logs_from_dir(tmp.path(), &query).unwrap();
+        assert_eq!(timestamps(&page), ["2024-06-15T08:00:00Z"]);
+    }
+
+    #[test]
+    fn rejects_invalid_cursor() {
+        let tmp = sample_dir();
+        let query = LogQuery {
+            cursor: Some("yesterday".to_string()),
+            ..LogQuery::default()
+        };
+        let err = read_logs_from_dir(tmp.path(), &query).unwrap_err();
+        assert!(err.contains("Invalid log cursor"));
+    }
+
+    #[test]
+    fn missing_directory_returns_empty_page() {
+        let tmp = tempfile::tempdir().unwrap();
+        let page = read_logs_from_dir(&tmp.path().join("logs"), &LogQuery::default()).unwrap();
+        assert!(page.entries.is_empty());
+        assert!(!page.has_more);
+    }
+}
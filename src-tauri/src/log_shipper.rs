@@ -0,0 +1,546 @@
+use crate::encrypted_storage;
+use crate::get_api_config;
+use crate::log_reader::{query_storage, LogQuery};
+use crate::log_store::LogStorage;
+use crate::logging::{current_log_config, get_log_directory, LogEntry};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+use ureq::http::Uri;
+
+const DEFAULT_BATCH_SIZE: usize = 200;
+const DEFAULT_INTERVAL: Duration = Duration::from_mins(1);
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_mins(15);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where and how often persisted log entries are shipped.
+///
+/// Read from the environment: shipping is enabled by `LOG_SHIPPING_URL`, with optional
+/// `LOG_SHIPPING_BATCH_SIZE` and `LOG_SHIPPING_INTERVAL_SECS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShipperConfig {
+    pub endpoint: String,
+    pub batch_size: usize,
+    /// Pause between checks once everything has been shipped
+    pub interval: Duration,
+}
+
+impl ShipperConfig {
+    /// `None` if no collector is configured. Fails if the collector URL is not https
+    /// (plain http is only allowed to loopback addresses).
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(endpoint) = env::var("LOG_SHIPPING_URL")
+            .ok()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+        else {
+            return Ok(None);
+        };
+        check_endpoint(&endpoint)?;
+
+        Ok(Some(Self {
+            endpoint,
+            batch_size: env::var("LOG_SHIPPING_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(DEFAULT_BATCH_SIZE),
+            interval: env::var("LOG_SHIPPING_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|n| *n > 0)
+                .map_or(DEFAULT_INTERVAL, Duration::from_secs),
+        }))
+    }
+}
+
+/// Logs and the device API key must not travel unencrypted; plain http is only accepted
+/// for a collector on the device itself (e.g. a local forwarder).
+fn check_endpoint(endpoint: &str) -> Result<(), String> {
+    let uri: Uri = endpoint
+        .parse()
+        .map_err(|e| format!("Invalid LOG_SHIPPING_URL {endpoint}: {e}"))?;
+    let host = uri.host().unwrap_or_default();
+    let loopback = host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback());
+    match uri.scheme_str() {
+        Some("https") if !host.is_empty() => Ok(()),
+        Some("http") if loopback => Ok(()),
+        _ => Err(format!(
+            "LOG_SHIPPING_URL must be an https URL (http only to localhost): {endpoint}"
+        )),
+    }
+}
+
+/// Shipping progress persisted in `log-shipping-state.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ShipperState {
+    /// Log cursor (see `read_logs`) after the last entry the collector acknowledged
+    acked_cursor: Option<String>,
+    /// Storage the cursor refers to; state from before this was recorded is for files
+    #[serde(default)]
+    storage: LogStorage,
+}
+
+/// What the shipping state is encrypted as, with the device key next to the state file
+const STATE_CONTEXT: &str = "log-shipping-state";
+
+/// Directory of the state file, which holds the device key it is sealed with
+fn key_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new("."))
+}
+
+fn load_state(path: &Path) -> ShipperState {
+    fs::read(path)
+        .ok()
+        .and_then(|data| encrypted_storage::open(key_dir(path), STATE_CONTEXT, data).ok())
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_state(path: &Path, state: &ShipperState) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(state)
+        .map_err(|e| format!("Failed to serialize log shipping state: {e}"))?;
+    let sealed = encrypted_storage::seal(key_dir(path), STATE_CONTEXT, &json)?;
+    fs::write(path, sealed).map_err(|e| format!("Failed to write log shipping state: {e}"))
+}
+
+#[derive(Serialize)]
+struct ShipmentBody<'a> {
+    entries: &'a [LogEntry],
+}
+
+/// What one shipping round achieved.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ShipOutcome {
+    /// A batch was acknowledged and more entries are waiting
+    MorePending,
+    /// Everything persisted so far has been shipped
+    CaughtUp,
+}
+
+/// Ships persisted log entries to the collector, one acknowledged batch at a time.
+pub(crate) struct Shipper {
+    config: ShipperConfig,
+    /// Where the entries to ship are read from
+    storage: LogStorage,
+    log_dir: PathBuf,
+    state_path: PathBuf,
+    api_key: String,
+    agent: ureq::Agent,
+}
+
+impl Shipper {
+    pub fn new(
+        config: ShipperConfig,
+        storage: LogStorage,
+        log_dir: PathBuf,
+        state_path: PathBuf,
+        api_key: String,
+    ) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            config,
+            storage,
+            log_dir,
+            state_path,
+            api_key,
+            agent,
+        }
+    }
+
+    /// POST the next batch after the acknowledged cursor and advance the cursor once the
+    /// collector answers with a 2xx status.
+    ///
+    /// After `LOG_STORAGE` changed, shipping starts over at the oldest entry of the new
+    /// storage, since the old cursor means nothing there.
+    pub fn ship_batch(&self) -> Result<ShipOutcome, String> {
+        let mut state = load_state(&self.state_path);
+        if state.storage != self.storage {
+            tracing::info!(storage = %self.storage, "Log storage changed, shipping from the start");
+            state = ShipperState {
+                acked_cursor: None,
+                storage: self.storage,
+            };
+        }
+        let page = query_storage(
+            self.storage,
+            &self.log_dir,
+            &LogQuery {
+                cursor: state.acked_cursor.clone(),
+                limit: Some(self.config.batch_size),
+                ..LogQuery::default()
+            },
+        )?;
+
+        if !page.entries.is_empty() {
+            let body = serde_json::to_string(&ShipmentBody {
+                entries: &page.entries,
+            })
+            .map_err(|e| format!("Failed to serialize log batch: {e}"))?;
+
+            let response = self
+                .agent
+                .post(&self.config.endpoint)
+                .header("Authorization", &format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .send(body)
+                .map_err(|e| format!("Failed to reach log collector: {e}"))?;
+
+            let status = response.status();
+            if !status.is_success() {
+                return Err(format!(
+                    "Log collector rejected batch: HTTP {}",
+                    status.as_u16()
+                ));
+            }
+        }
+
+        if page.next_cursor != state.acked_cursor {
+            state.acked_cursor = page.next_cursor;
+            save_state(&self.state_path, &state)?;
+        }
+
+        Ok(if page.has_more {
+            ShipOutcome::MorePending
+        } else {
+            ShipOutcome::CaughtUp
+        })
+    }
+}
+
+/// Delay before the next attempt after a failure: doubles from `MIN_BACKOFF` up to
+/// `MAX_BACKOFF`.
+fn next_backoff(current: Option<Duration>) -> Duration {
+    current.map_or(MIN_BACKOFF, |d| (d * 2).min(MAX_BACKOFF))
+}
+
+/// Ship batches until `stopped` fires: straight on while a backlog remains, every
+/// `interval` once caught up and with exponential backoff while the collector is unreachable.
+fn run(shipper: &Shipper, stopped: &Receiver<()>) {
+    let mut backoff = None;
+    loop {
+        let wait = match shipper.ship_batch() {
+            Ok(outcome) => {
+                if backoff.take().is_some() {
+                    tracing::info!("Log shipping recovered");
+                }
+                match outcome {
+                    ShipOutcome::MorePending => Duration::ZERO,
+                    ShipOutcome::CaughtUp => shipper.config.interval,
+                }
+            }
+            Err(e) => {
+                // Only the first failure is logged, not every retry while offline
+                if backoff.is_none() {
+                    tracing::warn!(error = %e, "Log shipping failed, retrying with backoff");
+                }
+                *backoff.insert(next_backoff(backoff))
+            }
+        };
+        if !matches!(stopped.recv_timeout(wait), Err(RecvTimeoutError::Timeout)) {
+            return;
+        }
+    }
+}
+
+/// Background thread running the `Shipper` until stopped.
+pub struct LogShipper {
+    stop: Mutex<Option<Sender<()>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LogShipper {
+    fn spawn(shipper: Shipper) -> Result<Self, String> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("log-shipper".to_string())
+            .spawn(move || run(&shipper, &stopped))
+            .map_err(|e| format!("Failed to start log shipper thread: {e}"))?;
+
+        Ok(Self {
+            stop: Mutex::new(Some(stop)),
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    /// Stop shipping. A batch in flight is finished first; it is shipped again on the next
+    /// start if its acknowledgement was not recorded.
+    pub fn stop(&self) {
+        self.stop
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let thread = self
+            .thread
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Start shipping logs if `LOG_SHIPPING_URL` is configured (called at startup).
+pub fn start_log_shipper<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let Some(config) = ShipperConfig::from_env()? else {
+        return Ok(());
+    };
+    let log_dir = get_log_directory(app).map_err(|e| e.to_string())?;
+    let state_path = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {e}"))?
+        .join("log-shipping-state.json");
+
+    let api_key = get_api_config()?.device_api_key;
+
+    tracing::info!(endpoint = %config.endpoint, "Starting log shipping");
+    // Entries stay spooled on disk if the thread can't be started
+    app.manage(LogShipper::spawn(Shipper::new(
+        config,
+        current_log_config().storage,
+        log_dir,
+        state_path,
+        api_key,
+    ))?);
+    Ok(())
+}
+
+/// Stop the log shipper, if one is running.
+pub fn stop_log_shipper<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(shipper) = app.try_state::<LogShipper>() {
+        shipper.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::entry;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// A request received by the stand-in collector.
+    struct Received {
+        authorization: Option<String>,
+        body: serde_json::Value,
+    }
+
+    /// Stand-in collector answering one request per status in `statuses`, in order.
+    fn spawn_collector(statuses: Vec<u16>) -> (String, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/logs", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut authorization = None;
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        match name.to_ascii_lowercase().as_str() {
+                            "authorization" => authorization = Some(value.trim().to_string()),
+                            "content-length" => content_length = value.trim().parse().unwrap(),
+                            _ => {}
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                received.push(Received {
+                    authorization,
+                    body: serde_json::from_slice(&body).unwrap(),
+                });
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+            received
+        });
+        (url, handle)
+    }
+
+    fn write_entries(log_dir: &Path, timestamps: &[&str]) {
+        fs::create_dir_all(log_dir).unwrap();
+        let mut file = fs::File::create(log_dir.join("pyre-portal-2024-06-15.log")).unwrap();
+        for timestamp in timestamps {
+            writeln!(file, "{}", entry("hello").at(timestamp).line()).unwrap();
+        }
+    }
+
+    fn shipper(dir: &Path, endpoint: String, batch_size: usize) -> Shipper {
+        Shipper::new(
+            ShipperConfig {
+                endpoint,
+                batch_size,
+                interval: DEFAULT_INTERVAL,
+            },
+            LogStorage::Jsonl,
+            dir.join("logs"),
+            dir.join("log-shipping-state.json"),
+            "device-key".to_string(),
+        )
+    }
+
+    fn shipped_timestamps(received: &Received) -> Vec<&str> {
+        received.body["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["timestamp"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ships_batches_with_bearer_auth_and_tracks_acknowledged_cursor() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_entries(
+            &tmp.path().join("logs"),
+            &[
+                "2024-06-15T08:00:00Z",
+                "2024-06-15T09:00:00Z",
+                "2024-06-15T10:00:00Z",
+            ],
+        );
+        let (url, collector) = spawn_collector(vec![200, 200]);
+        let shipper = shipper(tmp.path(), url, 2);
+
+        assert_eq!(shipper.ship_batch().unwrap(), ShipOutcome::MorePending);
+        assert_eq!(shipper.ship_batch().unwrap(), ShipOutcome::CaughtUp);
+        // Nothing new to ship, so the collector is not contacted again
+        assert_eq!(shipper.ship_batch().unwrap(), ShipOutcome::CaughtUp);
+
+        let received = collector.join().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(
+            received[0].authorization.as_deref(),
+            Some("Bearer device-key")
+        );
+        assert_eq!(
+            shipped_timestamps(&received[0]),
+            ["2024-06-15T08:00:00Z", "2024-06-15T09:00:00Z"]
+        );
+        assert_eq!(shipped_timestamps(&received[1]), ["2024-06-15T10:00:00Z"]);
+        let state_path = tmp.path().join("log-shipping-state.json");
+        assert!(load_state(&state_path).acked_cursor.is_some());
+        assert!(encrypted_storage::is_sealed(
+            &fs::read(&state_path).unwrap()
+        ));
+    }
+
+    #[test]
+    fn rejected_batch_is_retried_from_the_same_cursor() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_entries(&tmp.path().join("logs"), &["2024-06-15T08:00:00Z"]);
+        let (url, collector) = spawn_collector(vec![503, 200]);
+        let shipper = shipper(tmp.path(), url, 10);
+
+        let err = shipper.ship_batch().unwrap_err();
+        assert!(err.contains("HTTP 503"));
+        assert!(!tmp.path().join("log-shipping-state.json").exists());
+        assert_eq!(shipper.ship_batch().unwrap(), ShipOutcome::CaughtUp);
+
+        let received = collector.join().unwrap();
+        assert_eq!(
+            shipped_timestamps(&received[0]),
+            shipped_timestamps(&received[1])
+        );
+    }
+
+    #[test]
+    fn unreachable_collector_keeps_entries_spooled() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_entries(&tmp.path().join("logs"), &["2024-06-15T08:00:00Z"]);
+        // Reserve a port, then close it so nothing is listening
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let shipper = shipper(tmp.path(), format!("http://127.0.0.1:{port}/logs"), 10);
+
+        let err = shipper.ship_batch().unwrap_err();
+        assert!(err.contains("Failed to reach log collector"));
+        assert!(!tmp.path().join("log-shipping-state.json").exists());
+    }
+
+    #[test]
+    fn ships_from_the_configured_storage_and_restarts_after_a_switch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let timestamps = ["2024-06-15T08:00:00Z", "2024-06-15T09:00:00Z"];
+        write_entries(&tmp.path().join("logs"), &timestamps);
+        let (url, collector) = spawn_collector(vec![200, 200]);
+
+        let files = shipper(tmp.path(), url.clone(), 10);
+        assert_eq!(files.ship_batch().unwrap(), ShipOutcome::CaughtUp);
+
+        // The database imports the daily files; its ids are unrelated to file cursors
+        let database = Shipper {
+            storage: LogStorage::Sqlite,
+            ..shipper(tmp.path(), url, 10)
+        };
+        assert_eq!(database.ship_batch().unwrap(), ShipOutcome::CaughtUp);
+
+        let received = collector.join().unwrap();
+        assert_eq!(shipped_timestamps(&received[0]), timestamps);
+        assert_eq!(shipped_timestamps(&received[1]), timestamps);
+        let state = load_state(&tmp.path().join("log-shipping-state.json"));
+        assert_eq!(state.storage, LogStorage::Sqlite);
+        assert_eq!(state.acked_cursor.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn collector_must_use_https_unless_on_loopback() {
+        for endpoint in [
+            "https://logs.example.com/ingest",
+            "http://localhost:8080/logs",
+            "http://127.0.0.1/logs",
+            "http://[::1]:9000/logs",
+        ] {
+            assert!(check_endpoint(endpoint).is_ok(), "{endpoint}");
+        }
+        for endpoint in [
+            "http://logs.example.com/ingest",
+            "http://10.0.0.5/logs",
+            "ftp://localhost/logs",
+            "logs.example.com",
+            "not a url",
+        ] {
+            assert!(check_endpoint(endpoint).is_err(), "{endpoint}");
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(next_backoff(None), MIN_BACKOFF);
+        assert_eq!(next_backoff(Some(MIN_BACKOFF)), MIN_BACKOFF * 2);
+        assert_eq!(next_backoff(Some(MAX_BACKOFF)), MAX_BACKOFF);
+    }
+}
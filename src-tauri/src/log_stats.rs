@@ -0,0 +1,488 @@
+use crate::local_time::local_date;
+use crate::log_reader::{read_day, Cursor};
+use crate::log_store::{with_store, LogStorage, LogStore};
+use crate::logging::{
+    current_log_config, flush_log_writer, get_log_directory, list_log_files, log_key, LogEntry,
+    LogFileInfo, LogLevel,
+};
+use chrono::{DateTime, NaiveDate};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use tauri::{AppHandle, Runtime};
+
+/// Number of repeated messages returned by `get_log_stats`
+const TOP_MESSAGES: usize = 10;
+/// Longer messages are counted by their prefix
+const MAX_MESSAGE_CHARS: usize = 200;
+/// Distinct messages kept per day; once twice as many are counted, only the most
+/// frequent ones are kept, so a day of unique messages can't grow the cache unbounded
+const MAX_MESSAGES_PER_DAY: usize = 500;
+
+/// Counts kept between calls, so each call only reads entries written since.
+struct StatsCache {
+    log_dir: PathBuf,
+    storage: LogStorage,
+    days: BTreeMap<NaiveDate, DayStats>,
+    /// Database only: ids of the oldest entry and of the last counted one
+    store_range: Option<(i64, i64)>,
+}
+
+static STATS_CACHE: Mutex<Option<StatsCache>> = Mutex::new(None);
+
+/// Overview of the persisted log entries for the admin screen.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogStats {
+    /// Days with log files, oldest first
+    pub days: Vec<DayCounts>,
+    pub levels: BTreeMap<LogLevel, u64>,
+    pub sources: BTreeMap<String, u64>,
+    /// Most frequent messages, most frequent first
+    pub top_messages: Vec<MessageCount>,
+    /// Timestamp of the most recent ERROR entry
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DayCounts {
+    pub date: NaiveDate,
+    pub total: u64,
+    pub levels: BTreeMap<LogLevel, u64>,
+    pub sources: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageCount {
+    pub message: String,
+    pub count: u64,
+}
+
+/// A log file whose content was counted, up to `content_size` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CountedFile {
+    index: u32,
+    content_size: u64,
+}
+
+/// Counts for one day of log files.
+#[derive(Debug, Default)]
+pub(crate) struct DayStats {
+    /// Files already counted, oldest first
+    counted: Vec<CountedFile>,
+    levels: BTreeMap<LogLevel, u64>,
+    sources: BTreeMap<String, u64>,
+    messages: HashMap<String, u64>,
+    last_error: Option<String>,
+}
+
+impl DayStats {
+    fn record(&mut self, entry: LogEntry) {
+        *self.levels.entry(entry.level).or_default() += 1;
+        *self.sources.entry(entry.source).or_default() += 1;
+        if entry.level == LogLevel::Error {
+            self.last_error = Some(entry.timestamp);
+        }
+        let message = if entry.message.chars().count() > MAX_MESSAGE_CHARS {
+            entry.message.chars().take(MAX_MESSAGE_CHARS).collect()
+        } else {
+            entry.message
+        };
+        *self.messages.entry(message).or_default() += 1;
+        if self.messages.len() > MAX_MESSAGES_PER_DAY * 2 {
+            let mut messages: Vec<(String, u64)> = self.messages.drain().collect();
+            messages.sort_unstable_by_key(|(_, count)| std::cmp::Reverse(*count));
+            messages.truncate(MAX_MESSAGES_PER_DAY);
+            self.messages.extend(messages);
+        }
+    }
+
+    /// Content offset to continue counting `files` at, or `None` if a counted file is
+    /// gone or changed and the day has to be counted again.
+    ///
+    /// Rollover renames every file of the day to the next index, so counted files are
+    /// matched by their index shifted by the number of rollovers since, and by size.
+    fn resume_offset(&self, files: &[LogFileInfo]) -> Option<u64> {
+        let Some(oldest) = self.counted.first() else {
+            return Some(0);
+        };
+        let shift = files.first()?.index.checked_sub(oldest.index)?;
+        if files.len() < self.counted.len() {
+            return None;
+        }
+
+        let last = self.counted.len() - 1;
+        let unchanged = self
+            .counted
+            .iter()
+            .zip(files)
+            .enumerate()
+            .all(|(i, (counted, file))| {
+                file.index == counted.index + shift
+                // Only the newest counted file may have grown since
+                && if i == last {
+                    file.content_size >= counted.content_size
+                } else {
+                    file.content_size == counted.content_size
+                }
+            });
+        unchanged.then(|| self.counted.iter().map(|f| f.content_size).sum())
+    }
+
+    /// Remember which bytes of `files` have been counted once `offset` is reached.
+    fn set_counted(&mut self, files: &[LogFileInfo], offset: u64) {
+        self.counted.clear();
+        let mut file_start = 0;
+        for file in files {
+            if offset <= file_start {
+                break;
+            }
+            self.counted.push(CountedFile {
+                index: file.index,
+                content_size: file.content_size.min(offset - file_start),
+            });
+            file_start += file.content_size;
+        }
+    }
+}
+
+/// Bring `days` up to date with the files in `log_dir`, reading only lines added since
+/// the previous call.
+pub(crate) fn update_stats(
+    log_dir: &Path,
+    days: &mut BTreeMap<NaiveDate, DayStats>,
+) -> Result<(), String> {
+    if !log_dir.exists() {
+        days.clear();
+        return Ok(());
+    }
+
+    let mut listed: BTreeMap<NaiveDate, Vec<LogFileInfo>> = BTreeMap::new();
+    for file in list_log_files(log_dir)? {
+        listed.entry(file.date).or_default().push(file);
+    }
+    days.retain(|date, _| listed.contains_key(date));
+    let key = log_key(log_dir);
+
+    for (date, files) in listed {
+        let stats = days.entry(date).or_default();
+        // Retention deleted some of the day's rollover files; count the rest again
+        let offset = stats.resume_offset(&files).unwrap_or_else(|| {
+            *stats = DayStats::default();
+            0
+        });
+        let mut position = Cursor { date, offset };
+        read_day(&files, key.as_ref(), &mut position, |entry| {
+            stats.record(entry);
+            true
+        })?;
+        stats.set_counted(&files, position.offset);
+    }
+    Ok(())
+}
+
+/// Bring `days` up to date with the entries of `store`, reading only entries stored after
+/// the last one counted (`counted`, updated). Entries are counted under the local day of
+/// their timestamp.
+pub(crate) fn update_stats_from_store(
+    store: &LogStore,
+    days: &mut BTreeMap<NaiveDate, DayStats>,
+    counted: &mut Option<(i64, i64)>,
+) -> Result<(), String> {
+    let first = store.first_id()?;
+    // Retention or low storage deleted entries; count the rest again
+    if counted.is_some_and(|(counted_first, _)| Some(counted_first) != first) {
+        days.clear();
+        *counted = None;
+    }
+    let Some(first) = first else {
+        return Ok(());
+    };
+
+    let mut last = counted.map_or(0, |(_, last)| last);
+    store.for_each_after(last, |id, timestamp_ms, entry| {
+        last = id;
+        if let Some(at) = timestamp_ms.and_then(DateTime::from_timestamp_millis) {
+            days.entry(local_date(at)).or_default().record(entry);
+        }
+    })?;
+    *counted = Some((first, last));
+    Ok(())
+}
+
+/// Combine the per-day counts into the overview returned to the frontend.
+pub(crate) fn summarize(days: &BTreeMap<NaiveDate, DayStats>) -> LogStats {
+    let mut levels: BTreeMap<LogLevel, u64> = BTreeMap::new();
+    let mut sources: BTreeMap<String, u64> = BTreeMap::new();
+    let mut messages: HashMap<&str, u64> = HashMap::new();
+    let mut last_error = None;
+
+    let days = days
+        .iter()
+        .map(|(date, stats)| {
+            for (level, count) in &stats.levels {
+                *levels.entry(*level).or_default() += count;
+            }
+            for (source, count) in &stats.sources {
+                *sources.entry(source.clone()).or_default() += count;
+            }
+            for (message, count) in &stats.messages {
+                *messages.entry(message).or_default() += count;
+            }
+            if stats.last_error.is_some() {
+                last_error.clone_from(&stats.last_error);
+            }
+            DayCounts {
+                date: *date,
+                total: stats.levels.values().sum(),
+                levels: stats.levels.clone(),
+                sources: stats.sources.clone(),
+            }
+        })
+        .collect();
+
+    let mut top_messages: Vec<(&str, u64)> = messages.into_iter().collect();
+    top_messages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    top_messages.truncate(TOP_MESSAGES);
+
+    LogStats {
+        days,
+        levels,
+        sources,
+        top_messages: top_messages
+            .into_iter()
+            .map(|(message, count)| MessageCount {
+                message: message.to_string(),
+                count,
+            })
+            .collect(),
+        last_error,
+    }
+}
+
+/// Aggregate counts per day, level and source for the admin screen
+#[tauri::command]
+pub async fn get_log_stats<R: Runtime>(app: AppHandle<R>) -> Result<LogStats, String> {
+    let log_dir = get_log_directory(&app).map_err(|e| e.to_string())?;
+    let storage = current_log_config().storage;
+    flush_log_writer(&app);
+
+    let mut cache = STATS_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if cache
+        .as_ref()
+        .is_some_and(|cache| cache.log_dir != log_dir || cache.storage != storage)
+    {
+        *cache = None;
+    }
+    let cache = cache.get_or_insert_with(|| StatsCache {
+        log_dir: log_dir.clone(),
+        storage,
+        days: BTreeMap::new(),
+        store_range: None,
+    });
+    match storage {
+        LogStorage::Jsonl => update_stats(&log_dir, &mut cache.days)?,
+        LogStorage::Sqlite => with_store(&log_dir, |store| {
+            update_stats_from_store(store, &mut cache.days, &mut cache.store_range)
+        })?,
+    }
+    Ok(summarize(&cache.days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::{append_lines, entry};
+    use std::fs;
+
+    fn sample_dir() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let reader_lost = |timestamp| entry("Reader lost").at(timestamp).source("RFID");
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-14.log.1",
+            &[
+                entry("Started").at("2024-06-14T08:00:00Z").line(),
+                reader_lost("2024-06-14T09:00:00Z")
+                    .level(LogLevel::Error)
+                    .line(),
+            ],
+        );
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-14.log",
+            &[reader_lost("2024-06-14T10:00:00Z")
+                .level(LogLevel::Error)
+                .line()],
+        );
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-15.log",
+            &[
+                entry("Started").at("2024-06-15T08:00:00Z").line(),
+                entry("Slow response")
+                    .at("2024-06-15T09:00:00Z")
+                    .level(LogLevel::Warn)
+                    .source("Api")
+                    .line(),
+                reader_lost("2024-06-15T10:00:00Z").line(),
+            ],
+        );
+        tmp
+    }
+
+    /// An error that arrives after `sample_dir` was counted
+    fn timeout() -> String {
+        entry("Timeout")
+            .at("2024-06-15T11:00:00Z")
+            .level(LogLevel::Error)
+            .source("Api")
+            .line()
+    }
+
+    fn collect(log_dir: &Path, days: &mut BTreeMap<NaiveDate, DayStats>) -> LogStats {
+        update_stats(log_dir, days).unwrap();
+        summarize(days)
+    }
+
+    #[test]
+    fn counts_per_day_level_and_source() {
+        let tmp = sample_dir();
+        let stats = collect(tmp.path(), &mut BTreeMap::new());
+
+        assert_eq!(stats.days.len(), 2);
+        assert_eq!(stats.days[0].total, 3);
+        assert_eq!(stats.days[0].levels[&LogLevel::Error], 2);
+        assert_eq!(stats.days[1].sources["Api"], 1);
+        assert_eq!(stats.levels[&LogLevel::Info], 3);
+        assert_eq!(stats.sources["RFID"], 3);
+        assert_eq!(stats.last_error.as_deref(), Some("2024-06-14T10:00:00Z"));
+    }
+
+    #[test]
+    fn ranks_repeated_messages() {
+        let tmp = sample_dir();
+        let stats = collect(tmp.path(), &mut BTreeMap::new());
+
+        let top: Vec<(&str, u64)> = stats
+            .top_messages
+            .iter()
+            .map(|m| (m.message.as_str(), m.count))
+            .collect();
+        assert_eq!(
+            top,
+            [("Reader lost", 3), ("Started", 2), ("Slow response", 1)]
+        );
+    }
+
+    #[test]
+    fn later_calls_only_count_new_lines() {
+        let tmp = sample_dir();
+        let mut days = BTreeMap::new();
+        collect(tmp.path(), &mut days);
+
+        append_lines(tmp.path(), "pyre-portal-2024-06-15.log", &[timeout()]);
+        let stats = collect(tmp.path(), &mut days);
+
+        assert_eq!(stats.days[1].total, 4);
+        assert_eq!(stats.levels[&LogLevel::Error], 3);
+        assert_eq!(stats.last_error.as_deref(), Some("2024-06-15T11:00:00Z"));
+    }
+
+    #[test]
+    fn pruned_files_drop_out_of_the_counts() {
+        let tmp = sample_dir();
+        let mut days = BTreeMap::new();
+        collect(tmp.path(), &mut days);
+
+        fs::remove_file(tmp.path().join("pyre-portal-2024-06-14.log.1")).unwrap();
+        let stats = collect(tmp.path(), &mut days);
+        assert_eq!(stats.days[0].total, 1);
+
+        fs::remove_file(tmp.path().join("pyre-portal-2024-06-14.log")).unwrap();
+        let stats = collect(tmp.path(), &mut days);
+        assert_eq!(stats.days.len(), 1);
+        assert!(stats.last_error.is_none());
+    }
+
+    #[test]
+    fn pruned_rollover_file_is_noticed_after_the_day_grows_back() {
+        let tmp = sample_dir();
+        let mut days = BTreeMap::new();
+        collect(tmp.path(), &mut days);
+
+        // The day is larger than before, but its oldest file is gone
+        fs::remove_file(tmp.path().join("pyre-portal-2024-06-14.log.1")).unwrap();
+        let many = vec![entry("Tick").at("2024-06-14T11:00:00Z").line(); 5];
+        append_lines(tmp.path(), "pyre-portal-2024-06-14.log", &many);
+        let stats = collect(tmp.path(), &mut days);
+
+        assert_eq!(stats.days[0].total, 6);
+        assert_eq!(stats.days[0].levels[&LogLevel::Error], 1);
+    }
+
+    #[test]
+    fn rollover_keeps_counted_lines() {
+        let tmp = sample_dir();
+        let mut days = BTreeMap::new();
+        collect(tmp.path(), &mut days);
+
+        // Roll today's file over, as `append_log_lines` does, and start a new one
+        let file = |name: &str| tmp.path().join(name);
+        fs::rename(
+            file("pyre-portal-2024-06-15.log"),
+            file("pyre-portal-2024-06-15.log.1"),
+        )
+        .unwrap();
+        append_lines(
+            tmp.path(),
+            "pyre-portal-2024-06-15.log",
+            &[entry("Tick").at("2024-06-15T11:00:00Z").line()],
+        );
+        let stats = collect(tmp.path(), &mut days);
+
+        assert_eq!(stats.days[1].total, 4);
+    }
+
+    #[test]
+    fn database_entries_are_counted_incrementally() {
+        let tmp = sample_dir();
+        let mut store = LogStore::open(tmp.path()).unwrap();
+        store.import_log_files().unwrap();
+        let mut days = BTreeMap::new();
+        let mut counted = None;
+
+        update_stats_from_store(&store, &mut days, &mut counted).unwrap();
+        let stats = summarize(&days);
+        assert_eq!(stats.levels.values().sum::<u64>(), 6);
+        assert_eq!(stats.levels[&LogLevel::Error], 2);
+
+        store.insert([timeout().as_str()]).unwrap();
+        update_stats_from_store(&store, &mut days, &mut counted).unwrap();
+        let stats = summarize(&days);
+        assert_eq!(stats.levels[&LogLevel::Error], 3);
+        assert_eq!(stats.last_error.as_deref(), Some("2024-06-15T11:00:00Z"));
+
+        // Pruning the oldest entries makes the rest count again
+        store.prune_for_space(1).unwrap();
+        update_stats_from_store(&store, &mut days, &mut counted).unwrap();
+        assert_eq!(summarize(&days).levels.values().sum::<u64>(), 6);
+    }
+
+    #[test]
+    fn distinct_messages_per_day_are_capped() {
+        let mut stats = DayStats::default();
+        for _ in 0..3 {
+            stats.record(entry("Reader lost").build());
+        }
+        for i in 0..MAX_MESSAGES_PER_DAY * 5 {
+            stats.record(entry(format!("Unique {i}")).build());
+        }
+
+        assert!(stats.messages.len() <= MAX_MESSAGES_PER_DAY * 2);
+        assert_eq!(stats.messages["Reader lost"], 3);
+    }
+}
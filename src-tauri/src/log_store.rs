@@ -0,0 +1,1069 @@
+use crate::encrypted_storage::DeviceKey;
+use crate::local_time::local_timezone;
+use crate::log_reader::{LogPage, LogQuery};
+use crate::logging::{
+    current_log_config, flush_log_writer, get_log_directory, list_log_files, log_key,
+    open_log_line, LogEntry, LogFileInfo, LogLine, RetentionPolicy, LOG_LINE_CONTEXT,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rusqlite::types::Value;
+use rusqlite::{
+    params, params_from_iter, Connection, OptionalExtension, Transaction, TransactionBehavior,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs::{self, File};
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError, TryLockError};
+use tauri::{AppHandle, Runtime};
+
+/// Database file inside the log directory
+const DATABASE_NAME: &str = "pyre-portal-logs.sqlite3";
+
+/// Schema changes, applied in order; `PRAGMA user_version` counts those already applied
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE log_entries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp TEXT NOT NULL,
+        -- `timestamp` as Unix milliseconds, NULL if it can't be parsed
+        timestamp_ms INTEGER,
+        level TEXT NOT NULL,
+        source TEXT NOT NULL,
+        message TEXT NOT NULL,
+        session_id TEXT NOT NULL,
+        user_id TEXT,
+        -- The line exactly as it would have been appended to a daily file
+        line TEXT NOT NULL
+    );
+    CREATE INDEX idx_log_entries_timestamp ON log_entries (timestamp_ms);
+    CREATE INDEX idx_log_entries_level ON log_entries (level);
+    CREATE INDEX idx_log_entries_source ON log_entries (source);
+    CREATE INDEX idx_log_entries_session_id ON log_entries (session_id);
+    -- How far each daily file has been imported. Files are identified by their first line,
+    -- which survives rollover renames and compression, unlike the rollover index.
+    CREATE TABLE imported_log_content (
+        date TEXT NOT NULL,
+        -- SHA-256 of the file's first line
+        first_line TEXT NOT NULL,
+        imported_bytes INTEGER NOT NULL,
+        PRIMARY KEY (date, first_line)
+    );
+"];
+
+/// Where persisted log entries go (`LOG_STORAGE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStorage {
+    /// Daily JSONL files (`pyre-portal-YYYY-MM-DD.log`)
+    #[default]
+    Jsonl,
+    /// An `SQLite` database in the log directory, indexed for `read_logs` queries. The daily
+    /// files are imported when it is opened. Not available with the audit chain, which
+    /// only exists in the daily files.
+    Sqlite,
+}
+
+impl LogStorage {
+    /// The storage requested by `LOG_STORAGE`; daily files if unset or invalid.
+    pub(crate) fn from_env() -> Self {
+        env::var("LOG_STORAGE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for LogStorage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "jsonl" => Ok(Self::Jsonl),
+            "sqlite" => Ok(Self::Sqlite),
+            _ => Err(format!(
+                "Unknown log storage '{s}' (expected jsonl or sqlite)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Jsonl => "jsonl",
+            Self::Sqlite => "sqlite",
+        })
+    }
+}
+
+/// What the user ids of encrypted databases are fingerprinted as
+const LOG_USER_CONTEXT: &str = "log-user";
+
+/// The open database of one log directory.
+pub(crate) struct LogStore {
+    log_dir: PathBuf,
+    conn: Connection,
+    /// Key of an encrypted log directory: lines and messages are sealed, user ids are
+    /// stored as fingerprints so they can still be filtered on
+    key: Option<DeviceKey>,
+    /// Day retention was last applied on
+    pruned_on: Option<NaiveDate>,
+}
+
+/// The store used by the write path and commands, opened on first use.
+static STORE: Mutex<Option<LogStore>> = Mutex::new(None);
+
+/// Run `f` on the store of `log_dir`, opening it (and importing the daily files) first if
+/// needed.
+pub(crate) fn with_store<T>(
+    log_dir: &Path,
+    f: impl FnOnce(&mut LogStore) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut store = STORE.lock().unwrap_or_else(PoisonError::into_inner);
+    if store.as_ref().is_none_or(|store| store.log_dir != log_dir) {
+        let mut opened = LogStore::open(log_dir)?;
+        opened.import_log_files()?;
+        *store = Some(opened);
+    }
+    f(store.as_mut().expect("store was just opened"))
+}
+
+/// Like `with_store`, but gives up instead of waiting if the store is in use or not open
+/// yet. For the panic hook, which may run while this thread holds the store.
+pub(crate) fn try_with_store<T>(log_dir: &Path, f: impl FnOnce(&LogStore) -> T) -> Option<T> {
+    let store = match STORE.try_lock() {
+        Ok(store) => store,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => return None,
+    };
+    store
+        .as_ref()
+        .filter(|store| store.log_dir == log_dir)
+        .map(f)
+}
+
+/// Insert rendered log lines as part of `tx`, sealed with `key` if given. Lines that are
+/// not log entries are skipped.
+fn insert_in<'a>(
+    tx: &Transaction<'_>,
+    key: Option<&DeviceKey>,
+    lines: impl IntoIterator<Item = &'a str>,
+) -> Result<usize, String> {
+    let mut insert = tx
+        .prepare_cached(
+            "INSERT INTO log_entries
+                (timestamp, timestamp_ms, level, source, message, session_id, user_id, line)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .map_err(|e| format!("Failed to write to log database: {e}"))?;
+    let mut inserted = 0;
+    for line in lines {
+        let line = line.trim_end();
+        let Ok(mut entry) = serde_json::from_str::<LogEntry>(line) else {
+            continue;
+        };
+        let mut line = line.to_string();
+        if let Some(key) = key {
+            line = key.seal_line(LOG_LINE_CONTEXT, &line)?;
+            entry.message = key.seal_line(LOG_LINE_CONTEXT, &entry.message)?;
+            entry.user_id = entry
+                .user_id
+                .map(|user_id| key.fingerprint(LOG_USER_CONTEXT, &user_id));
+        }
+        insert
+            .execute(params![
+                entry.timestamp,
+                timestamp_ms(&entry.timestamp),
+                entry.level.to_string(),
+                entry.source,
+                entry.message,
+                entry.session_id,
+                entry.user_id,
+                line,
+            ])
+            .map_err(|e| format!("Failed to write to log database: {e}"))?;
+        inserted += 1;
+    }
+    Ok(inserted)
+}
+
+/// Unix milliseconds of an RFC 3339 timestamp.
+fn timestamp_ms(timestamp: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(timestamp.trim())
+        .ok()
+        .map(|t| t.timestamp_millis())
+}
+
+impl LogStore {
+    /// Open (or create) the database in `log_dir` and bring its schema up to date.
+    pub(crate) fn open(log_dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(log_dir).map_err(|e| format!("Failed to create log directory: {e}"))?;
+        let conn = Connection::open(log_dir.join(DATABASE_NAME))
+            .map_err(|e| format!("Failed to open log database: {e}"))?;
+
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(|e| format!("Failed to read log database version: {e}"))?;
+        if version == 0 {
+            // Lets `prune_for_space` hand freed pages back to the file system; this can
+            // only be chosen before the first table is created
+            conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")
+                .map_err(|e| format!("Failed to configure log database: {e}"))?;
+        }
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(|e| format!("Failed to configure log database: {e}"))?;
+        for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(&format!(
+                "BEGIN; {migration} PRAGMA user_version = {}; COMMIT;",
+                applied + 1
+            ))
+            .map_err(|e| format!("Failed to migrate log database: {e}"))?;
+        }
+
+        Ok(Self {
+            log_dir: log_dir.to_path_buf(),
+            conn,
+            key: log_key(log_dir),
+            pruned_on: None,
+        })
+    }
+
+    /// Insert rendered log lines. Lines that are not log entries are skipped.
+    pub(crate) fn insert<'a>(
+        &mut self,
+        lines: impl IntoIterator<Item = &'a str>,
+    ) -> Result<usize, String> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| format!("Failed to write to log database: {e}"))?;
+        let inserted = insert_in(&tx, self.key.as_ref(), lines)?;
+        tx.commit()
+            .map_err(|e| format!("Failed to write to log database: {e}"))?;
+        Ok(inserted)
+    }
+
+    /// Import what was added to the daily files of the log directory since the last import.
+    ///
+    /// Returns the number of imported entries.
+    pub(crate) fn import_log_files(&mut self) -> Result<usize, String> {
+        let mut imported = 0;
+        for file in list_log_files(&self.log_dir)? {
+            imported += self.import_log_file(&file)?;
+        }
+        Ok(imported)
+    }
+
+    /// Import the complete lines of `file` after the part imported before.
+    fn import_log_file(&mut self, file: &LogFileInfo) -> Result<usize, String> {
+        let read_error = |e| format!("Failed to read log file: {e}");
+        let mut first_line = Vec::new();
+        file.open_at(0)
+            .and_then(|mut reader| reader.read_until(b'\n', &mut first_line))
+            .map_err(read_error)?;
+        if first_line.last() != Some(&b'\n') {
+            return Ok(0);
+        }
+        let date = file.date.format("%Y-%m-%d").to_string();
+        let first_line = format!("{:x}", Sha256::digest(&first_line));
+
+        // Entries and progress are committed together, and the write lock is taken up
+        // front, so neither a crash nor another process importing (`pyreportal admin`)
+        // makes lines be imported twice
+        let write_error = |e| format!("Failed to write to log database: {e}");
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(write_error)?;
+        let imported_bytes: u64 = tx
+            .query_row(
+                "SELECT imported_bytes FROM imported_log_content WHERE date = ?1 AND first_line = ?2",
+                params![date, first_line],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to read log database: {e}"))?
+            .unwrap_or(0);
+        if imported_bytes >= file.content_size {
+            return Ok(0);
+        }
+
+        let mut reader = file.open_at(imported_bytes).map_err(read_error)?;
+        let mut lines = Vec::new();
+        let mut read_bytes = imported_bytes;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).map_err(read_error)?;
+            // A line still being written is imported next time
+            if read == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            read_bytes += read as u64;
+            let line = String::from_utf8_lossy(&line);
+            if let Some(line) = open_log_line(self.key.as_ref(), &line) {
+                lines.push(line.into_owned());
+            }
+        }
+
+        let entries = insert_in(&tx, self.key.as_ref(), lines.iter().map(String::as_str))?;
+        tx.execute(
+            "INSERT OR REPLACE INTO imported_log_content (date, first_line, imported_bytes)
+             VALUES (?1, ?2, ?3)",
+            params![date, first_line, read_bytes],
+        )
+        .map_err(write_error)?;
+        tx.commit().map_err(write_error)?;
+        Ok(entries)
+    }
+
+    /// Delete entries from before the retention period. Entries without a parseable
+    /// timestamp are kept.
+    pub(crate) fn apply_retention(
+        &mut self,
+        policy: &RetentionPolicy,
+        today: NaiveDate,
+    ) -> Result<usize, String> {
+        let cutoff = (today - Duration::days(i64::from(policy.retention_days) - 1))
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| midnight.and_local_timezone(local_timezone()).earliest())
+            .map_or(0, |midnight| midnight.timestamp_millis());
+        let deleted = self
+            .conn
+            .execute(
+                "DELETE FROM log_entries WHERE timestamp_ms < ?1",
+                params![cutoff],
+            )
+            .map_err(|e| format!("Failed to prune log database: {e}"))?;
+        self.pruned_on = Some(today);
+        Ok(deleted)
+    }
+
+    /// Store rendered lines, applying the retention days on the first write of each day and
+    /// the size limit on every write.
+    pub(crate) fn append(
+        &mut self,
+        lines: &[LogLine],
+        policy: &RetentionPolicy,
+        today: NaiveDate,
+    ) -> Result<(), String> {
+        if self.pruned_on != Some(today) {
+            self.apply_retention(policy, today)?;
+        }
+        self.insert(lines.iter().map(|line| line.text.as_str()))?;
+        self.enforce_size_limit(policy.max_total_bytes)?;
+        Ok(())
+    }
+
+    /// Read a page of entries matching `query`, oldest first. Cursors are entry ids.
+    pub(crate) fn query(&mut self, query: &LogQuery) -> Result<LogPage, String> {
+        let limit = query.page_size();
+        let after: i64 = match query.cursor.as_deref() {
+            Some(cursor) => cursor
+                .parse()
+                .map_err(|_| format!("Invalid log cursor: {cursor}"))?,
+            None => 0,
+        };
+
+        let mut sql = String::from("SELECT id, line FROM log_entries WHERE id > ?");
+        let mut values = vec![Value::Integer(after)];
+        let mut filter = |column: &str, op: &str, value: Value| {
+            let _ = write!(sql, " AND {column} {op} ?");
+            values.push(value);
+        };
+        if let Some(level) = query.level {
+            filter("level", "=", Value::Text(level.to_string()));
+        }
+        if let Some(source) = &query.source {
+            filter("source", "=", Value::Text(source.clone()));
+        }
+        if let Some(session_id) = &query.session_id {
+            filter("session_id", "=", Value::Text(session_id.clone()));
+        }
+        if let Some(user_id) = &query.user_id {
+            let user_id = match &self.key {
+                Some(key) => key.fingerprint(LOG_USER_CONTEXT, user_id),
+                None => user_id.clone(),
+            };
+            filter("user_id", "=", Value::Text(user_id));
+        }
+        if let Some(from) = query.from {
+            filter(
+                "timestamp_ms",
+                ">=",
+                Value::Integer(from.timestamp_millis()),
+            );
+        }
+        if let Some(to) = query.to {
+            filter("timestamp_ms", "<", Value::Integer(to.timestamp_millis()));
+        }
+        // One more than a page tells whether there are more
+        let _ = write!(sql, " ORDER BY id LIMIT {}", limit + 1);
+
+        // One snapshot for the page and the newest id, so polling misses nothing
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| format!("Failed to read log database: {e}"))?;
+        let mut rows: Vec<(i64, String)> = {
+            let mut statement = tx
+                .prepare(&sql)
+                .map_err(|e| format!("Failed to query log database: {e}"))?;
+            statement
+                .query_map(params_from_iter(values), |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .and_then(Iterator::collect)
+                .map_err(|e| format!("Failed to query log database: {e}"))?
+        };
+        let newest: i64 = tx
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM log_entries", [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Failed to read log database: {e}"))?;
+        tx.commit()
+            .map_err(|e| format!("Failed to read log database: {e}"))?;
+
+        let has_more = rows.len() > limit;
+        rows.truncate(limit);
+        let next_cursor = if has_more {
+            rows.last().map_or(after, |(id, _)| *id)
+        } else {
+            newest.max(after)
+        };
+
+        Ok(LogPage {
+            entries: rows
+                .iter()
+                .filter_map(|(_, line)| self.open_line(line))
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .collect(),
+            next_cursor: Some(next_cursor.to_string()),
+            has_more,
+        })
+    }
+
+    /// Write the entries as JSONL, in the order they were stored. With `since`, entries
+    /// older than that are left out (entries without a parseable timestamp are kept).
+    pub(crate) fn export_jsonl(
+        &self,
+        out: &mut impl Write,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<usize, String> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT line FROM log_entries
+                 WHERE ?1 IS NULL OR timestamp_ms IS NULL OR timestamp_ms >= ?1
+                 ORDER BY id",
+            )
+            .map_err(|e| format!("Failed to query log database: {e}"))?;
+        let mut rows = statement
+            .query(params![since.map(|since| since.timestamp_millis())])
+            .map_err(|e| format!("Failed to query log database: {e}"))?;
+
+        let mut exported = 0;
+        while let Some(row) = rows
+            .next()
+            .map_err(|e| format!("Failed to query log database: {e}"))?
+        {
+            let line: String = row
+                .get(0)
+                .map_err(|e| format!("Failed to query log database: {e}"))?;
+            let Some(line) = self.open_line(&line) else {
+                continue;
+            };
+            writeln!(out, "{line}").map_err(|e| format!("Failed to write log export: {e}"))?;
+            exported += 1;
+        }
+        Ok(exported)
+    }
+
+    /// The last `count` stored lines, oldest first.
+    pub(crate) fn recent_lines(&self, count: usize) -> Result<Vec<String>, String> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT line FROM log_entries ORDER BY id DESC LIMIT ?1")
+            .map_err(|e| format!("Failed to query log database: {e}"))?;
+        let stored: Vec<String> = statement
+            .query_map(params![i64::try_from(count).unwrap_or(i64::MAX)], |row| {
+                row.get(0)
+            })
+            .and_then(Iterator::collect)
+            .map_err(|e| format!("Failed to query log database: {e}"))?;
+        let mut lines: Vec<String> = stored
+            .iter()
+            .filter_map(|line| self.open_line(line))
+            .map(Cow::into_owned)
+            .collect();
+        lines.reverse();
+        Ok(lines)
+    }
+
+    /// The newest `count` entries, oldest first. The cursor polls for later entries.
+    pub(crate) fn tail(&mut self, count: usize) -> Result<LogPage, String> {
+        // One snapshot for the entries and the newest id, like `query`
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| format!("Failed to read log database: {e}"))?;
+        let stored: Vec<String> = {
+            let mut statement = tx
+                .prepare("SELECT line FROM log_entries ORDER BY id DESC LIMIT ?1")
+                .map_err(|e| format!("Failed to query log database: {e}"))?;
+            statement
+                .query_map(params![i64::try_from(count).unwrap_or(i64::MAX)], |row| {
+                    row.get(0)
+                })
+                .and_then(Iterator::collect)
+                .map_err(|e| format!("Failed to query log database: {e}"))?
+        };
+        let newest: i64 = tx
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM log_entries", [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Failed to read log database: {e}"))?;
+        tx.commit()
+            .map_err(|e| format!("Failed to read log database: {e}"))?;
+
+        let mut entries: Vec<LogEntry> = stored
+            .iter()
+            .filter_map(|line| self.open_line(line))
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+        entries.reverse();
+        Ok(LogPage {
+            entries,
+            next_cursor: Some(newest.to_string()),
+            has_more: false,
+        })
+    }
+
+    /// Plaintext of a stored line, `None` if it can't be decrypted.
+    fn open_line<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        open_log_line(self.key.as_ref(), line)
+    }
+
+    /// Id of the oldest stored entry, `None` if the store is empty.
+    pub(crate) fn first_id(&self) -> Result<Option<i64>, String> {
+        self.conn
+            .query_row("SELECT MIN(id) FROM log_entries", [], |row| row.get(0))
+            .map_err(|e| format!("Failed to read log database: {e}"))
+    }
+
+    /// Feed the entries stored after id `after` to `visit`, with their ids and Unix
+    /// millisecond timestamps (`None` if unparseable), in the order they were stored.
+    pub(crate) fn for_each_after(
+        &self,
+        after: i64,
+        mut visit: impl FnMut(i64, Option<i64>, LogEntry),
+    ) -> Result<(), String> {
+        let query_error = |e| format!("Failed to query log database: {e}");
+        let mut statement = self
+            .conn
+            .prepare("SELECT id, timestamp_ms, line FROM log_entries WHERE id > ?1 ORDER BY id")
+            .map_err(query_error)?;
+        let mut rows = statement.query(params![after]).map_err(query_error)?;
+        while let Some(row) = rows.next().map_err(query_error)? {
+            let line: String = row.get(2).map_err(query_error)?;
+            let entry = self
+                .open_line(&line)
+                .and_then(|line| serde_json::from_str(&line).ok());
+            if let Some(entry) = entry {
+                visit(
+                    row.get(0).map_err(query_error)?,
+                    row.get(1).map_err(query_error)?,
+                    entry,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the oldest entries until about `bytes` of log lines are gone, then give the
+    /// freed pages back to the file system where the database allows it.
+    ///
+    /// Returns the number of deleted entries.
+    pub(crate) fn prune_for_space(&mut self, bytes: u64) -> Result<usize, String> {
+        let query_error = |e| format!("Failed to prune log database: {e}");
+        let mut last_id = None;
+        {
+            let mut statement = self
+                .conn
+                .prepare("SELECT id, length(CAST(line AS BLOB)) FROM log_entries ORDER BY id")
+                .map_err(query_error)?;
+            let mut rows = statement.query([]).map_err(query_error)?;
+            let mut freed = 0;
+            while freed < bytes {
+                let Some(row) = rows.next().map_err(query_error)? else {
+                    break;
+                };
+                last_id = Some(row.get::<_, i64>(0).map_err(query_error)?);
+                freed += row.get::<_, u64>(1).map_err(query_error)?;
+            }
+        }
+        let Some(last_id) = last_id else {
+            return Ok(0);
+        };
+
+        let deleted = self
+            .conn
+            .execute("DELETE FROM log_entries WHERE id <= ?1", params![last_id])
+            .map_err(query_error)?;
+        // Frees one page per step. Databases created before auto_vacuum was enabled reuse
+        // the freed pages instead.
+        let mut vacuum = self
+            .conn
+            .prepare("PRAGMA incremental_vacuum")
+            .map_err(query_error)?;
+        let mut pages = vacuum.query([]).map_err(query_error)?;
+        while pages.next().map_err(query_error)?.is_some() {}
+        drop(pages);
+        drop(vacuum);
+        // Move the shrunk pages out of the write-ahead log into the database file
+        self.conn
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+            .map_err(query_error)?;
+        Ok(deleted)
+    }
+
+    /// Bytes of the database holding data; freed pages not handed back yet don't count.
+    fn used_bytes(&self) -> Result<u64, String> {
+        let pragma = |name| {
+            self.conn
+                .pragma_query_value(None, name, |row| row.get::<_, u64>(0))
+                .map_err(|e| format!("Failed to read log database size: {e}"))
+        };
+        Ok((pragma("page_count")? - pragma("freelist_count")?) * pragma("page_size")?)
+    }
+
+    /// Delete the oldest entries until the database fits into `max_bytes` (see
+    /// `prune_for_space`). A tenth more than needed is freed, so a full database isn't
+    /// pruned again on every write.
+    ///
+    /// Returns the number of deleted entries.
+    pub(crate) fn enforce_size_limit(&mut self, max_bytes: u64) -> Result<usize, String> {
+        let mut deleted = 0;
+        loop {
+            let used = self.used_bytes()?;
+            if used <= max_bytes {
+                return Ok(deleted);
+            }
+            // Line lengths leave out the indexes, so this can take more than one round
+            match self.prune_for_space(used - max_bytes + max_bytes / 10)? {
+                0 => return Ok(deleted),
+                pruned => deleted += pruned,
+            }
+        }
+    }
+}
+
+/// Store rendered lines in the database of `log_dir` (see `LogStore::append`).
+pub(crate) fn append_lines(
+    log_dir: &Path,
+    lines: &[LogLine],
+    policy: &RetentionPolicy,
+    today: NaiveDate,
+) -> Result<(), String> {
+    with_store(log_dir, |store| store.append(lines, policy, today))
+}
+
+/// Open the log database and import existing daily files when `LOG_STORAGE=sqlite`
+/// (called at startup).
+///
+/// Fails if the database was requested together with `LOG_AUDIT_CHAIN`: the chain only
+/// exists in the daily files, so logs stay there (see `LogConfig::from_env`).
+pub fn init_log_store<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let config = current_log_config();
+    if config.audit_chain && LogStorage::from_env() == LogStorage::Sqlite {
+        return Err(
+            "LOG_STORAGE=sqlite can't be combined with LOG_AUDIT_CHAIN, keeping logs in the daily files"
+                .to_string(),
+        );
+    }
+    if config.storage != LogStorage::Sqlite {
+        return Ok(());
+    }
+    let log_dir = get_log_directory(app).map_err(|e| e.to_string())?;
+    with_store(&log_dir, |_| Ok(()))
+}
+
+/// Export the log database as a JSONL file into `target_dir`, in the format of the daily
+/// files. Returns the path of the created file.
+#[tauri::command]
+pub async fn export_log_store<R: Runtime>(
+    app: AppHandle<R>,
+    target_dir: String,
+) -> Result<String, String> {
+    let log_dir = get_log_directory(&app).map_err(|e| e.to_string())?;
+    flush_log_writer(&app);
+
+    let target_dir = Path::new(&target_dir);
+    fs::create_dir_all(target_dir)
+        .map_err(|e| format!("Failed to create export directory: {e}"))?;
+    let path = target_dir.join(format!(
+        "pyre-portal-logs-{}.jsonl",
+        Utc::now()
+            .with_timezone(&local_timezone())
+            .format("%Y%m%d-%H%M%S")
+    ));
+    let file = File::create(&path).map_err(|e| format!("Failed to create log export: {e}"))?;
+
+    let mut out = BufWriter::new(file);
+    with_store(&log_dir, |store| store.export_jsonl(&mut out, None))?;
+    out.flush()
+        .map_err(|e| format!("Failed to write log export: {e}"))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::entry;
+    use crate::logging::{enable_log_encryption, LogLevel};
+
+    fn sample_store(dir: &Path) -> LogStore {
+        let mut store = LogStore::open(dir).unwrap();
+        let lines = [
+            entry("hello").at("2024-06-14T08:00:00.000Z").line(),
+            entry("hello")
+                .at("2024-06-14T09:00:00.000Z")
+                .level(LogLevel::Error)
+                .source("RFID")
+                .line(),
+            entry("hello")
+                .at("2024-06-15T08:00:00.000Z")
+                .level(LogLevel::Warn)
+                .source("RFID")
+                .session("s2")
+                .line(),
+            entry("hello")
+                .at("2024-06-15T09:00:00.000Z")
+                .level(LogLevel::Error)
+                .source("Api")
+                .session("s2")
+                .line(),
+        ];
+        store.insert(lines.iter().map(String::as_str)).unwrap();
+        store
+    }
+
+    fn timestamps(page: &LogPage) -> Vec<&str> {
+        page.entries.iter().map(|e| e.timestamp.as_str()).collect()
+    }
+
+    #[test]
+    fn log_storage_parses_case_insensitively() {
+        assert_eq!("SQLite".parse::<LogStorage>(), Ok(LogStorage::Sqlite));
+        assert_eq!(" jsonl ".parse::<LogStorage>(), Ok(LogStorage::Jsonl));
+        assert!("csv".parse::<LogStorage>().is_err());
+    }
+
+    #[test]
+    fn schema_uses_indexes_for_filtered_queries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = LogStore::open(tmp.path()).unwrap();
+        for (column, index) in [
+            ("timestamp_ms", "idx_log_entries_timestamp"),
+            ("level", "idx_log_entries_level"),
+            ("source", "idx_log_entries_source"),
+            ("session_id", "idx_log_entries_session_id"),
+        ] {
+            let plan: String = store
+                .conn
+                .query_row(
+                    &format!("EXPLAIN QUERY PLAN SELECT id FROM log_entries WHERE {column} = 1"),
+                    [],
+                    |row| row.get(3),
+                )
+                .unwrap();
+            assert!(plan.contains(index), "{column}: {plan}");
+        }
+
+        // Reopening doesn't apply the migrations again
+        drop(store);
+        LogStore::open(tmp.path()).unwrap();
+    }
+
+    #[test]
+    fn tail_returns_newest_entries_and_polls_from_there() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut store = sample_store(tmp.path());
+
+        let page = store.tail(2).unwrap();
+        assert_eq!(
+            timestamps(&page),
+            ["2024-06-15T08:00:00.000Z", "2024-06-15T09:00:00.000Z"]
+        );
+        store
+            .insert([entry("hello")
+                .at("2024-06-15T10:00:00.000Z")
+                .line()
+                .as_str()])
+            .unwrap();
+        let polled = store
+            .query(&LogQuery {
+                cursor: page.next_cursor,
+                ..LogQuery::default()
+            })
+            .unwrap();
+        assert_eq!(timestamps(&polled), ["2024-06-15T10:00:00.000Z"]);
+    }
+
+    #[test]
+    fn query_filters_and_pages_by_cursor() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut store = sample_store(tmp.path());
+
+        let query = LogQuery {
+            source: Some("RFID".to_string()),
+            ..LogQuery::default()
+        };
+        let page = store.query(&query).unwrap();
+        assert_eq!(
+            timestamps(&page),
+            ["2024-06-14T09:00:00.000Z", "2024-06-15T08:00:00.000Z"]
+        );
+
+        let query = LogQuery {
+            level: Some(LogLevel::Error),
+            from: Some("2024-06-15T00:00:00Z".parse().unwrap()),
+            ..LogQuery::default()
+        };
+        assert_eq!(
+            timestamps(&store.query(&query).unwrap()),
+            ["2024-06-15T09:00:00.000Z"]
+        );
+
+        let first = store
+            .query(&LogQuery {
+                limit: Some(3),
+                ..LogQuery::default()
+            })
+            .unwrap();
+        assert_eq!(first.entries.len(), 3);
+        assert!(first.has_more);
+        let rest = store
+            .query(&LogQuery {
+                cursor: first.next_cursor,
+                ..LogQuery::default()
+            })
+            .unwrap();
+        assert_eq!(timestamps(&rest), ["2024-06-15T09:00:00.000Z"]);
+        assert!(!rest.has_more);
+
+        // Polling with the last cursor picks up new entries only
+        store
+            .insert([entry("hello")
+                .at("2024-06-15T10:00:00.000Z")
+                .session("s3")
+                .line()
+                .as_str()])
+            .unwrap();
+        let polled = store
+            .query(&LogQuery {
+                cursor: rest.next_cursor,
+                ..LogQuery::default()
+            })
+            .unwrap();
+        assert_eq!(timestamps(&polled), ["2024-06-15T10:00:00.000Z"]);
+    }
+
+    #[test]
+    fn daily_files_are_imported_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(
+            tmp.path().join("pyre-portal-2024-06-14.log"),
+            format!(
+                "{}\nnot json\n{}\n",
+                entry("hello").at("2024-06-14T08:00:00Z").line(),
+                entry("hello")
+                    .at("2024-06-14T09:00:00Z")
+                    .level(LogLevel::Warn)
+                    .source("RFID")
+                    .line()
+            ),
+        )
+        .unwrap();
+
+        let mut store = LogStore::open(tmp.path()).unwrap();
+        assert_eq!(store.import_log_files().unwrap(), 2);
+        assert_eq!(store.import_log_files().unwrap(), 0);
+
+        let mut export = Vec::new();
+        assert_eq!(store.export_jsonl(&mut export, None).unwrap(), 2);
+        let exported = String::from_utf8(export).unwrap();
+        assert!(exported.starts_with(&entry("hello").at("2024-06-14T08:00:00Z").line()));
+        assert_eq!(exported.lines().count(), 2);
+    }
+
+    #[test]
+    fn encrypted_store_seals_lines_and_filters_by_user_fingerprint() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        fs::create_dir_all(&log_dir).unwrap();
+        let key = DeviceKey::load_or_create(tmp.path()).unwrap();
+        enable_log_encryption(&log_dir, key.clone());
+        // A sealed daily file is imported decrypted and sealed again
+        let imported = entry("hello").at("2024-06-14T08:00:00Z").line();
+        fs::write(
+            log_dir.join("pyre-portal-2024-06-14.log"),
+            format!("{}\n", key.seal_line(LOG_LINE_CONTEXT, &imported).unwrap()),
+        )
+        .unwrap();
+
+        let mut store = LogStore::open(&log_dir).unwrap();
+        assert_eq!(store.import_log_files().unwrap(), 1);
+        let scanned = entry("Scanned for Herr Müller")
+            .at("2024-06-14T09:00:00Z")
+            .source("RFID")
+            .user("staff-42")
+            .line();
+        store.insert([scanned.as_str()]).unwrap();
+
+        let stored: Vec<(String, String, Option<String>)> = store
+            .conn
+            .prepare("SELECT line, message, user_id FROM log_entries ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .and_then(Iterator::collect)
+            .unwrap();
+        assert!(stored.iter().all(|(line, message, _)| {
+            !line.contains("App") && !line.contains("Müller") && !message.contains("Müller")
+        }));
+        assert!(stored[1]
+            .2
+            .as_deref()
+            .is_some_and(|user| user != "staff-42"));
+
+        let page = store
+            .query(&LogQuery {
+                user_id: Some("staff-42".to_string()),
+                ..LogQuery::default()
+            })
+            .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].message, "Scanned for Herr Müller");
+        assert_eq!(store.recent_lines(2).unwrap(), [imported, scanned]);
+    }
+
+    #[test]
+    fn import_tops_up_growing_files_and_skips_rolled_over_ones() {
+        let tmp = tempfile::tempdir().unwrap();
+        let current = tmp.path().join("pyre-portal-2024-06-14.log");
+        let first = entry("hello").at("2024-06-14T08:00:00Z").line();
+        let partial = entry("hello")
+            .at("2024-06-14T09:00:00Z")
+            .level(LogLevel::Warn)
+            .source("RFID")
+            .line();
+        fs::write(&current, format!("{first}\n{partial}")).unwrap();
+
+        let mut store = LogStore::open(tmp.path()).unwrap();
+        assert_eq!(store.import_log_files().unwrap(), 1);
+
+        // The line being written is completed and more follow
+        let later = entry("hello")
+            .at("2024-06-14T10:00:00Z")
+            .source("Api")
+            .line();
+        fs::write(&current, format!("{first}\n{partial}\n{later}\n")).unwrap();
+        assert_eq!(store.import_log_files().unwrap(), 2);
+
+        // Rollover renames the file; only the new current file is imported
+        fs::rename(&current, tmp.path().join("pyre-portal-2024-06-14.log.1")).unwrap();
+        let next = entry("hello")
+            .at("2024-06-14T11:00:00Z")
+            .level(LogLevel::Error)
+            .source("Api")
+            .session("s2")
+            .line();
+        fs::write(&current, format!("{next}\n")).unwrap();
+        assert_eq!(store.import_log_files().unwrap(), 1);
+
+        let page = store.query(&LogQuery::default()).unwrap();
+        assert_eq!(
+            timestamps(&page),
+            [
+                "2024-06-14T08:00:00Z",
+                "2024-06-14T09:00:00Z",
+                "2024-06-14T10:00:00Z",
+                "2024-06-14T11:00:00Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn prune_for_space_deletes_oldest_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut store = sample_store(tmp.path());
+        let first_len = entry("hello").at("2024-06-14T08:00:00.000Z").line().len() as u64;
+
+        assert_eq!(store.prune_for_space(first_len + 1).unwrap(), 2);
+        assert_eq!(
+            timestamps(&store.query(&LogQuery::default()).unwrap()),
+            ["2024-06-15T08:00:00.000Z", "2024-06-15T09:00:00.000Z"]
+        );
+        assert_eq!(store.prune_for_space(0).unwrap(), 0);
+        assert_eq!(store.recent_lines(1).unwrap().len(), 1);
+        assert!(store.recent_lines(1).unwrap()[0].contains("2024-06-15T09:00:00.000Z"));
+    }
+
+    #[test]
+    fn append_keeps_database_within_size_limit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut store = LogStore::open(tmp.path()).unwrap();
+        let policy = RetentionPolicy {
+            max_total_bytes: 64 * 1024,
+            ..RetentionPolicy::default()
+        };
+        let today = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        let session = "s".repeat(100);
+
+        for batch in 0..30 {
+            let lines: Vec<LogLine> = (0..100)
+                .map(|i| LogLine {
+                    date: today,
+                    text: entry("hello")
+                        .at(&format!("2024-06-15T10:{batch:02}:{:02}.000Z", i % 60))
+                        .session(&session)
+                        .line(),
+                })
+                .collect();
+            store.append(&lines, &policy, today).unwrap();
+            assert!(store.used_bytes().unwrap() <= policy.max_total_bytes);
+        }
+
+        // The oldest entries made room, the newest are kept
+        assert!(store.first_id().unwrap().unwrap() > 1);
+        assert!(store.recent_lines(1).unwrap()[0].contains("2024-06-15T10:29:39.000Z"));
+    }
+
+    #[test]
+    fn export_can_leave_out_older_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = sample_store(tmp.path());
+        let mut export = Vec::new();
+        let since = "2024-06-15T00:00:00Z".parse().unwrap();
+        assert_eq!(store.export_jsonl(&mut export, Some(since)).unwrap(), 2);
+    }
+
+    #[test]
+    fn retention_deletes_entries_of_expired_days() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut store = sample_store(tmp.path());
+        let policy = RetentionPolicy {
+            retention_days: 1,
+            ..RetentionPolicy::default()
+        };
+
+        let today = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        assert_eq!(store.apply_retention(&policy, today).unwrap(), 2);
+        assert_eq!(store.query(&LogQuery::default()).unwrap().entries.len(), 2);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::logging::{LogEntry, LogLevel};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use tauri::{AppHandle, Emitter, Runtime};
+
+/// Entries queued per subscriber; further entries are dropped until it catches up
+const QUEUE_CAPACITY: usize = 256;
+
+/// Subscriptions beyond this evict the oldest one (e.g. left behind by a webview reload)
+const MAX_SUBSCRIBERS: usize = 8;
+
+/// Prefix of the event each subscription's entries are emitted as
+const EVENT_PREFIX: &str = "log-entry:";
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Which entries a subscriber receives. All filters are optional and combined with AND.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogStreamFilter {
+    /// Minimum level
+    pub level: Option<LogLevel>,
+    /// Exact `source` to match
+    pub source: Option<String>,
+    pub session_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+impl LogStreamFilter {
+    fn matches(&self, entry: &LogEntry) -> bool {
+        self.level.is_none_or(|level| entry.level >= level)
+            && self.source.as_ref().is_none_or(|s| *s == entry.source)
+            && self
+                .session_id
+                .as_ref()
+                .is_none_or(|s| *s == entry.session_id)
+            && self
+                .user_id
+                .as_ref()
+                .is_none_or(|u| entry.user_id.as_ref() == Some(u))
+    }
+}
+
+/// Returned by `subscribe_logs`: entries arrive as `event` until `unsubscribe_logs(id)`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogSubscription {
+    pub id: u64,
+    pub event: String,
+}
+
+/// Payload of a subscription's event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogStreamEvent {
+    pub entry: LogEntry,
+    /// Entries skipped since the previous event because the subscriber fell behind
+    pub dropped: u64,
+}
+
+struct Subscriber {
+    id: u64,
+    filter: LogStreamFilter,
+    sender: SyncSender<LogEntry>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscriber {
+    /// Queue `entry` if it matches, without ever blocking the caller.
+    fn offer(&self, entry: &LogEntry) {
+        if self.filter.matches(entry) && self.sender.try_send(entry.clone()).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Hand a (redacted) entry to all live subscribers. Called from the write path.
+pub(crate) fn publish(entry: &LogEntry) {
+    let subscribers = SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner);
+    for subscriber in subscribers.iter() {
+        subscriber.offer(entry);
+    }
+}
+
+/// Register a subscriber whose entries are emitted by a thread of its own, so a slow
+/// webview only ever fills its own queue.
+fn subscribe<R: Runtime>(app: AppHandle<R>, filter: LogStreamFilter) -> LogSubscription {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let event = format!("{EVENT_PREFIX}{id}");
+    let (sender, receiver) = mpsc::sync_channel::<LogEntry>(QUEUE_CAPACITY);
+    let dropped = Arc::new(AtomicU64::new(0));
+
+    let emitter_dropped = Arc::clone(&dropped);
+    let emitter_event = event.clone();
+    // Runs until the subscriber is removed and its sender dropped
+    let spawned = thread::Builder::new()
+        .name(format!("log-stream-{id}"))
+        .spawn(move || {
+            for entry in receiver {
+                let payload = LogStreamEvent {
+                    entry,
+                    dropped: emitter_dropped.swap(0, Ordering::Relaxed),
+                };
+                // Not reported through `tracing`, which would feed back into the stream
+                if let Err(e) = app.emit(&emitter_event, payload) {
+                    eprintln!("Failed to emit log stream event: {e}");
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        // Without a thread the queue is never drained; entries are counted as dropped
+        eprintln!("Failed to start log stream thread: {e}");
+    }
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner);
+    if subscribers.len() >= MAX_SUBSCRIBERS {
+        subscribers.remove(0);
+    }
+    subscribers.push(Subscriber {
+        id,
+        filter,
+        sender,
+        dropped,
+    });
+    LogSubscription { id, event }
+}
+
+/// Remove a subscriber. Returns `false` if it did not exist (anymore).
+fn unsubscribe(id: u64) -> bool {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner);
+    let before = subscribers.len();
+    subscribers.retain(|subscriber| subscriber.id != id);
+    subscribers.len() != before
+}
+
+/// Stream log entries matching `filter` to the webview as they are written
+#[tauri::command]
+pub async fn subscribe_logs<R: Runtime>(
+    app: AppHandle<R>,
+    filter: Option<LogStreamFilter>,
+) -> Result<LogSubscription, String> {
+    Ok(subscribe(app, filter.unwrap_or_default()))
+}
+
+/// Stop a stream started with `subscribe_logs`
+#[tauri::command]
+pub async fn unsubscribe_logs(id: u64) -> Result<(), String> {
+    if unsubscribe(id) {
+        Ok(())
+    } else {
+        Err(format!("Unknown log subscription: {id}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support;
+    use std::time::Duration;
+    use tauri::Listener;
+
+    fn entry(level: LogLevel, source: &str) -> LogEntry {
+        test_support::entry("streamed")
+            .level(level)
+            .source(source)
+            .build()
+    }
+
+    #[test]
+    fn filter_matches_minimum_level_and_source() {
+        let filter = LogStreamFilter {
+            level: Some(LogLevel::Warn),
+            source: Some("RFID".to_string()),
+            ..LogStreamFilter::default()
+        };
+        assert!(filter.matches(&entry(LogLevel::Error, "RFID")));
+        assert!(filter.matches(&entry(LogLevel::Warn, "RFID")));
+        assert!(!filter.matches(&entry(LogLevel::Info, "RFID")));
+        assert!(!filter.matches(&entry(LogLevel::Error, "Api")));
+    }
+
+    #[test]
+    fn full_queue_drops_entries_instead_of_blocking() {
+        let (sender, receiver) = mpsc::sync_channel(2);
+        let subscriber = Subscriber {
+            id: 0,
+            filter: LogStreamFilter::default(),
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+
+        for _ in 0..5 {
+            subscriber.offer(&entry(LogLevel::Info, "App"));
+        }
+
+        assert_eq!(receiver.try_iter().count(), 2);
+        assert_eq!(subscriber.dropped.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn subscribers_receive_published_entries_as_events() {
+        let app = tauri::test::mock_builder()
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap();
+        let filter = LogStreamFilter {
+            source: Some("StreamTest".to_string()),
+            ..LogStreamFilter::default()
+        };
+        let subscription = subscribe(app.handle().clone(), filter);
+
+        let (tx, rx) = mpsc::channel();
+        app.listen_any(subscription.event.clone(), move |event| {
+            let _ = tx.send(event.payload().to_string());
+        });
+
+        publish(&entry(LogLevel::Info, "Other"));
+        publish(&entry(LogLevel::Info, "StreamTest"));
+
+        let payload: serde_json::Value =
+            serde_json::from_str(&rx.recv_timeout(Duration::from_secs(5)).unwrap()).unwrap();
+        assert_eq!(payload["entry"]["source"], "StreamTest");
+        assert_eq!(payload["dropped"], 0);
+
+        assert!(unsubscribe(subscription.id));
+        assert!(!unsubscribe(subscription.id));
+    }
+}
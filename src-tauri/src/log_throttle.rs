@@ -0,0 +1,337 @@
+use crate::logging::{env_or, LogEntry};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Distinct messages tracked at once; beyond that new messages pass unthrottled
+const MAX_TRACKED_MESSAGES: usize = 1024;
+
+/// How often the same message may be logged before repeats are suppressed.
+///
+/// Read once from the environment (`LOG_RATE_LIMIT_BURST`, `LOG_RATE_LIMIT_WINDOW_SECS`);
+/// missing or invalid values fall back to the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Entries with the same source and message let through per window
+    pub burst: u32,
+    /// Length of a window; suppressed repeats are summarized when it ends
+    pub window: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            burst: 10,
+            window: Duration::seconds(60),
+        }
+    }
+}
+
+impl RateLimit {
+    pub(crate) fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            burst: env_or("LOG_RATE_LIMIT_BURST", defaults.burst),
+            window: Duration::seconds(env_or(
+                "LOG_RATE_LIMIT_WINDOW_SECS",
+                defaults.window.num_seconds(),
+            )),
+        }
+    }
+}
+
+/// Outcome of `LogThrottle::admit`
+#[derive(Debug)]
+pub(crate) struct Admission {
+    /// Whether the entry itself should be logged
+    pub admitted: bool,
+    /// "repeated N times" entries for windows that ended, to be logged before the entry
+    pub summaries: Vec<LogEntry>,
+}
+
+/// Repeats of one (source, message) pair within the current window
+#[derive(Debug)]
+struct Window {
+    seen: u32,
+    suppressed: u64,
+    first_suppressed: Option<DateTime<Utc>>,
+    /// The most recent suppressed entry, which the summary is based on
+    last_suppressed: Option<LogEntry>,
+}
+
+impl Window {
+    /// The summary of this window, if anything was suppressed.
+    fn summary(self, now: DateTime<Utc>) -> Option<LogEntry> {
+        let mut entry = self.last_suppressed?;
+        let first = self.first_suppressed.unwrap_or(now);
+        entry.data = Some(json!({
+            "repeated": self.suppressed,
+            "firstSuppressedAt": first.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "lastSuppressedAt": entry.timestamp,
+            "lastData": entry.data.take().unwrap_or(Value::Null),
+        }));
+        entry.message = format!("{} (repeated {} times)", entry.message, self.suppressed);
+        entry.timestamp = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+        entry.received_at = None;
+        entry.clock_skew_ms = None;
+        Some(entry)
+    }
+}
+
+type MessageKey = (String, String);
+
+/// Open windows, also indexed by start so ended ones are found without a full scan
+#[derive(Debug, Default)]
+struct Windows {
+    by_key: BTreeMap<MessageKey, Window>,
+    /// Start and key of every window in `by_key`, oldest first
+    by_start: BTreeSet<(DateTime<Utc>, MessageKey)>,
+}
+
+impl Windows {
+    /// Close the windows that started at or before `cutoff` (all of them if `None`) and
+    /// return the summaries of those that suppressed anything.
+    fn close_started_by(
+        &mut self,
+        cutoff: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Vec<LogEntry> {
+        let mut summaries = Vec::new();
+        while let Some((started, _)) = self.by_start.first() {
+            if cutoff.is_some_and(|cutoff| *started > cutoff) {
+                break;
+            }
+            let Some((_, key)) = self.by_start.pop_first() else {
+                break;
+            };
+            if let Some(summary) = self.by_key.remove(&key).and_then(|w| w.summary(now)) {
+                summaries.push(summary);
+            }
+        }
+        summaries
+    }
+}
+
+/// Per-(source, message) rate limiting of the log stream.
+///
+/// The first `burst` entries of a pair within a window are logged; further repeats are
+/// dropped and counted. Once the window has ended, a summary of the suppressed repeats
+/// is logged, so a flood that keeps going yields one summary per window. Ended windows
+/// are collected by the log writer's periodic tick (`collect_expired`) or by the next
+/// entry logged, whichever comes first, and `drain` flushes the rest at shutdown.
+#[derive(Debug)]
+pub struct LogThrottle {
+    limit: RateLimit,
+    windows: Mutex<Windows>,
+}
+
+impl Default for LogThrottle {
+    fn default() -> Self {
+        Self::new(RateLimit::default())
+    }
+}
+
+impl LogThrottle {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            windows: Mutex::new(Windows::default()),
+        }
+    }
+
+    fn windows(&self) -> MutexGuard<'_, Windows> {
+        self.windows.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Summaries of the windows that have ended by `now`.
+    pub(crate) fn collect_expired(&self, now: DateTime<Utc>) -> Vec<LogEntry> {
+        self.windows()
+            .close_started_by(Some(now - self.limit.window), now)
+    }
+
+    /// Close every window, ended or not, and return the summaries of pending repeats.
+    pub(crate) fn drain(&self, now: DateTime<Utc>) -> Vec<LogEntry> {
+        self.windows().close_started_by(None, now)
+    }
+
+    /// Count `entry` (already redacted) and decide whether it is logged at `now`.
+    pub(crate) fn admit(&self, entry: &LogEntry, now: DateTime<Utc>) -> Admission {
+        let mut windows = self.windows();
+        let summaries = windows.close_started_by(Some(now - self.limit.window), now);
+
+        let key = (entry.source.clone(), entry.message.clone());
+        if !windows.by_key.contains_key(&key) {
+            if windows.by_key.len() >= MAX_TRACKED_MESSAGES {
+                return Admission {
+                    admitted: true,
+                    summaries,
+                };
+            }
+            windows.by_start.insert((now, key.clone()));
+        }
+        let window = windows.by_key.entry(key).or_insert_with(|| Window {
+            seen: 0,
+            suppressed: 0,
+            first_suppressed: None,
+            last_suppressed: None,
+        });
+
+        window.seen = window.seen.saturating_add(1);
+        let admitted = window.seen <= self.limit.burst;
+        if !admitted {
+            window.suppressed += 1;
+            window.first_suppressed.get_or_insert(now);
+            window.last_suppressed = Some(entry.clone());
+        }
+        Admission {
+            admitted,
+            summaries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{test_support, LogLevel};
+
+    fn entry(source: &str, message: &str) -> LogEntry {
+        test_support::entry(message)
+            .level(LogLevel::Warn)
+            .source(source)
+            .data(json!({ "attempt": 1 }))
+            .build()
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        "2024-06-15T10:30:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::seconds(secs)
+    }
+
+    fn throttle(burst: u32) -> LogThrottle {
+        LogThrottle::new(RateLimit {
+            burst,
+            window: Duration::seconds(60),
+        })
+    }
+
+    #[test]
+    fn repeats_beyond_burst_are_suppressed() {
+        let throttle = throttle(3);
+        let admitted: Vec<bool> = (0..5)
+            .map(|i| {
+                throttle
+                    .admit(&entry("RFID", "Reader stuck"), at(i))
+                    .admitted
+            })
+            .collect();
+        assert_eq!(admitted, [true, true, true, false, false]);
+
+        // Other messages and sources are counted separately
+        assert!(throttle.admit(&entry("RFID", "Reader ok"), at(5)).admitted);
+        assert!(
+            throttle
+                .admit(&entry("Api", "Reader stuck"), at(5))
+                .admitted
+        );
+    }
+
+    #[test]
+    fn ended_window_yields_summary_of_suppressed_repeats() {
+        let throttle = throttle(2);
+        for i in 0..7 {
+            throttle.admit(&entry("RFID", "Reader stuck"), at(i));
+        }
+
+        let admission = throttle.admit(&entry("Api", "Poll failed"), at(61));
+        assert!(admission.admitted);
+        let [summary] = admission.summaries.as_slice() else {
+            panic!("expected one summary, got {:?}", admission.summaries);
+        };
+        assert_eq!(summary.source, "RFID");
+        assert_eq!(summary.level, LogLevel::Warn);
+        assert_eq!(summary.message, "Reader stuck (repeated 5 times)");
+        let data = summary.data.as_ref().unwrap();
+        assert_eq!(data["repeated"], 5);
+        assert_eq!(data["firstSuppressedAt"], "2024-06-15T10:30:02.000Z");
+        assert_eq!(data["lastData"]["attempt"], 1);
+
+        // A new window lets the message through again
+        assert!(
+            throttle
+                .admit(&entry("RFID", "Reader stuck"), at(62))
+                .admitted
+        );
+    }
+
+    #[test]
+    fn ongoing_flood_yields_one_summary_per_window() {
+        let throttle = throttle(1);
+        let mut summaries = Vec::new();
+        for i in 0..180 {
+            let admission = throttle.admit(&entry("RFID", "Reader stuck"), at(i));
+            summaries.extend(admission.summaries);
+        }
+
+        let messages: Vec<&str> = summaries.iter().map(|s| s.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Reader stuck (repeated 59 times)",
+                "Reader stuck (repeated 59 times)"
+            ]
+        );
+    }
+
+    #[test]
+    fn ended_windows_are_collected_without_further_entries() {
+        let throttle = throttle(1);
+        for i in 0..3 {
+            throttle.admit(&entry("RFID", "Reader stuck"), at(i));
+        }
+        for i in 30..33 {
+            throttle.admit(&entry("Api", "Poll failed"), at(i));
+        }
+
+        assert!(throttle.collect_expired(at(59)).is_empty());
+        let summaries = throttle.collect_expired(at(60));
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message, "Reader stuck (repeated 2 times)");
+        assert_eq!(summaries[0].timestamp, "2024-06-15T10:31:00.000Z");
+
+        let summaries = throttle.collect_expired(at(90));
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message, "Poll failed (repeated 2 times)");
+        assert!(throttle.collect_expired(at(600)).is_empty());
+    }
+
+    #[test]
+    fn drain_summarizes_open_windows() {
+        let throttle = throttle(1);
+        for i in 0..4 {
+            throttle.admit(&entry("RFID", "Reader stuck"), at(i));
+        }
+        throttle.admit(&entry("Api", "Poll failed"), at(5));
+
+        let summaries = throttle.drain(at(10));
+        let messages: Vec<&str> = summaries.iter().map(|s| s.message.as_str()).collect();
+        assert_eq!(messages, ["Reader stuck (repeated 3 times)"]);
+
+        // Everything starts over afterwards
+        assert!(throttle.drain(at(11)).is_empty());
+        assert!(
+            throttle
+                .admit(&entry("RFID", "Reader stuck"), at(12))
+                .admitted
+        );
+    }
+
+    #[test]
+    fn windows_without_repeats_end_silently() {
+        let throttle = throttle(2);
+        throttle.admit(&entry("RFID", "Reader stuck"), at(0));
+        let admission = throttle.admit(&entry("RFID", "Reader stuck"), at(120));
+        assert!(admission.admitted);
+        assert!(admission.summaries.is_empty());
+    }
+}
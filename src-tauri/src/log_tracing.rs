@@ -0,0 +1,231 @@
+use crate::logging::{persist_log_entry, LogEntry, LogLevel};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::OnceLock;
+use tauri::{AppHandle, Runtime};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+
+/// `source` of every entry produced by the Rust backend
+pub const RUST_LOG_SOURCE: &str = "rust";
+
+/// Sends `tracing` events into the JSONL log stream as `LogEntry`s.
+pub struct LogEntryLayer {
+    sink: Box<dyn Fn(LogEntry) + Send + Sync>,
+}
+
+impl LogEntryLayer {
+    pub fn new(sink: impl Fn(LogEntry) + Send + Sync + 'static) -> Self {
+        Self {
+            sink: Box::new(sink),
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogEntryLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        (self.sink)(event_to_entry(event));
+    }
+}
+
+/// Collects the fields of an event; `message` becomes the entry message, the rest `data`.
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = Some(match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            });
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}").into());
+    }
+}
+
+fn level_of(event: &Event<'_>) -> LogLevel {
+    match *event.metadata().level() {
+        Level::ERROR => LogLevel::Error,
+        Level::WARN => LogLevel::Warn,
+        Level::INFO => LogLevel::Info,
+        Level::DEBUG | Level::TRACE => LogLevel::Debug,
+    }
+}
+
+/// Identifies this backend process in `sessionId`, like the frontend's per-load session.
+fn process_session_id() -> &'static str {
+    static SESSION_ID: OnceLock<String> = OnceLock::new();
+    SESSION_ID.get_or_init(|| {
+        format!(
+            "rust_{:x}_{:x}",
+            Utc::now().timestamp_millis(),
+            std::process::id()
+        )
+    })
+}
+
+fn event_to_entry(event: &Event<'_>) -> LogEntry {
+    let mut visitor = FieldVisitor::default();
+    event.record(&mut visitor);
+
+    let mut data = visitor.fields;
+    data.insert("target".to_string(), event.metadata().target().into());
+
+    LogEntry {
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        level: level_of(event),
+        source: RUST_LOG_SOURCE.to_string(),
+        message: visitor.message.unwrap_or_default(),
+        data: Some(Value::Object(data)),
+        session_id: process_session_id().to_string(),
+        user_id: None,
+        received_at: None,
+        clock_skew_ms: None,
+        invalid_timestamp: None,
+    }
+}
+
+/// Install a global `tracing` subscriber that persists this crate's events (and warnings
+/// from dependencies) next to the frontend entries.
+pub fn init_tracing<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let app = app.clone();
+    let layer = LogEntryLayer::new(move |entry| persist_log_entry(&app, entry));
+    let filter = Targets::new()
+        .with_target("pyreportal_lib", Level::DEBUG)
+        .with_target("pyreportal", Level::DEBUG)
+        .with_default(Level::WARN);
+
+    tracing::subscriber::set_global_default(
+        tracing_subscriber::registry().with(layer.with_filter(filter)),
+    )
+    .map_err(|e| format!("Failed to install tracing subscriber: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Run `f` with a subscriber that collects the produced entries.
+    fn capture(f: impl FnOnce()) -> Vec<LogEntry> {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&entries);
+        let subscriber = tracing_subscriber::registry().with(LogEntryLayer::new(move |entry| {
+            sink.lock().unwrap().push(entry);
+        }));
+        tracing::subscriber::with_default(subscriber, f);
+        let entries = entries.lock().unwrap();
+        entries
+            .iter()
+            .map(|e| serde_json::from_value(serde_json::to_value(e).unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn event_becomes_rust_log_entry() {
+        let entries = capture(|| {
+            tracing::warn!(
+                retries = 3,
+                path = "/tmp/x",
+                "Failed to write session settings"
+            );
+        });
+
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.source, RUST_LOG_SOURCE);
+        assert_eq!(entry.level, LogLevel::Warn);
+        assert_eq!(entry.message, "Failed to write session settings");
+        assert!(entry.session_id.starts_with("rust_"));
+        assert!(entry.user_id.is_none());
+
+        let data = entry.data.as_ref().unwrap();
+        assert_eq!(data["retries"], 3);
+        assert_eq!(data["path"], "/tmp/x");
+        assert_eq!(data["target"], module_path!());
+    }
+
+    #[test]
+    fn levels_map_onto_log_levels() {
+        let entries = capture(|| {
+            tracing::trace!("t");
+            tracing::debug!("d");
+            tracing::info!("i");
+            tracing::warn!("w");
+            tracing::error!("e");
+        });
+        let levels: Vec<LogLevel> = entries.iter().map(|e| e.level).collect();
+        assert_eq!(
+            levels,
+            [
+                LogLevel::Debug,
+                LogLevel::Debug,
+                LogLevel::Info,
+                LogLevel::Warn,
+                LogLevel::Error
+            ]
+        );
+    }
+
+    #[test]
+    fn display_and_debug_fields_are_recorded_as_strings() {
+        let error = std::io::Error::other("disk full");
+        let entries = capture(|| {
+            tracing::error!(error = %error, kind = ?error.kind(), "Write failed");
+        });
+        let data = entries[0].data.as_ref().unwrap();
+        assert_eq!(data["error"], "disk full");
+        assert_eq!(data["kind"], "Other");
+    }
+
+    #[test]
+    fn entries_use_frontend_schema() {
+        let entries = capture(|| tracing::info!("Backend started"));
+        let json = serde_json::to_value(&entries[0]).unwrap();
+
+        assert!(json["sessionId"].is_string());
+        assert!(json.get("session_id").is_none());
+        assert!(chrono::DateTime::parse_from_rfc3339(json["timestamp"].as_str().unwrap()).is_ok());
+        assert_eq!(json["level"], "INFO");
+    }
+
+    #[test]
+    fn session_id_is_stable_within_process() {
+        assert_eq!(process_session_id(), process_session_id());
+    }
+}
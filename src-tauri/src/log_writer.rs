@@ -0,0 +1,413 @@
+use crate::local_time::local_today;
+use crate::log_compression::spawn_compression;
+use crate::logging::{append_log_lines, current_log_config, throttle_summary_lines, LogLine};
+use crate::storage_guard;
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Lines are written at the latest this long after they were queued
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A batch is written immediately once this many lines are queued
+const MAX_BATCH_LINES: usize = 500;
+
+/// How often ended flood-protection windows are checked for summaries to write
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends beyond this are handed back to be written by the caller, so a slow disk slows
+/// down the callers instead of growing memory without bound
+const QUEUE_CAPACITY: usize = 1024;
+
+enum Command {
+    Append(Vec<LogLine>),
+    /// Write everything queued so far, then acknowledge
+    Flush(mpsc::Sender<()>),
+}
+
+/// Why `LogWriter::submit` didn't accept lines
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SubmitError {
+    /// The writer has stopped; the lines are handed back
+    Stopped(Vec<LogLine>),
+    /// The queue is full; the lines are handed back to be written synchronously
+    Full(Vec<LogLine>),
+    /// The lines were queued, but the last background write failed with this error
+    WriteFailed(String),
+}
+
+/// State shared between the writer handle and its thread
+#[derive(Default)]
+struct Shared {
+    /// Lines lost because neither the writer nor the caller could write them, not yet
+    /// reported
+    dropped: AtomicU64,
+    /// Error of the last write; cleared once a write succeeds again
+    write_error: Mutex<Option<String>>,
+}
+
+/// Dedicated thread that batches rendered log lines and appends them to the daily file.
+///
+/// Dropping the last sender (see `shutdown`) makes the thread write what is left and exit.
+pub struct LogWriter {
+    sender: Mutex<Option<SyncSender<Command>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    shared: Arc<Shared>,
+}
+
+impl LogWriter {
+    /// Start a writer appending to the daily files in `log_dir`.
+    pub fn spawn(log_dir: PathBuf) -> Result<Self, String> {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let shared = Arc::new(Shared::default());
+        let thread_shared = Arc::clone(&shared);
+        let thread = thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn(move || run(&log_dir, &receiver, &thread_shared))
+            .map_err(|e| format!("Failed to start log writer thread: {e}"))?;
+
+        Ok(Self {
+            sender: Mutex::new(Some(sender)),
+            thread: Mutex::new(Some(thread)),
+            shared,
+        })
+    }
+
+    /// Queue lines for writing without blocking.
+    ///
+    /// Lines are handed back if the queue is full or the writer has already stopped; the
+    /// caller writes them itself then. Fails if the previous background write failed, so
+    /// callers learn about a full or broken disk even though the write itself is
+    /// asynchronous.
+    pub(crate) fn submit(&self, lines: Vec<LogLine>) -> Result<(), SubmitError> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let sender = self.sender.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(sender) = sender.as_ref() else {
+            return Err(SubmitError::Stopped(lines));
+        };
+        match sender.try_send(Command::Append(lines)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(Command::Append(lines))) => {
+                return Err(SubmitError::Full(lines));
+            }
+            Err(TrySendError::Disconnected(Command::Append(lines))) => {
+                return Err(SubmitError::Stopped(lines));
+            }
+            Err(_) => unreachable!("only appends are sent here"),
+        }
+
+        match self.last_write_error() {
+            Some(error) => Err(SubmitError::WriteFailed(error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Count lines that were handed back by `submit` but couldn't be written either; they
+    /// are reported with the next background write.
+    pub(crate) fn record_dropped(&self, count: usize) {
+        self.shared
+            .dropped
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Error of the last background write, if it failed.
+    pub(crate) fn last_write_error(&self) -> Option<String> {
+        self.shared
+            .write_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Block until everything queued so far has been written.
+    pub fn flush(&self) {
+        let (ack, done) = mpsc::channel();
+        // Cloned so a full queue doesn't block `submit` while this waits for room
+        let sender = self
+            .sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let sent = sender.is_some_and(|sender| sender.send(Command::Flush(ack)).is_ok());
+        if sent {
+            let _ = done.recv();
+        }
+    }
+
+    /// Drain the queue and stop the thread. Later calls are no-ops.
+    pub fn shutdown(&self) {
+        self.sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let thread = self
+            .thread
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Lines collected by the writer thread but not yet written
+#[derive(Default)]
+struct Batch {
+    lines: Vec<LogLine>,
+    /// When the oldest line must be written; `None` while the batch is empty
+    due: Option<Instant>,
+}
+
+impl Batch {
+    /// Add lines queued at `now`. Returns whether the batch is full and must be written.
+    fn push(&mut self, lines: Vec<LogLine>, now: Instant) -> bool {
+        if lines.is_empty() {
+            return false;
+        }
+        self.due.get_or_insert(now + FLUSH_INTERVAL);
+        self.lines.extend(lines);
+        self.lines.len() >= MAX_BATCH_LINES
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.due.is_some_and(|due| now >= due)
+    }
+
+    fn take(&mut self) -> Vec<LogLine> {
+        self.due = None;
+        std::mem::take(&mut self.lines)
+    }
+}
+
+/// Writer loop: collect lines until the batch is full, the oldest line is due or the
+/// channel closes, then append them in one go. Every `TICK_INTERVAL` the summaries of
+/// ended flood-protection windows are added, and all pending ones when the channel closes.
+fn run(log_dir: &Path, receiver: &mpsc::Receiver<Command>, shared: &Shared) {
+    let mut batch = Batch::default();
+    let mut day = local_today();
+
+    let mut write = |lines: Vec<LogLine>| {
+        let result = append_log_lines(log_dir, &lines, &current_log_config());
+        if let Err(e) = &result {
+            eprintln!("Failed to write {} log entries: {e}", lines.len());
+        }
+        shared
+            .write_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone_from(&result.as_ref().err().cloned());
+        storage_guard::record_write_outcome(&result);
+
+        // Reported after the write, so the queue has room for the warning again
+        let dropped = shared.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            tracing::warn!(dropped, "Dropped log entries that could not be written");
+        }
+
+        // Yesterday's files are complete now
+        let today = local_today();
+        if today != day {
+            day = today;
+            spawn_compression(log_dir.to_path_buf());
+        }
+    };
+
+    let mut next_tick = Instant::now() + TICK_INTERVAL;
+    loop {
+        let wake = batch.due.map_or(next_tick, |due| due.min(next_tick));
+        let message = receiver.recv_timeout(wake.saturating_duration_since(Instant::now()));
+
+        match message {
+            Ok(Command::Append(lines)) => {
+                if batch.push(lines, Instant::now()) {
+                    write(batch.take());
+                }
+            }
+            Ok(Command::Flush(ack)) => {
+                write(batch.take());
+                let _ = ack.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                if now >= next_tick {
+                    next_tick = now + TICK_INTERVAL;
+                    let summaries =
+                        throttle_summary_lines(&current_log_config(), Utc::now(), false);
+                    if batch.push(summaries, now) {
+                        write(batch.take());
+                    }
+                }
+                if batch.is_due(now) {
+                    write(batch.take());
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let mut lines = batch.take();
+                lines.extend(throttle_summary_lines(
+                    &current_log_config(),
+                    Utc::now(),
+                    true,
+                ));
+                write(lines);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn line(i: usize) -> LogLine {
+        LogLine {
+            date: local_today(),
+            text: format!("{{\"message\":\"line-{i}\"}}\n"),
+        }
+    }
+
+    fn written_lines(log_dir: &Path) -> Vec<String> {
+        let mut lines = Vec::new();
+        for file in fs::read_dir(log_dir).unwrap() {
+            let content = fs::read_to_string(file.unwrap().path()).unwrap();
+            lines.extend(content.lines().map(str::to_string));
+        }
+        lines
+    }
+
+    #[test]
+    fn flush_writes_queued_lines() {
+        let tmp = tempfile::tempdir().unwrap();
+        let writer = LogWriter::spawn(tmp.path().to_path_buf()).unwrap();
+
+        writer.submit(vec![line(1), line(2)]).unwrap();
+        writer.flush();
+
+        assert_eq!(written_lines(tmp.path()).len(), 2);
+    }
+
+    #[test]
+    fn batch_is_due_one_flush_interval_after_its_first_line() {
+        let start = Instant::now();
+        let mut batch = Batch::default();
+        assert!(!batch.is_due(start + FLUSH_INTERVAL));
+
+        assert!(!batch.push(vec![line(1)], start));
+        // Later lines don't postpone the oldest one
+        assert!(!batch.push(vec![line(2)], start + FLUSH_INTERVAL / 2));
+        assert!(!batch.is_due(start + FLUSH_INTERVAL / 2));
+        assert!(batch.is_due(start + FLUSH_INTERVAL));
+
+        assert_eq!(batch.take(), vec![line(1), line(2)]);
+        assert!(!batch.is_due(start + FLUSH_INTERVAL * 2));
+    }
+
+    #[test]
+    fn empty_push_leaves_batch_idle() {
+        let now = Instant::now();
+        let mut batch = Batch::default();
+        assert!(!batch.push(Vec::new(), now));
+        assert!(!batch.is_due(now + FLUSH_INTERVAL));
+    }
+
+    #[test]
+    fn full_batch_is_written_immediately() {
+        let mut batch = Batch::default();
+        let now = Instant::now();
+        assert!(!batch.push(vec![line(0); MAX_BATCH_LINES - 1], now));
+        assert!(batch.push(vec![line(1)], now));
+    }
+
+    #[test]
+    fn failed_write_is_reported_by_the_next_submit() {
+        let tmp = tempfile::tempdir().unwrap();
+        // A file where the log directory should be makes every write fail
+        let log_dir = tmp.path().join("logs");
+        fs::write(&log_dir, "").unwrap();
+        let writer = LogWriter::spawn(log_dir).unwrap();
+
+        writer.submit(vec![line(1)]).unwrap();
+        writer.flush();
+
+        assert!(writer.last_write_error().is_some());
+        assert!(matches!(
+            writer.submit(vec![line(2)]),
+            Err(SubmitError::WriteFailed(_))
+        ));
+    }
+
+    #[test]
+    fn shutdown_drains_queue_in_order() {
+        let tmp = tempfile::tempdir().unwrap();
+        let writer = LogWriter::spawn(tmp.path().to_path_buf()).unwrap();
+
+        for i in 0..(MAX_BATCH_LINES * 2 + 7) {
+            writer.submit(vec![line(i)]).unwrap();
+        }
+        writer.shutdown();
+
+        let lines = written_lines(tmp.path());
+        assert_eq!(lines.len(), MAX_BATCH_LINES * 2 + 7);
+        assert!(lines[0].contains("line-0\""));
+        assert!(lines
+            .last()
+            .unwrap()
+            .contains(&format!("line-{}\"", MAX_BATCH_LINES * 2 + 6)));
+    }
+
+    #[test]
+    fn submit_after_shutdown_hands_lines_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let writer = LogWriter::spawn(tmp.path().to_path_buf()).unwrap();
+        writer.shutdown();
+        writer.shutdown();
+
+        assert_eq!(
+            writer.submit(vec![line(1)]),
+            Err(SubmitError::Stopped(vec![line(1)]))
+        );
+        writer.flush();
+    }
+
+    #[test]
+    fn full_queue_hands_lines_back() {
+        // A writer whose queue is never drained
+        let (sender, _receiver) = mpsc::sync_channel(1);
+        let writer = LogWriter {
+            sender: Mutex::new(Some(sender)),
+            thread: Mutex::new(None),
+            shared: Arc::default(),
+        };
+
+        writer.submit(vec![line(1)]).unwrap();
+        assert_eq!(
+            writer.submit(vec![line(2)]),
+            Err(SubmitError::Full(vec![line(2)]))
+        );
+    }
+
+    #[test]
+    fn drop_drains_queue() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let writer = LogWriter::spawn(tmp.path().to_path_buf()).unwrap();
+            writer.submit(vec![line(1), line(2), line(3)]).unwrap();
+        }
+        assert_eq!(written_lines(tmp.path()).len(), 3);
+    }
+}
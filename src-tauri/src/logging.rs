@@ -1,16 +1,106 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use std::fs::{self, OpenOptions};
-use std::io::Write;
-use std::path::PathBuf;
+use crate::encrypted_storage::{self, DeviceKey};
+use crate::local_time::{local_date, local_today};
+use crate::log_audit::{chain_lines, ChainHead};
+use crate::log_store::{self, LogStorage};
+use crate::log_stream;
+use crate::log_throttle::{Admission, LogThrottle, RateLimit};
+use crate::log_writer::{LogWriter, SubmitError};
+use crate::redaction::{load_or_create_salt, RedactionRules, Redactor};
+use crate::storage_guard;
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, RwLock};
 use tauri::{AppHandle, Manager, Runtime};
 
+const LOG_FILE_PREFIX: &str = "pyre-portal-";
+/// Directory of the log files inside the app data directory
+pub(crate) const LOG_DIR_NAME: &str = "logs";
+/// Log settings chosen at runtime, inside the app data directory
+pub(crate) const LOG_SETTINGS_FILE: &str = "log-settings.json";
+const LOG_FILE_EXTENSION: &str = ".log";
+const DEFAULT_MAX_CLOCK_SKEW_SECS: i64 = 300;
+/// Appended to the name of completed daily files once they are gzipped
+pub(crate) const COMPRESSED_EXTENSION: &str = ".gz";
+/// What log lines are encrypted as, in the daily files and the log database
+pub(crate) const LOG_LINE_CONTEXT: &str = "log-line";
+
+/// Device keys of the log directories whose logs are encrypted (see `enable_log_encryption`)
+static LOG_KEYS: RwLock<Vec<(PathBuf, DeviceKey)>> = RwLock::new(Vec::new());
+
+/// Serializes writers so a size rollover never races with an append. Also holds the head
+/// of the audit chain when `LOG_AUDIT_CHAIN` is enabled.
+static WRITE_LOCK: Mutex<Option<ChainHead>> = Mutex::new(None);
+
+/// Hold while renaming or deleting files in the log directory, so no write or rollover
+/// happens in between.
+pub(crate) fn lock_log_dir() -> MutexGuard<'static, Option<ChainHead>> {
+    WRITE_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Severity of a log entry, ordered from least to most severe.
+///
+/// Parsed case-insensitively (`"warning"` is accepted for `WARN`) and always
+/// persisted in upper case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LogLevel {
+    #[default]
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "DEBUG" => Ok(Self::Debug),
+            "INFO" => Ok(Self::Info),
+            "WARN" | "WARNING" => Ok(Self::Warn),
+            "ERROR" => Ok(Self::Error),
+            _ => Err(format!(
+                "Unknown log level '{s}' (expected DEBUG, INFO, WARN or ERROR)"
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LogLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+        })
+    }
+}
+
 /// Log entry structure for serialization/deserialization
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LogEntry {
     pub timestamp: String,
-    pub level: String,
+    pub level: LogLevel,
     pub source: String,
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -18,84 +108,960 @@ pub struct LogEntry {
     pub session_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user_id: Option<String>,
+    /// Backend time at which `write_log` received the entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<String>,
+    /// `timestamp` minus `receivedAt`, only set when the clocks disagree by more than
+    /// the allowed skew
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_skew_ms: Option<i64>,
+    /// Timestamp sent by the frontend if it couldn't be parsed; `timestamp` holds
+    /// `receivedAt` instead then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invalid_timestamp: Option<String>,
+}
+
+/// A rendered log line and the day whose file it is appended to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LogLine {
+    pub date: NaiveDate,
+    pub text: String,
+}
+
+/// Retention limits for the daily log files.
+///
+/// Read once from the environment (`LOG_RETENTION_DAYS`, `LOG_MAX_TOTAL_BYTES`,
+/// `LOG_MAX_FILE_BYTES`); missing or invalid values fall back to the defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Number of days (including today) whose log files are kept
+    pub retention_days: u32,
+    /// Upper bound for the whole log directory; oldest files are deleted first
+    pub max_total_bytes: u64,
+    /// Size at which the current file is rolled over to `.1`, `.2`, ...
+    pub max_file_bytes: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            retention_days: 14,
+            max_total_bytes: 200 * 1024 * 1024,
+            max_file_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            retention_days: env_or("LOG_RETENTION_DAYS", defaults.retention_days),
+            max_total_bytes: env_or("LOG_MAX_TOTAL_BYTES", defaults.max_total_bytes),
+            max_file_bytes: env_or("LOG_MAX_FILE_BYTES", defaults.max_file_bytes),
+        }
+    }
+}
+
+/// Parse a positive number from the environment, falling back to `default`.
+pub(crate) fn env_or<T: std::str::FromStr + PartialOrd + Default>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse::<T>().ok())
+        .filter(|v| *v > T::default())
+        .unwrap_or(default)
+}
+
+/// Backend settings for persisting log entries.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub retention: RetentionPolicy,
+    /// Entries below this level are not written to disk (`LOG_PERSIST_LEVEL`)
+    pub persist_level: LogLevel,
+    /// Strips personal data from entries before they are printed or persisted
+    pub redactor: Arc<Redactor>,
+    /// Link every persisted line to the previous one by hash (`LOG_AUDIT_CHAIN`)
+    pub audit_chain: bool,
+    /// Daily JSONL files or the `SQLite` database (`LOG_STORAGE`)
+    pub storage: LogStorage,
+    /// Frontend timestamps further than this from the backend clock are flagged
+    /// (`LOG_MAX_CLOCK_SKEW_SECS`)
+    pub max_clock_skew: Duration,
+    /// Suppresses floods of the same message (`LOG_RATE_LIMIT_BURST`,
+    /// `LOG_RATE_LIMIT_WINDOW_SECS`); shared by all snapshots of the config
+    pub throttle: Arc<LogThrottle>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            retention: RetentionPolicy::default(),
+            persist_level: LogLevel::default(),
+            redactor: Arc::default(),
+            audit_chain: false,
+            storage: LogStorage::default(),
+            max_clock_skew: Duration::seconds(DEFAULT_MAX_CLOCK_SKEW_SECS),
+            throttle: Arc::default(),
+        }
+    }
+}
+
+impl LogConfig {
+    fn from_env() -> Self {
+        let audit_chain = env::var("LOG_AUDIT_CHAIN")
+            .is_ok_and(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1"));
+        Self {
+            retention: RetentionPolicy::from_env(),
+            persist_level: env::var("LOG_PERSIST_LEVEL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            // Hashing needs the device salt, which `init_redaction` loads at startup
+            redactor: Arc::new(Redactor::new(RedactionRules::from_env(), None)),
+            audit_chain,
+            // The chain only exists in the daily files; `init_log_store` reports the conflict
+            storage: if audit_chain {
+                LogStorage::Jsonl
+            } else {
+                LogStorage::from_env()
+            },
+            max_clock_skew: Duration::seconds(env_or(
+                "LOG_MAX_CLOCK_SKEW_SECS",
+                DEFAULT_MAX_CLOCK_SKEW_SECS,
+            )),
+            throttle: Arc::new(LogThrottle::new(RateLimit::from_env())),
+        }
+    }
+}
+
+/// The logging config of this process, read from the environment on first use and
+/// adjusted at runtime through `set_log_level`.
+fn log_config() -> &'static RwLock<LogConfig> {
+    static CONFIG: OnceLock<RwLock<LogConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| RwLock::new(LogConfig::from_env()))
+}
+
+/// Snapshot of the current logging config.
+pub(crate) fn current_log_config() -> LogConfig {
+    log_config()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Log settings chosen at runtime, persisted across restarts in `log-settings.json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct LogSettings {
+    persist_level: LogLevel,
+}
+
+/// Get the path to the persisted log settings
+fn get_log_settings_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {e}"))?;
+    Ok(app_data_dir.join(LOG_SETTINGS_FILE))
+}
+
+/// The persist level stored by `set_log_level`, `None` if it was never changed.
+pub(crate) fn read_log_settings(settings_path: &Path) -> Result<Option<LogLevel>, String> {
+    if !settings_path.exists() {
+        return Ok(None);
+    }
+
+    let json_data = fs::read_to_string(settings_path)
+        .map_err(|e| format!("Failed to read log settings file: {e}"))?;
+    let settings: LogSettings = serde_json::from_str(&json_data)
+        .map_err(|e| format!("Failed to parse log settings: {e}"))?;
+    Ok(Some(settings.persist_level))
+}
+
+/// Apply a persist level chosen through `set_log_level` in an earlier run (called at
+/// startup). It takes precedence over `LOG_PERSIST_LEVEL`.
+pub fn load_log_settings<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let Some(persist_level) = read_log_settings(&get_log_settings_path(app)?)? else {
+        return Ok(());
+    };
+
+    log_config()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .persist_level = persist_level;
+    Ok(())
 }
 
-/// Parse and write a log entry to the given log directory.
-fn write_log_to_dir(log_dir: &std::path::Path, entry: &str) -> Result<(), String> {
-    let log_entry = serde_json::from_str::<LogEntry>(entry)
+/// Load (or create) the device-local salt so personal identifiers in log entries are
+/// hashed instead of masked (called at startup).
+pub fn init_redaction<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {e}"))?;
+    let salt = load_or_create_salt(&app_data_dir.join("log-redaction-salt"))?;
+
+    let mut config = log_config().write().unwrap_or_else(PoisonError::into_inner);
+    config.redactor = Arc::new(config.redactor.with_salt(salt));
+    Ok(())
+}
+
+/// Encrypt the logs of the app with the device key from now on (called at startup, before
+/// the first entry is written).
+pub fn init_log_encryption<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {e}"))?;
+    let key = DeviceKey::load_or_create(&app_data_dir)?;
+    enable_log_encryption(&app_data_dir.join(LOG_DIR_NAME), key);
+    Ok(())
+}
+
+/// Encrypt what is written to `log_dir` from now on with `key`: the daily files, the log
+/// database and crash reports. What was written before stays readable.
+pub(crate) fn enable_log_encryption(log_dir: &Path, key: DeviceKey) {
+    let mut keys = LOG_KEYS.write().unwrap_or_else(PoisonError::into_inner);
+    keys.retain(|(dir, _)| dir != log_dir);
+    keys.push((log_dir.to_path_buf(), key));
+}
+
+/// Key the logs in `log_dir` are encrypted with, `None` if they are kept in plaintext.
+pub(crate) fn log_key(log_dir: &Path) -> Option<DeviceKey> {
+    LOG_KEYS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|(dir, _)| dir == log_dir)
+        .map(|(_, key)| key.clone())
+}
+
+/// `text` as written to a daily file: sealed if the log directory is encrypted.
+fn seal_log_line<'a>(key: Option<&DeviceKey>, text: &'a str) -> Result<Cow<'a, str>, String> {
+    let Some(key) = key else {
+        return Ok(Cow::Borrowed(text));
+    };
+    let sealed = key.seal_line(LOG_LINE_CONTEXT, text.trim_end_matches('\n'))?;
+    Ok(Cow::Owned(sealed + "\n"))
+}
+
+/// Plaintext of a line read from the logs; `None` if it is sealed and can't be decrypted
+/// with `key`.
+pub(crate) fn open_log_line<'a>(key: Option<&DeviceKey>, line: &'a str) -> Option<Cow<'a, str>> {
+    encrypted_storage::open_line(key, LOG_LINE_CONTEXT, line).ok()
+}
+
+/// Parse a log entry sent by the frontend at `received_at`, normalize its timestamp and
+/// render it (see `render_log_lines`).
+fn prepare_log_line(
+    entry: &str,
+    config: &LogConfig,
+    received_at: DateTime<Utc>,
+) -> Result<Vec<LogLine>, String> {
+    let mut log_entry = serde_json::from_str::<LogEntry>(entry)
         .map_err(|e| format!("Failed to parse log entry: {e}"))?;
+    normalize_timestamp(&mut log_entry, received_at, config.max_clock_skew);
+    let date = local_date(received_at);
+    Ok(render_log_lines(log_entry, config, received_at)
+        .into_iter()
+        .map(|text| LogLine { date, text })
+        .collect())
+}
+
+/// Rewrite the frontend's timestamp as UTC RFC 3339 and stamp `receivedAt`.
+///
+/// If the webview clock is off by more than `max_skew`, the skew is recorded in
+/// `clockSkewMs`. An unparseable timestamp is kept in `invalidTimestamp` and replaced by
+/// `receivedAt`. Entries are filed under the day they were received, whatever their
+/// timestamp: cursors (`read_logs`, the shipper) only move forward through the days, so
+/// a late entry appended to an earlier day would never be seen.
+fn normalize_timestamp(entry: &mut LogEntry, received_at: DateTime<Utc>, max_skew: Duration) {
+    let received = received_at.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let Ok(timestamp) = DateTime::parse_from_rfc3339(entry.timestamp.trim()) else {
+        entry.invalid_timestamp = Some(std::mem::replace(&mut entry.timestamp, received.clone()));
+        entry.received_at = Some(received);
+        return;
+    };
+    let timestamp = timestamp.with_timezone(&Utc);
+    let skew = timestamp - received_at;
+
+    entry.timestamp = timestamp.to_rfc3339_opts(SecondsFormat::Millis, true);
+    entry.received_at = Some(received);
+    entry.clock_skew_ms = (skew.abs() > max_skew).then(|| skew.num_milliseconds());
+}
+
+/// Redact a log entry and run it through the flood protection, then render it along with
+/// the summaries of repeats suppressed earlier (see `render_log_line`).
+fn render_log_lines(
+    mut log_entry: LogEntry,
+    config: &LogConfig,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    config.redactor.redact(&mut log_entry);
+    let Admission {
+        admitted,
+        mut summaries,
+    } = config.throttle.admit(&log_entry, now);
+    if admitted {
+        summaries.push(log_entry);
+    }
+    summaries
+        .into_iter()
+        .filter_map(|entry| render_log_line(&entry, config))
+        .collect()
+}
+
+/// Render the summaries of flood-protection windows that have ended by `now`, or of all
+/// open windows with `drain` (at shutdown), so suppressed repeats are reported even when
+/// nothing else is logged.
+pub(crate) fn throttle_summary_lines(
+    config: &LogConfig,
+    now: DateTime<Utc>,
+    drain: bool,
+) -> Vec<LogLine> {
+    let summaries = if drain {
+        config.throttle.drain(now)
+    } else {
+        config.throttle.collect_expired(now)
+    };
+    let date = local_date(now);
+    summaries
+        .iter()
+        .filter_map(|entry| render_log_line(entry, config))
+        .map(|text| LogLine { date, text })
+        .collect()
+}
+
+/// Print a redacted log entry to the terminal, pass it to live subscribers and render it
+/// as a JSON line. Returns `None` for entries below `persist_level`.
+fn render_log_line(log_entry: &LogEntry, config: &LogConfig) -> Option<String> {
+    log_stream::publish(log_entry);
+
+    // Print log to terminal (visible in `pnpm run tauri dev` and production binary)
+    eprintln!("{}", format_log_entry(log_entry));
+
+    // Low storage sheds the least important entries first
+    if log_entry.level < config.persist_level || !storage_guard::allows_log(log_entry.level) {
+        return None;
+    }
+
+    // Format the log entry as a JSON line
+    Some(format!("{}\n", serde_json::to_string(log_entry).unwrap()))
+}
 
-    // Print frontend log to terminal (visible in `pnpm run tauri dev` and production binary)
-    let data_suffix = log_entry
+/// One-line, human-readable form of a log entry, as printed to the terminal.
+pub(crate) fn format_log_entry(entry: &LogEntry) -> String {
+    let data_suffix = entry
         .data
         .as_ref()
         .map(|d| format!(" {d}"))
         .unwrap_or_default();
-    eprintln!(
+    format!(
         "[{}] [{}] [{}] {}{}",
-        log_entry.timestamp, log_entry.level, log_entry.source, log_entry.message, data_suffix
-    );
+        entry.timestamp, entry.level, entry.source, entry.message, data_suffix
+    )
+}
 
-    let log_file = get_log_file_path(log_dir);
+/// Append rendered log lines to the daily files of their dates in `log_dir`.
+///
+/// The first write after today's file is due applies the retention policy, and a file is
+/// rolled over whenever it would grow beyond `max_file_bytes`. Each run of lines between
+/// rollovers is written with a single call. With `audit_chain` enabled the lines are
+/// hash-chained first (see `log_audit`); since a chain only grows at its end, the audit
+/// chain overrides the per-day routing and they all go to today's file then. Lines are
+/// sealed one by one if the directory is encrypted (see `enable_log_encryption`). With
+/// `LOG_STORAGE=sqlite` the lines go to the database instead.
+pub(crate) fn append_log_lines(
+    log_dir: &Path,
+    lines: &[LogLine],
+    config: &LogConfig,
+) -> Result<(), String> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    if config.storage == LogStorage::Sqlite {
+        return log_store::append_lines(log_dir, lines, &config.retention, local_today());
+    }
 
     // Create log directory if it doesn't exist
     if !log_dir.exists() {
         fs::create_dir_all(log_dir).map_err(|e| format!("Failed to create log directory: {e}"))?;
     }
 
+    let today = local_today();
+    let key = log_key(log_dir);
+    let mut chain_head = lock_log_dir();
+
+    if !log_file_path_for(log_dir, today).exists() {
+        // First write of the day: prune what the previous days left behind
+        apply_retention(log_dir, &config.retention, today)?;
+    }
+
+    if config.audit_chain {
+        let texts: Vec<String> = lines.iter().map(|line| line.text.clone()).collect();
+        // The cached head is only put back once the lines are on disk
+        let (chained, head) = chain_lines(chain_head.take(), log_dir, today, &texts)?;
+        let sealed = chained
+            .iter()
+            .map(|text| seal_log_line(key.as_ref(), text))
+            .collect::<Result<Vec<_>, _>>()?;
+        append_to_day(log_dir, today, today, &sealed, &config.retention)?;
+        *chain_head = Some(head);
+        return Ok(());
+    }
+
+    let mut days: BTreeMap<NaiveDate, Vec<Cow<str>>> = BTreeMap::new();
+    for line in lines {
+        days.entry(line.date.min(today))
+            .or_default()
+            .push(seal_log_line(key.as_ref(), &line.text)?);
+    }
+    for (date, texts) in days {
+        append_to_day(log_dir, date, today, &texts, &config.retention)?;
+    }
+    Ok(())
+}
+
+/// Append `lines` to the current file of `date`, rolling it over as needed.
+fn append_to_day(
+    log_dir: &Path,
+    date: NaiveDate,
+    today: NaiveDate,
+    lines: &[impl AsRef<str>],
+    policy: &RetentionPolicy,
+) -> Result<(), String> {
+    let log_file = log_file_path_for(log_dir, date);
+
+    let mut size = if let Ok(meta) = fs::metadata(&log_file) {
+        meta.len()
+    } else {
+        // Late entries for a day that was compressed already go into a new file
+        if compressed_path(&log_file).exists() {
+            rotate_log_file(&log_file)?;
+        }
+        0
+    };
+
+    let mut chunk = String::new();
+    for line in lines {
+        let line = line.as_ref();
+        if size > 0 && size + line.len() as u64 > policy.max_file_bytes {
+            append_to_file(&log_file, &chunk)?;
+            chunk.clear();
+            rotate_log_file(&log_file)?;
+            apply_retention(log_dir, policy, today)?;
+            size = 0;
+        }
+        chunk.push_str(line);
+        size += line.len() as u64;
+    }
+    append_to_file(&log_file, &chunk)
+}
+
+fn append_to_file(log_file: &Path, data: &str) -> Result<(), String> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
     // Open log file for appending, create if it doesn't exist
     let mut file = OpenOptions::new()
         .append(true)
         .create(true)
-        .open(&log_file)
-        .map_err(|e| format!("Failed to open log file: {e}"))?;
-
-    // Format the log entry as a JSON line
-    let log_line = format!("{}\n", serde_json::to_string(&log_entry).unwrap());
+        .open(log_file)
+        .map_err(|e| storage_guard::write_error("Failed to open log file", &e))?;
 
     // Write to file
-    file.write_all(log_line.as_bytes())
-        .map_err(|e| format!("Failed to write to log file: {e}"))?;
+    file.write_all(data.as_bytes())
+        .map_err(|e| storage_guard::write_error("Failed to write to log file", &e))
+}
 
-    Ok(())
+/// Prepare `entries` and store them. Invalid entries are reported after the valid ones
+/// were stored.
+fn persist_entries<R: Runtime>(app: &AppHandle<R>, entries: &[String]) -> Result<(), String> {
+    let config = current_log_config();
+    let received_at = Utc::now();
+    let mut lines = Vec::with_capacity(entries.len());
+    let mut errors = Vec::new();
+
+    for entry in entries {
+        match prepare_log_line(entry, &config, received_at) {
+            Ok(prepared) => lines.extend(prepared),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    store_lines(app, lines, &config)?;
+
+    match errors.as_slice() {
+        [] => Ok(()),
+        [error] if entries.len() == 1 => Err(error.clone()),
+        [first, ..] => Err(format!(
+            "{} of {} log entries were rejected: {first}",
+            errors.len(),
+            entries.len()
+        )),
+    }
+}
+
+/// Persist a log entry produced by the backend itself. Failures are only printed, since
+/// reporting them through `tracing` would feed them back into this function.
+pub(crate) fn persist_log_entry<R: Runtime>(app: &AppHandle<R>, entry: LogEntry) {
+    let config = current_log_config();
+    let date = local_today();
+    let lines: Vec<LogLine> = render_log_lines(entry, &config, Utc::now())
+        .into_iter()
+        .map(|text| LogLine { date, text })
+        .collect();
+    if let Err(e) = store_lines(app, lines, &config) {
+        eprintln!("Failed to persist backend log entry: {e}");
+    }
+}
+
+/// Hand rendered lines to the background writer, or write them directly when no writer
+/// is running or its queue is full (so a slow disk slows down the callers instead of
+/// losing entries). Lines that can't be written either are counted as dropped.
+fn store_lines<R: Runtime>(
+    app: &AppHandle<R>,
+    lines: Vec<LogLine>,
+    config: &LogConfig,
+) -> Result<(), String> {
+    let writer = app.try_state::<LogWriter>();
+    let unsent = match &writer {
+        Some(writer) => match writer.submit(lines) {
+            Ok(()) => None,
+            Err(SubmitError::Stopped(lines) | SubmitError::Full(lines)) => Some(lines),
+            Err(SubmitError::WriteFailed(e)) => return Err(e),
+        },
+        None => Some(lines),
+    };
+    let Some(lines) = unsent else {
+        return Ok(());
+    };
+    let written = get_log_directory(app)
+        .map_err(|e| e.to_string())
+        .and_then(|log_dir| append_log_lines(&log_dir, &lines, config));
+    if written.is_err() {
+        if let Some(writer) = &writer {
+            writer.record_dropped(lines.len());
+        }
+    }
+    written
 }
 
 /// Function to write a log entry to the log file
 #[tauri::command]
 pub async fn write_log<R: Runtime>(app: AppHandle<R>, entry: String) -> Result<(), String> {
-    let log_dir = get_log_directory(&app).map_err(|e| e.to_string())?;
-    write_log_to_dir(&log_dir, &entry)
+    persist_entries(&app, &[entry])
+}
+
+/// Write several log entries with one IPC round trip
+#[tauri::command]
+pub async fn write_logs<R: Runtime>(app: AppHandle<R>, entries: Vec<String>) -> Result<(), String> {
+    persist_entries(&app, &entries)
+}
+
+/// Start the background log writer for the app's log directory (called at startup).
+pub fn start_log_writer<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let log_dir = get_log_directory(app).map_err(|e| e.to_string())?;
+    app.manage(LogWriter::spawn(log_dir)?);
+    Ok(())
+}
+
+/// Wait until the background writer has written everything queued so far, so readers
+/// see the latest entries.
+pub(crate) fn flush_log_writer<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(writer) = app.try_state::<LogWriter>() {
+        writer.flush();
+    }
+}
+
+/// Write out everything the background writer still holds and stop it. Called before the
+/// process exits; entries logged afterwards are written directly.
+pub fn shutdown_log_writer<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(writer) = app.try_state::<LogWriter>() {
+        writer.shutdown();
+    }
+}
+
+/// Change the minimum level persisted to disk and remember it across restarts
+#[tauri::command]
+pub async fn set_log_level<R: Runtime>(app: AppHandle<R>, level: String) -> Result<(), String> {
+    let persist_level: LogLevel = level.parse()?;
+    let settings_path = get_log_settings_path(&app)?;
+    tracing::info!(level = %persist_level, "Changing log persist level");
+
+    if let Some(parent) = settings_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create app data directory: {e}"))?;
+    }
+    let json_data = serde_json::to_string_pretty(&LogSettings { persist_level })
+        .map_err(|e| format!("Failed to serialize log settings: {e}"))?;
+    fs::write(&settings_path, json_data).map_err(|e| {
+        tracing::error!(error = %e, "Failed to write log settings");
+        format!("Failed to write log settings file: {e}")
+    })?;
+
+    log_config()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .persist_level = persist_level;
+    Ok(())
+}
+
+/// Apply the retention policy to the app's log directory (called at startup).
+///
+/// Returns the number of deleted files.
+pub fn prune_logs<R: Runtime>(app: &AppHandle<R>) -> Result<usize, String> {
+    let log_dir = get_log_directory(app).map_err(|e| e.to_string())?;
+    prune_log_dir(&log_dir)
+}
+
+/// Apply the retention policy to the log files in `log_dir`.
+pub(crate) fn prune_log_dir(log_dir: &Path) -> Result<usize, String> {
+    if !log_dir.exists() {
+        return Ok(0);
+    }
+    let _guard = lock_log_dir();
+    apply_retention(log_dir, &current_log_config().retention, local_today())
 }
 
 /// Get the path to the log directory
-fn get_log_directory<R: Runtime>(
+pub(crate) fn get_log_directory<R: Runtime>(
     app: &AppHandle<R>,
 ) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let app_dir = app.path().app_data_dir()?;
-    Ok(app_dir.join("logs"))
+    Ok(app_dir.join(LOG_DIR_NAME))
 }
 
-/// Get the path to the current log file
-fn get_log_file_path(log_dir: &std::path::Path) -> PathBuf {
-    let now: DateTime<Utc> = Utc::now();
-    let filename = format!("pyre-portal-{}.log", now.format("%Y-%m-%d"));
+/// Get the path to the (current) log file of `date`
+fn log_file_path_for(log_dir: &Path, date: NaiveDate) -> PathBuf {
+    let filename = format!(
+        "{LOG_FILE_PREFIX}{}{LOG_FILE_EXTENSION}",
+        date.format("%Y-%m-%d")
+    );
     log_dir.join(filename)
 }
 
+/// A log file in the log directory: its day and rollover index (0 = current file).
+#[derive(Debug)]
+pub(crate) struct LogFileInfo {
+    pub path: PathBuf,
+    pub date: NaiveDate,
+    pub index: u32,
+    /// Bytes on disk
+    pub size: u64,
+    /// Whether the file is gzipped (`.log.gz`)
+    pub compressed: bool,
+    /// Bytes of log lines in the file, i.e. its size once decompressed
+    pub content_size: u64,
+}
+
+impl LogFileInfo {
+    /// Open the file positioned `offset` bytes into its content, decompressing `.gz`
+    /// files transparently.
+    pub fn open_at(&self, offset: u64) -> io::Result<Box<dyn BufRead>> {
+        let (mut file, compressed) = match File::open(&self.path) {
+            Ok(file) => (file, self.compressed),
+            // Compressed since it was listed
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !self.compressed => {
+                (File::open(compressed_path(&self.path))?, true)
+            }
+            Err(e) => return Err(e),
+        };
+        if !compressed {
+            file.seek(SeekFrom::Start(offset))?;
+            return Ok(Box::new(BufReader::new(file)));
+        }
+        let mut reader = BufReader::new(GzDecoder::new(file));
+        io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+        Ok(Box::new(reader))
+    }
+
+    /// Read the whole content of the file.
+    pub fn read_to_string(&self) -> Result<String, String> {
+        let mut content = String::new();
+        self.open_at(0)
+            .and_then(|mut reader| reader.read_to_string(&mut content))
+            .map_err(|e| format!("Failed to read log file: {e}"))?;
+        Ok(content)
+    }
+}
+
+/// Path of the gzipped version of a log file
+pub(crate) fn compressed_path(file: &Path) -> PathBuf {
+    PathBuf::from(format!("{}{COMPRESSED_EXTENSION}", file.display()))
+}
+
+/// Uncompressed size of a gzip file, taken from its trailer.
+fn gzip_content_size(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::End(-4))?;
+    let mut trailer = [0; 4];
+    file.read_exact(&mut trailer)?;
+    Ok(u64::from(u32::from_le_bytes(trailer)))
+}
+
+/// Parse `pyre-portal-YYYY-MM-DD.log[.N]` into its date and rollover index.
+fn parse_log_file_name(name: &str) -> Option<(NaiveDate, u32)> {
+    let rest = name.strip_prefix(LOG_FILE_PREFIX)?;
+    let (date, suffix) = rest.split_at_checked(10)?;
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let index = match suffix.strip_prefix(LOG_FILE_EXTENSION)? {
+        "" => 0,
+        n => n.strip_prefix('.')?.parse().ok().filter(|n| *n > 0)?,
+    };
+    Some((date, index))
+}
+
+/// List all log files in `log_dir`, oldest first. Gzipped files are listed like plain ones.
+pub(crate) fn list_log_files(log_dir: &Path) -> Result<Vec<LogFileInfo>, String> {
+    let entries =
+        fs::read_dir(log_dir).map_err(|e| format!("Failed to read log directory: {e}"))?;
+
+    let mut files: Vec<LogFileInfo> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let name = name.to_str()?;
+            let (name, compressed) = match name.strip_suffix(COMPRESSED_EXTENSION) {
+                Some(name) => (name, true),
+                None => (name, false),
+            };
+            let (date, index) = parse_log_file_name(name)?;
+            let meta = entry.metadata().ok().filter(fs::Metadata::is_file)?;
+            let content_size = if compressed {
+                gzip_content_size(&entry.path()).ok()?
+            } else {
+                meta.len()
+            };
+            Some(LogFileInfo {
+                path: entry.path(),
+                date,
+                index,
+                size: meta.len(),
+                compressed,
+                content_size,
+            })
+        })
+        .collect();
+
+    // Higher rollover indices hold older entries of the same day
+    files.sort_by(|a, b| {
+        a.date
+            .cmp(&b.date)
+            .then(b.index.cmp(&a.index))
+            .then(a.compressed.cmp(&b.compressed))
+    });
+    // While a file is being compressed both versions exist; the plain one is complete
+    files.dedup_by(|later, earlier| later.date == earlier.date && later.index == earlier.index);
+    Ok(files)
+}
+
+/// Move `file` to `file.1`, shifting existing `.1`, `.2`, ... up by one.
+fn rotate_log_file(file: &Path) -> Result<(), String> {
+    let rotated = |n: u32| PathBuf::from(format!("{}.{n}", file.display()));
+    // Each index may exist plain, compressed or (while being compressed) both
+    let rename = |from: &Path, to: &Path| -> Result<(), String> {
+        for (from, to) in [
+            (from.to_path_buf(), to.to_path_buf()),
+            (compressed_path(from), compressed_path(to)),
+        ] {
+            if from.exists() {
+                fs::rename(from, to).map_err(|e| format!("Failed to rotate log file: {e}"))?;
+            }
+        }
+        Ok(())
+    };
+
+    let mut highest = 0;
+    while rotated(highest + 1).exists() || compressed_path(&rotated(highest + 1)).exists() {
+        highest += 1;
+    }
+    for n in (1..=highest).rev() {
+        rename(&rotated(n), &rotated(n + 1))?;
+    }
+    rename(file, &rotated(1))
+}
+
+/// Delete the oldest log files until `bytes` were freed, sparing today's current file.
+/// With `LOG_STORAGE=sqlite` the oldest database entries are deleted for whatever the
+/// files (left from before the database, already imported) couldn't free. Used when the
+/// disk runs low, independent of the retention policy.
+///
+/// Returns the number of deleted files and database entries.
+pub(crate) fn prune_logs_for_space(log_dir: &Path, bytes: u64) -> Result<usize, String> {
+    if !log_dir.exists() {
+        return Ok(0);
+    }
+    let sqlite = current_log_config().storage == LogStorage::Sqlite;
+    if sqlite {
+        // Opening the database imports the files, so none is lost before it is imported
+        log_store::with_store(log_dir, |_| Ok(()))?;
+    }
+    let mut freed = 0;
+    let mut deleted = 0;
+    {
+        let _guard = lock_log_dir();
+        let today = local_today();
+        for file in list_log_files(log_dir)? {
+            if freed >= bytes {
+                break;
+            }
+            if file.date == today && file.index == 0 {
+                continue;
+            }
+            fs::remove_file(&file.path).map_err(|e| format!("Failed to delete log file: {e}"))?;
+            freed += file.size;
+            deleted += 1;
+        }
+    }
+
+    if sqlite && freed < bytes {
+        deleted += log_store::with_store(log_dir, |store| store.prune_for_space(bytes - freed))?;
+    }
+    Ok(deleted)
+}
+
+/// Delete log files older than `retention_days`, then the oldest remaining files until the
+/// directory fits into `max_total_bytes`. Today's current file is never deleted.
+///
+/// Returns the number of deleted files.
+fn apply_retention(
+    log_dir: &Path,
+    policy: &RetentionPolicy,
+    today: NaiveDate,
+) -> Result<usize, String> {
+    let cutoff = today - Duration::days(i64::from(policy.retention_days));
+    let mut deleted = 0;
+    let mut kept = Vec::new();
+
+    for file in list_log_files(log_dir)? {
+        if file.date <= cutoff {
+            fs::remove_file(&file.path).map_err(|e| format!("Failed to delete log file: {e}"))?;
+            deleted += 1;
+        } else {
+            kept.push(file);
+        }
+    }
+
+    let mut total: u64 = kept.iter().map(|f| f.size).sum();
+    for file in kept {
+        if total <= policy.max_total_bytes {
+            break;
+        }
+        if file.date == today && file.index == 0 {
+            continue;
+        }
+        fs::remove_file(&file.path).map_err(|e| format!("Failed to delete log file: {e}"))?;
+        total -= file.size;
+        deleted += 1;
+    }
+
+    Ok(deleted)
+}
+
+/// Log entries and log files shared by the tests of the log modules
 #[cfg(test)]
-mod tests {
-    use super::*;
+pub(crate) mod test_support {
+    use super::{LogEntry, LogLevel};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
 
-    fn sample_entry() -> LogEntry {
-        LogEntry {
-            timestamp: "2024-01-01T00:00:00Z".to_string(),
-            level: "INFO".to_string(),
-            source: "Test".to_string(),
-            message: "hello".to_string(),
+    /// Builds a `LogEntry` from the defaults of `entry`
+    pub(crate) struct EntryBuilder(LogEntry);
+
+    /// An INFO entry from `App` in session `s1` at 2024-06-15T10:30:00.000Z
+    pub(crate) fn entry(message: impl Into<String>) -> EntryBuilder {
+        EntryBuilder(LogEntry {
+            timestamp: "2024-06-15T10:30:00.000Z".to_string(),
+            level: LogLevel::Info,
+            source: "App".to_string(),
+            message: message.into(),
             data: None,
             session_id: "s1".to_string(),
             user_id: None,
+            received_at: None,
+            clock_skew_ms: None,
+            invalid_timestamp: None,
+        })
+    }
+
+    impl EntryBuilder {
+        pub(crate) fn at(mut self, timestamp: &str) -> Self {
+            self.0.timestamp = timestamp.to_string();
+            self
+        }
+
+        pub(crate) fn level(mut self, level: LogLevel) -> Self {
+            self.0.level = level;
+            self
         }
+
+        pub(crate) fn source(mut self, source: &str) -> Self {
+            self.0.source = source.to_string();
+            self
+        }
+
+        pub(crate) fn session(mut self, session: &str) -> Self {
+            self.0.session_id = session.to_string();
+            self
+        }
+
+        pub(crate) fn user(mut self, user: &str) -> Self {
+            self.0.user_id = Some(user.to_string());
+            self
+        }
+
+        pub(crate) fn data(mut self, data: serde_json::Value) -> Self {
+            self.0.data = Some(data);
+            self
+        }
+
+        pub(crate) fn build(self) -> LogEntry {
+            self.0
+        }
+
+        /// The entry as a line of a log file, without the newline
+        pub(crate) fn line(&self) -> String {
+            serde_json::to_string(&self.0).unwrap()
+        }
+    }
+
+    /// Append `lines` to the log file `name` in `dir`, creating both if needed.
+    pub(crate) fn append_lines(dir: &Path, name: &str, lines: &[String]) {
+        fs::create_dir_all(dir).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(dir.join(name))
+            .unwrap();
+        for line in lines {
+            writeln!(file, "{line}").unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::entry;
+    use super::*;
+
+    /// Path of today's current log file
+    fn get_log_file_path(log_dir: &Path) -> PathBuf {
+        log_file_path_for(log_dir, local_today())
+    }
+
+    /// Parse and write a log entry the way `write_log` does without a background writer.
+    fn write_log_to_dir(log_dir: &Path, entry: &str, config: &LogConfig) -> Result<(), String> {
+        let lines = prepare_log_line(entry, config, Utc::now())?;
+        append_log_lines(log_dir, &lines, config)
+    }
+
+    fn sample_entry() -> LogEntry {
+        entry("hello")
+            .at("2024-01-01T00:00:00Z")
+            .source("Test")
+            .build()
     }
 
     fn sample_entry_json() -> String {
@@ -103,16 +1069,14 @@ mod tests {
     }
 
     fn sample_entry_json_with_data() -> String {
-        serde_json::to_string(&LogEntry {
-            timestamp: "2024-06-15T10:30:00Z".to_string(),
-            level: "WARN".to_string(),
-            source: "RFID".to_string(),
-            message: "Scan timeout".to_string(),
-            data: Some(serde_json::json!({"retries": 3})),
-            session_id: "abc".to_string(),
-            user_id: Some("staff-42".to_string()),
-        })
-        .unwrap()
+        entry("Scan timeout")
+            .at("2024-06-15T10:30:00Z")
+            .level(LogLevel::Warn)
+            .source("RFID")
+            .data(serde_json::json!({"retries": 3}))
+            .session("abc")
+            .user("staff-42")
+            .line()
     }
 
     // ====================================================================
@@ -123,17 +1087,20 @@ mod tests {
     fn log_entry_serialization_roundtrip() {
         let entry = LogEntry {
             timestamp: "2024-01-01T00:00:00Z".to_string(),
-            level: "INFO".to_string(),
+            level: LogLevel::Info,
             source: "TestComponent".to_string(),
             message: "Test message".to_string(),
             data: Some(serde_json::json!({"key": "value"})),
             session_id: "test-session".to_string(),
             user_id: Some("user-1".to_string()),
+            received_at: None,
+            clock_skew_ms: None,
+            invalid_timestamp: None,
         };
         let json = serde_json::to_string(&entry).unwrap();
         let d: LogEntry = serde_json::from_str(&json).unwrap();
         assert_eq!(d.timestamp, "2024-01-01T00:00:00Z");
-        assert_eq!(d.level, "INFO");
+        assert_eq!(d.level, LogLevel::Info);
         assert_eq!(d.source, "TestComponent");
         assert_eq!(d.message, "Test message");
         assert_eq!(d.session_id, "test-session");
@@ -145,12 +1112,15 @@ mod tests {
     fn log_entry_omits_none_fields() {
         let entry = LogEntry {
             timestamp: "t".to_string(),
-            level: "l".to_string(),
+            level: LogLevel::Info,
             source: "s".to_string(),
             message: "m".to_string(),
             data: None,
             session_id: "id".to_string(),
             user_id: None,
+            received_at: None,
+            clock_skew_ms: None,
+            invalid_timestamp: None,
         };
         let json = serde_json::to_string(&entry).unwrap();
         assert!(!json.contains("data"));
@@ -161,12 +1131,15 @@ mod tests {
     fn log_entry_uses_camel_case_keys() {
         let entry = LogEntry {
             timestamp: "t".to_string(),
-            level: "l".to_string(),
+            level: LogLevel::Info,
             source: "s".to_string(),
             message: "m".to_string(),
             data: None,
             session_id: "id".to_string(),
             user_id: Some("u".to_string()),
+            received_at: None,
+            clock_skew_ms: None,
+            invalid_timestamp: None,
         };
         let json = serde_json::to_string(&entry).unwrap();
         assert!(json.contains("sessionId"));
@@ -187,12 +1160,54 @@ mod tests {
             "userId": "staff-42"
         }"#;
         let entry: LogEntry = serde_json::from_str(frontend_json).unwrap();
-        assert_eq!(entry.level, "WARN");
+        assert_eq!(entry.level, LogLevel::Warn);
         assert_eq!(entry.session_id, "abc123_def456");
         assert_eq!(entry.user_id.as_deref(), Some("staff-42"));
         assert!(entry.data.is_some());
     }
 
+    #[test]
+    fn log_level_parses_case_insensitively() {
+        for (input, expected) in [
+            ("debug", LogLevel::Debug),
+            ("Info", LogLevel::Info),
+            ("warn", LogLevel::Warn),
+            ("WARN", LogLevel::Warn),
+            ("Warning", LogLevel::Warn),
+            (" ERROR ", LogLevel::Error),
+        ] {
+            assert_eq!(input.parse::<LogLevel>().unwrap(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn log_level_rejects_unknown_values_with_clear_error() {
+        let err = "TRACE".parse::<LogLevel>().unwrap_err();
+        assert_eq!(
+            err,
+            "Unknown log level 'TRACE' (expected DEBUG, INFO, WARN or ERROR)"
+        );
+
+        let json = sample_entry_json().replace("INFO", "verbose");
+        let err = serde_json::from_str::<LogEntry>(&json).unwrap_err();
+        assert!(err.to_string().contains("Unknown log level 'verbose'"));
+    }
+
+    #[test]
+    fn log_level_is_persisted_in_canonical_form() {
+        let json = sample_entry_json().replace("INFO", "warning");
+        let entry: LogEntry = serde_json::from_str(&json).unwrap();
+        let persisted = serde_json::to_string(&entry).unwrap();
+        assert!(persisted.contains(r#""level":"WARN""#));
+    }
+
+    #[test]
+    fn log_levels_are_ordered_by_severity() {
+        assert!(LogLevel::Debug < LogLevel::Info);
+        assert!(LogLevel::Info < LogLevel::Warn);
+        assert!(LogLevel::Warn < LogLevel::Error);
+    }
+
     // ====================================================================
     // Pure function tests
     // ====================================================================
@@ -206,7 +1221,7 @@ mod tests {
         assert!(std::path::Path::new(filename)
             .extension()
             .is_some_and(|ext| ext.eq_ignore_ascii_case("log")));
-        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let today = local_today().format("%Y-%m-%d").to_string();
         assert!(filename.contains(&today));
     }
 
@@ -226,8 +1241,8 @@ mod tests {
         let tmp = tempfile::tempdir().unwrap();
         let log_dir = tmp.path().join("logs");
 
-        write_log_to_dir(&log_dir, &sample_entry_json()).unwrap();
-        write_log_to_dir(&log_dir, &sample_entry_json()).unwrap();
+        write_log_to_dir(&log_dir, &sample_entry_json(), &LogConfig::default()).unwrap();
+        write_log_to_dir(&log_dir, &sample_entry_json(), &LogConfig::default()).unwrap();
 
         let log_file = get_log_file_path(&log_dir);
         assert!(log_file.exists());
@@ -241,21 +1256,515 @@ mod tests {
     fn write_log_to_dir_with_data() {
         let tmp = tempfile::tempdir().unwrap();
         let log_dir = tmp.path().join("logs");
-        write_log_to_dir(&log_dir, &sample_entry_json_with_data()).unwrap();
+        write_log_to_dir(
+            &log_dir,
+            &sample_entry_json_with_data(),
+            &LogConfig::default(),
+        )
+        .unwrap();
 
         let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
         assert!(content.contains("retries"));
         assert!(content.contains("staff-42"));
     }
 
+    #[test]
+    fn write_log_to_dir_skips_entries_below_persist_level() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let config = LogConfig {
+            persist_level: LogLevel::Warn,
+            ..LogConfig::default()
+        };
+
+        write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+        write_log_to_dir(&log_dir, &sample_entry_json_with_data(), &config).unwrap();
+
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains("Scan timeout"));
+    }
+
+    #[test]
+    fn write_log_to_dir_persists_redacted_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let entry = sample_entry_json_with_data().replace(
+            r#"{"retries":3}"#,
+            r#"{"retries":3,"pin":"1234","tagId":"04:D6:94:82:97:6A:80"}"#,
+        );
+
+        write_log_to_dir(&log_dir, &entry, &LogConfig::default()).unwrap();
+
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        assert!(content.contains("retries"));
+        assert!(!content.contains("1234"));
+        assert!(!content.contains("04:D6:94:82:97:6A:80"));
+    }
+
+    #[test]
+    fn write_log_to_dir_chains_lines_in_audit_mode() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let config = LogConfig {
+            audit_chain: true,
+            ..LogConfig::default()
+        };
+
+        write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+        write_log_to_dir(&log_dir, &sample_entry_json_with_data(), &config).unwrap();
+
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        let entries: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["source"], crate::log_audit::AUDIT_SOURCE);
+        assert_eq!(entries[1]["prevHash"], entries[0]["hash"]);
+        assert_eq!(entries[2]["prevHash"], entries[1]["hash"]);
+
+        let results = crate::log_audit::verify_log_dir(&log_dir).unwrap();
+        assert!(results.iter().all(|r| r.broken_link.is_none()));
+    }
+
+    #[test]
+    fn append_log_lines_files_chained_lines_under_today() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let config = LogConfig {
+            audit_chain: true,
+            ..LogConfig::default()
+        };
+        let line = LogLine {
+            date: local_today() - Duration::days(1),
+            text: format!("{}\n", sample_entry_json()),
+        };
+
+        append_log_lines(&log_dir, &[line], &config).unwrap();
+
+        // The chain overrides routing: a late entry for yesterday still ends today's chain
+        assert!(!log_dir.join(dated_log_name(1)).exists());
+        let content = fs::read_to_string(log_dir.join(dated_log_name(0))).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("hello"));
+    }
+
+    #[test]
+    fn append_log_lines_writes_batch_across_rollover() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let line = LogLine {
+            date: local_today(),
+            text: format!("{}\n", sample_entry_json()),
+        };
+        let policy = RetentionPolicy {
+            max_file_bytes: (line.text.len() * 2) as u64,
+            ..RetentionPolicy::default()
+        };
+
+        let config = LogConfig {
+            retention: policy,
+            ..LogConfig::default()
+        };
+
+        append_log_lines(&log_dir, &vec![line.clone(); 5], &config).unwrap();
+
+        let current = get_log_file_path(&log_dir);
+        let count = |path: PathBuf| fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(count(current.clone()), 1);
+        assert_eq!(count(PathBuf::from(format!("{}.1", current.display()))), 2);
+        assert_eq!(count(PathBuf::from(format!("{}.2", current.display()))), 2);
+    }
+
     #[test]
     fn write_log_to_dir_rejects_invalid_json() {
         let tmp = tempfile::tempdir().unwrap();
-        let result = write_log_to_dir(tmp.path(), "not valid json");
+        let result = write_log_to_dir(tmp.path(), "not valid json", &LogConfig::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Failed to parse"));
     }
 
+    #[test]
+    fn prepare_log_line_normalizes_timestamp_and_sets_received_at() {
+        let received_at = "2024-06-15T10:30:05Z".parse().unwrap();
+        let entry = sample_entry_json_with_data().replace("10:30:00Z", "12:30:00+02:00");
+        let line = prepare_log_line(&entry, &LogConfig::default(), received_at)
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        let persisted: LogEntry = serde_json::from_str(&line.text).unwrap();
+        assert_eq!(persisted.timestamp, "2024-06-15T10:30:00.000Z");
+        assert_eq!(
+            persisted.received_at.as_deref(),
+            Some("2024-06-15T10:30:05.000Z")
+        );
+        assert_eq!(persisted.clock_skew_ms, None);
+        assert_eq!(line.date, NaiveDate::from_ymd_opt(2024, 6, 15).unwrap());
+    }
+
+    #[test]
+    fn prepare_log_line_keeps_entry_with_malformed_timestamp() {
+        let received_at = "2024-06-15T10:30:00Z".parse().unwrap();
+        let entry = sample_entry_json().replace("2024-01-01T00:00:00Z", "yesterday");
+        let line = prepare_log_line(&entry, &LogConfig::default(), received_at)
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        let persisted: LogEntry = serde_json::from_str(&line.text).unwrap();
+        assert_eq!(persisted.timestamp, "2024-06-15T10:30:00.000Z");
+        assert_eq!(
+            persisted.received_at.as_deref(),
+            Some("2024-06-15T10:30:00.000Z")
+        );
+        assert_eq!(persisted.invalid_timestamp.as_deref(), Some("yesterday"));
+        assert_eq!(persisted.clock_skew_ms, None);
+        assert_eq!(line.date, local_date(received_at));
+    }
+
+    #[test]
+    fn prepare_log_line_flags_skewed_clock_and_files_by_arrival() {
+        let received_at = "2024-06-16T00:10:00Z".parse().unwrap();
+        let entry = sample_entry_json_with_data();
+        let line = prepare_log_line(&entry, &LogConfig::default(), received_at)
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        let persisted: LogEntry = serde_json::from_str(&line.text).unwrap();
+        assert_eq!(persisted.clock_skew_ms, Some(-49_200_000));
+        assert_eq!(line.date, NaiveDate::from_ymd_opt(2024, 6, 16).unwrap());
+    }
+
+    #[test]
+    fn prepare_log_line_files_entries_queued_before_midnight_by_arrival() {
+        let received_at = "2024-06-15T22:00:30Z".parse().unwrap();
+        let entry = sample_entry_json_with_data().replace("10:30:00Z", "21:59:50Z");
+        let line = prepare_log_line(&entry, &LogConfig::default(), received_at)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(line.date, local_date(received_at));
+
+        let persisted: LogEntry = serde_json::from_str(&line.text).unwrap();
+        assert_eq!(persisted.timestamp, "2024-06-15T21:59:50.000Z");
+    }
+
+    #[test]
+    fn back_dated_entry_written_after_a_cursor_is_read() {
+        use crate::log_reader::{read_logs_from_dir, LogQuery};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let config = LogConfig::default();
+        write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+        let page = read_logs_from_dir(&log_dir, &LogQuery::default()).unwrap();
+        assert_eq!(page.entries.len(), 1);
+
+        // Queued by the frontend two days ago and only delivered now
+        let queued = (Utc::now() - Duration::days(2)).to_rfc3339();
+        let entry = sample_entry_json().replace("2024-01-01T00:00:00Z", &queued);
+        write_log_to_dir(&log_dir, &entry, &config).unwrap();
+
+        let query = LogQuery {
+            cursor: page.next_cursor,
+            ..LogQuery::default()
+        };
+        let page = read_logs_from_dir(&log_dir, &query).unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert!(page.entries[0].clock_skew_ms.is_some());
+    }
+
+    #[test]
+    fn encrypted_log_dir_is_written_sealed_and_read_back() {
+        use crate::log_audit::verify_log_dir;
+        use crate::log_reader::{read_logs_from_dir, LogQuery};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let config = LogConfig {
+            audit_chain: true,
+            ..LogConfig::default()
+        };
+        // Written before encryption was enabled; stays readable
+        write_log_to_dir(&log_dir, &sample_entry_json(), &LogConfig::default()).unwrap();
+        enable_log_encryption(&log_dir, DeviceKey::load_or_create(tmp.path()).unwrap());
+        write_log_to_dir(&log_dir, &sample_entry_json_with_data(), &config).unwrap();
+        write_log_to_dir(&log_dir, &sample_entry_json_with_data(), &config).unwrap();
+
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        assert!(!content.contains("Scan timeout"));
+        assert_eq!(content.lines().count(), 4);
+
+        let page = read_logs_from_dir(&log_dir, &LogQuery::default()).unwrap();
+        let messages: Vec<&str> = page.entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "hello",
+                "Daily audit chain anchor",
+                "Scan timeout",
+                "Scan timeout"
+            ]
+        );
+        let verified = verify_log_dir(&log_dir).unwrap();
+        assert_eq!(verified[0].chained_lines, 3);
+        assert!(verified[0].broken_link.is_none());
+    }
+
+    #[test]
+    fn write_log_to_dir_collapses_repeated_messages() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let config = LogConfig {
+            throttle: Arc::new(LogThrottle::new(RateLimit {
+                burst: 2,
+                window: Duration::zero(),
+            })),
+            ..LogConfig::default()
+        };
+        // With an empty window every entry starts a new one, so nothing is suppressed
+        for _ in 0..4 {
+            write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+        }
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        assert_eq!(content.lines().count(), 4);
+
+        let config = LogConfig {
+            throttle: Arc::new(LogThrottle::new(RateLimit {
+                burst: 2,
+                window: Duration::hours(1),
+            })),
+            ..LogConfig::default()
+        };
+        for _ in 0..5 {
+            write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+        }
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        assert_eq!(content.lines().count(), 6);
+    }
+
+    #[test]
+    fn throttle_summary_lines_report_pending_repeats() {
+        let config = LogConfig {
+            throttle: Arc::new(LogThrottle::new(RateLimit {
+                burst: 1,
+                window: Duration::hours(1),
+            })),
+            ..LogConfig::default()
+        };
+        let now = Utc::now();
+        for _ in 0..4 {
+            prepare_log_line(&sample_entry_json(), &config, now).unwrap();
+        }
+
+        assert!(throttle_summary_lines(&config, now, false).is_empty());
+        let lines = throttle_summary_lines(&config, now, true);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].date, local_date(now));
+        assert!(lines[0].text.contains("hello (repeated 3 times)"));
+        assert!(throttle_summary_lines(&config, now, true).is_empty());
+    }
+
+    #[test]
+    fn append_log_lines_routes_lines_to_their_day() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let today = local_today();
+        let line = |date: NaiveDate| LogLine {
+            date,
+            text: format!("{}\n", sample_entry_json()),
+        };
+
+        append_log_lines(
+            &log_dir,
+            &[line(today - Duration::days(1)), line(today)],
+            &LogConfig::default(),
+        )
+        .unwrap();
+
+        assert!(log_dir.join(dated_log_name(1)).exists());
+        assert!(log_dir.join(dated_log_name(0)).exists());
+    }
+
+    #[test]
+    fn append_log_lines_continues_compressed_day_in_new_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let yesterday = local_today() - Duration::days(1);
+        write_file(&log_dir, &dated_log_name(1), 10);
+        crate::log_compression::compress_completed_logs(&log_dir, local_today()).unwrap();
+
+        let line = LogLine {
+            date: yesterday,
+            text: format!("{}\n", sample_entry_json()),
+        };
+        append_log_lines(&log_dir, &[line], &LogConfig::default()).unwrap();
+
+        assert!(log_dir.join(format!("{}.1.gz", dated_log_name(1))).exists());
+        let files: Vec<LogFileInfo> = list_log_files(&log_dir)
+            .unwrap()
+            .into_iter()
+            .filter(|f| f.date == yesterday)
+            .collect();
+        assert_eq!(files.len(), 2);
+        assert!(files[1].read_to_string().unwrap().contains("hello"));
+    }
+
+    // ====================================================================
+    // Retention tests
+    // ====================================================================
+
+    fn dated_log_name(days_ago: i64) -> String {
+        let date = local_today() - Duration::days(days_ago);
+        format!("pyre-portal-{}.log", date.format("%Y-%m-%d"))
+    }
+
+    fn write_file(dir: &Path, name: &str, size: usize) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(name), "x".repeat(size)).unwrap();
+    }
+
+    #[test]
+    fn parse_log_file_name_accepts_daily_and_rotated_files() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        assert_eq!(
+            parse_log_file_name("pyre-portal-2024-06-15.log"),
+            Some((date, 0))
+        );
+        assert_eq!(
+            parse_log_file_name("pyre-portal-2024-06-15.log.2"),
+            Some((date, 2))
+        );
+        assert_eq!(parse_log_file_name("pyre-portal-2024-06-15.log.0"), None);
+        assert_eq!(parse_log_file_name("pyre-portal-2024-13-01.log"), None);
+        assert_eq!(parse_log_file_name("other-2024-06-15.log"), None);
+        assert_eq!(parse_log_file_name("pyre-portal-2024-06-15.txt"), None);
+    }
+
+    #[test]
+    fn write_log_to_dir_prunes_expired_files_on_new_day() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        write_file(&log_dir, &dated_log_name(1), 10);
+        write_file(&log_dir, &dated_log_name(3), 10);
+        write_file(&log_dir, &format!("{}.1", dated_log_name(3)), 10);
+        write_file(&log_dir, "unrelated.txt", 10);
+
+        let config = LogConfig {
+            retention: RetentionPolicy {
+                retention_days: 2,
+                ..RetentionPolicy::default()
+            },
+            ..LogConfig::default()
+        };
+        write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+
+        assert!(log_dir.join(dated_log_name(1)).exists());
+        assert!(!log_dir.join(dated_log_name(3)).exists());
+        assert!(!log_dir.join(format!("{}.1", dated_log_name(3))).exists());
+        assert!(log_dir.join("unrelated.txt").exists());
+        assert!(get_log_file_path(&log_dir).exists());
+    }
+
+    #[test]
+    fn prune_logs_for_space_deletes_oldest_files_first() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        write_file(&log_dir, &dated_log_name(3), 100);
+        write_file(&log_dir, &format!("{}.1", dated_log_name(3)), 100);
+        write_file(&log_dir, &dated_log_name(2), 100);
+        write_file(&log_dir, &dated_log_name(0), 100);
+
+        assert_eq!(prune_logs_for_space(&log_dir, 150).unwrap(), 2);
+        assert!(!log_dir.join(format!("{}.1", dated_log_name(3))).exists());
+        assert!(!log_dir.join(dated_log_name(3)).exists());
+        assert!(log_dir.join(dated_log_name(2)).exists());
+
+        // Today's current file is kept even if not enough could be freed
+        assert_eq!(prune_logs_for_space(&log_dir, 1000).unwrap(), 1);
+        assert!(log_dir.join(dated_log_name(0)).exists());
+    }
+
+    #[test]
+    fn write_log_to_dir_rolls_over_oversized_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        let config = LogConfig {
+            retention: RetentionPolicy {
+                max_file_bytes: 400,
+                ..RetentionPolicy::default()
+            },
+            ..LogConfig::default()
+        };
+
+        for _ in 0..5 {
+            write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+        }
+
+        let current = get_log_file_path(&log_dir);
+        let rotated = |n: u32| PathBuf::from(format!("{}.{n}", current.display()));
+        assert!(current.exists());
+        assert!(rotated(1).exists());
+        assert!(rotated(2).exists());
+        for path in [current.clone(), rotated(1), rotated(2)] {
+            assert!(fs::metadata(path).unwrap().len() <= 400);
+        }
+    }
+
+    #[test]
+    fn write_log_to_dir_enforces_total_size_oldest_first() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_dir = tmp.path().join("logs");
+        write_file(&log_dir, &dated_log_name(2), 400);
+        write_file(&log_dir, &dated_log_name(1), 400);
+
+        let config = LogConfig {
+            retention: RetentionPolicy {
+                max_total_bytes: 600,
+                ..RetentionPolicy::default()
+            },
+            ..LogConfig::default()
+        };
+        write_log_to_dir(&log_dir, &sample_entry_json(), &config).unwrap();
+
+        assert!(!log_dir.join(dated_log_name(2)).exists());
+        assert!(log_dir.join(dated_log_name(1)).exists());
+        assert!(get_log_file_path(&log_dir).exists());
+    }
+
+    #[test]
+    fn apply_retention_never_deletes_current_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_file(tmp.path(), &dated_log_name(0), 500);
+        write_file(tmp.path(), &format!("{}.1", dated_log_name(0)), 500);
+
+        let policy = RetentionPolicy {
+            max_total_bytes: 100,
+            ..RetentionPolicy::default()
+        };
+        let deleted = apply_retention(tmp.path(), &policy, local_today()).unwrap();
+
+        assert_eq!(deleted, 1);
+        assert!(tmp.path().join(dated_log_name(0)).exists());
+    }
+
+    #[test]
+    fn env_or_falls_back_on_missing_or_invalid_values() {
+        let key = "PYREPORTAL_TEST_LOG_RETENTION";
+        env::remove_var(key);
+        assert_eq!(env_or(key, 7_u32), 7);
+        env::set_var(key, "not-a-number");
+        assert_eq!(env_or(key, 7_u32), 7);
+        env::set_var(key, "0");
+        assert_eq!(env_or(key, 7_u32), 7);
+        env::set_var(key, " 30 ");
+        assert_eq!(env_or(key, 7_u32), 30);
+        env::remove_var(key);
+    }
+
     // ====================================================================
     // Tauri mock-app integration tests
     // ====================================================================
@@ -292,4 +1801,102 @@ mod tests {
         let result = write_log(app.handle().clone(), "{{bad".to_string()).await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn write_logs_writes_all_entries_through_background_writer() {
+        let app = tauri::test::mock_builder()
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap();
+        let handle = app.handle().clone();
+        start_log_writer(&handle).unwrap();
+
+        let entries: Vec<String> = (0..20)
+            .map(|i| sample_entry_json().replace("hello", &format!("batch-entry-{i}")))
+            .collect();
+        write_logs(handle.clone(), entries).await.unwrap();
+        shutdown_log_writer(&handle);
+
+        let log_dir = get_log_directory(&handle).unwrap();
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        for i in 0..20 {
+            assert!(content.contains(&format!("batch-entry-{i}\"")), "entry {i}");
+        }
+    }
+
+    #[tokio::test]
+    async fn persist_log_entry_writes_backend_entries_to_daily_file() {
+        let app = tauri::test::mock_builder()
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap();
+        let handle = app.handle().clone();
+
+        persist_log_entry(
+            &handle,
+            LogEntry {
+                source: "rust".to_string(),
+                message: "backend-entry".to_string(),
+                ..sample_entry()
+            },
+        );
+
+        let log_dir = get_log_directory(&handle).unwrap();
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        assert!(content.contains(r#""source":"rust","message":"backend-entry""#));
+    }
+
+    #[tokio::test]
+    async fn write_logs_keeps_valid_entries_and_reports_invalid_ones() {
+        let app = tauri::test::mock_builder()
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap();
+        let handle = app.handle().clone();
+        let entries = vec![
+            sample_entry_json().replace("hello", "valid-batch-entry"),
+            "{{bad".to_string(),
+        ];
+
+        let err = write_logs(handle.clone(), entries).await.unwrap_err();
+        assert!(err.starts_with("1 of 2 log entries were rejected"), "{err}");
+
+        let log_dir = get_log_directory(&handle).unwrap();
+        let content = fs::read_to_string(get_log_file_path(&log_dir)).unwrap();
+        assert!(content.contains("valid-batch-entry"));
+    }
+
+    #[tokio::test]
+    async fn set_log_level_applies_and_persists_level() {
+        let app = tauri::test::mock_builder()
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap();
+        let handle = app.handle().clone();
+
+        // INFO keeps the entries of tests running alongside persisted
+        set_log_level(handle.clone(), "info".to_string())
+            .await
+            .unwrap();
+        assert_eq!(current_log_config().persist_level, LogLevel::Info);
+        let saved = fs::read_to_string(get_log_settings_path(&handle).unwrap()).unwrap();
+        assert!(saved.contains("INFO"));
+
+        // A fresh start picks the persisted level up again
+        log_config().write().unwrap().persist_level = LogLevel::Debug;
+        load_log_settings(&handle).unwrap();
+        assert_eq!(current_log_config().persist_level, LogLevel::Info);
+
+        set_log_level(handle.clone(), "debug".to_string())
+            .await
+            .unwrap();
+        fs::remove_file(get_log_settings_path(&handle).unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_log_level_rejects_unknown_level() {
+        let app = tauri::test::mock_builder()
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap();
+        let err = set_log_level(app.handle().clone(), "loud".to_string())
+            .await
+            .unwrap_err();
+        assert!(err.contains("Unknown log level 'loud'"));
+    }
 }
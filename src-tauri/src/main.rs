@@ -2,5 +2,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // `pyreportal admin ...` runs maintenance commands without starting the webview
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "admin") {
+        std::process::exit(pyreportal_lib::run_admin(&args[1..]));
+    }
     pyreportal_lib::run();
 }
@@ -0,0 +1,478 @@
+use crate::logging::LogEntry;
+use regex::Regex;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Replacement for values that must never be persisted
+const REDACTED: &str = "[REDACTED]";
+
+/// Field names whose values are always replaced, regardless of `LOG_REDACT_TAGS`
+const DEFAULT_SECRET_FIELDS: &[&str] = &[
+    "pin",
+    "staffPin",
+    "password",
+    "token",
+    "apiKey",
+    "deviceApiKey",
+    "authorization",
+];
+
+/// Field names holding personal identifiers: RFID tags, names and staff/student IDs
+const DEFAULT_PERSONAL_FIELDS: &[&str] = &[
+    "tagId",
+    "rfid",
+    "studentRfid",
+    "uid",
+    "studentId",
+    "studentName",
+    "staffId",
+    "staffName",
+    "supervisorName",
+    "supervisorNames",
+    "supervisorIds",
+    "username",
+    "firstName",
+    "lastName",
+];
+
+/// RFID UIDs as the readers report them: 4 to 10 hex bytes separated by `:` or `-`, or
+/// 4, 7 or 10 bytes (single, double and triple size UIDs) written without separators
+const DEFAULT_PATTERNS: &[&str] = &[
+    r"(?i)\b[0-9a-f]{2}(?:[:-][0-9a-f]{2}){3,9}\b",
+    r"(?i)\b(?:[0-9a-f]{20}|[0-9a-f]{14}|[0-9a-f]{8})\b",
+];
+
+/// How personal identifiers are made unreadable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagRedaction {
+    /// Replace the value with `[REDACTED]`
+    Mask,
+    /// Replace the value with a salted hash, so repeated scans of one tag stay correlatable
+    #[default]
+    Hash,
+}
+
+/// Which parts of a log entry are redacted before it is persisted.
+#[derive(Debug, Clone)]
+pub struct RedactionRules {
+    /// Normalized names of fields whose values are always masked
+    secret_fields: Vec<String>,
+    /// Normalized names of fields whose values are masked or hashed per `mode`
+    personal_fields: Vec<String>,
+    /// Patterns replaced in any string of the entry (message and data)
+    patterns: Vec<Regex>,
+    mode: TagRedaction,
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        Self {
+            secret_fields: DEFAULT_SECRET_FIELDS
+                .iter()
+                .map(|f| normalize_field(f))
+                .collect(),
+            personal_fields: DEFAULT_PERSONAL_FIELDS
+                .iter()
+                .map(|f| normalize_field(f))
+                .collect(),
+            patterns: DEFAULT_PATTERNS
+                .iter()
+                .map(|p| Regex::new(p).expect("default redaction pattern is valid"))
+                .collect(),
+            mode: TagRedaction::default(),
+        }
+    }
+}
+
+impl RedactionRules {
+    /// Default rules extended from the environment:
+    /// `LOG_REDACT_FIELDS` (comma-separated field names to mask),
+    /// `LOG_REDACT_PATTERNS` (whitespace-separated regexes to treat like tag IDs) and
+    /// `LOG_REDACT_TAGS` (`hash` or `mask`). Invalid patterns are reported and skipped.
+    pub fn from_env() -> Self {
+        let mut rules = Self::default();
+
+        if let Ok(fields) = env::var("LOG_REDACT_FIELDS") {
+            rules.secret_fields.extend(
+                fields
+                    .split(',')
+                    .map(normalize_field)
+                    .filter(|f| !f.is_empty()),
+            );
+        }
+        if let Ok(patterns) = env::var("LOG_REDACT_PATTERNS") {
+            for pattern in patterns.split_whitespace() {
+                match Regex::new(pattern) {
+                    Ok(regex) => rules.patterns.push(regex),
+                    Err(e) => eprintln!("Ignoring invalid LOG_REDACT_PATTERNS entry: {e}"),
+                }
+            }
+        }
+        if env::var("LOG_REDACT_TAGS").is_ok_and(|v| v.trim().eq_ignore_ascii_case("mask")) {
+            rules.mode = TagRedaction::Mask;
+        }
+
+        rules
+    }
+}
+
+/// Field names are compared ignoring case, `_` and `-` (`tagId` = `tag_id` = `TAG-ID`).
+fn normalize_field(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_' && *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Applies `RedactionRules` to log entries before they are written to disk.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    rules: RedactionRules,
+    /// Device-local salt for hashing; without it, hashed values are masked instead
+    salt: Option<Vec<u8>>,
+}
+
+impl Redactor {
+    pub fn new(rules: RedactionRules, salt: Option<Vec<u8>>) -> Self {
+        Self { rules, salt }
+    }
+
+    /// The same rules, hashing with `salt`.
+    pub fn with_salt(&self, salt: Vec<u8>) -> Self {
+        Self::new(self.rules.clone(), Some(salt))
+    }
+
+    /// Redact the message and data of `entry` in place.
+    pub fn redact(&self, entry: &mut LogEntry) {
+        entry.message = self.redact_patterns(&entry.message);
+        if let Some(data) = entry.data.as_mut() {
+            self.redact_value(data);
+        }
+    }
+
+    /// Redact a whole JSON document in place, e.g. a settings file leaving the device.
+    pub fn redact_json(&self, value: &mut Value) {
+        self.redact_value(value);
+    }
+
+    /// Redact the patterns in free text that never went through `redact`, e.g. a crash
+    /// report's panic message.
+    pub fn redact_text(&self, text: &str) -> String {
+        self.redact_patterns(text)
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, field) in map.iter_mut() {
+                    let key = normalize_field(key);
+                    if self.rules.secret_fields.contains(&key) {
+                        *field = Value::String(REDACTED.to_string());
+                    } else if self.rules.personal_fields.contains(&key) {
+                        self.redact_personal(field);
+                    } else {
+                        self.redact_value(field);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact_value(item)),
+            Value::String(s) => *s = self.redact_patterns(s),
+            _ => {}
+        }
+    }
+
+    /// Replace every scalar below a personal field.
+    fn redact_personal(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => map.values_mut().for_each(|v| self.redact_personal(v)),
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact_personal(v)),
+            Value::Null => {}
+            Value::String(s) => *s = self.pseudonymize(s),
+            scalar => *scalar = Value::String(self.pseudonymize(&scalar.to_string())),
+        }
+    }
+
+    fn redact_patterns(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in &self.rules.patterns {
+            if pattern.is_match(&text) {
+                text = pattern
+                    .replace_all(&text, |caps: &regex::Captures| self.pseudonymize(&caps[0]))
+                    .into_owned();
+            }
+        }
+        text
+    }
+
+    /// Mask or hash a personal identifier. Tag IDs are hashed case-insensitively and
+    /// without separators, so `04:d6:...` and `04-D6-...` map to the same value.
+    fn pseudonymize(&self, value: &str) -> String {
+        let (TagRedaction::Hash, Some(salt)) = (self.rules.mode, &self.salt) else {
+            return REDACTED.to_string();
+        };
+        let canonical: String = value
+            .chars()
+            .filter(|c| *c != ':' && *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(canonical.as_bytes());
+        let digest = format!("{:x}", hasher.finalize());
+        format!("[h:{}]", &digest[..12])
+    }
+}
+
+/// Read the device-local salt stored at `path`.
+fn load_salt(path: &Path) -> Result<Vec<u8>, String> {
+    let existing = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read redaction salt {}: {e}", path.display()))?;
+    hex::decode(existing.trim())
+        .ok()
+        .filter(|salt| !salt.is_empty())
+        .ok_or_else(|| format!("Invalid redaction salt in {}", path.display()))
+}
+
+/// Read the device-local salt from `path`, creating a random one on first use.
+///
+/// An existing but unreadable salt is never replaced: every tag hash would change and no
+/// longer match earlier logs.
+pub fn load_or_create_salt(path: &Path) -> Result<Vec<u8>, String> {
+    if path.exists() {
+        return load_salt(path);
+    }
+
+    let mut salt = vec![0u8; 32];
+    getrandom::fill(&mut salt).map_err(|e| format!("Failed to generate redaction salt: {e}"))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create app data directory: {e}"))?;
+    }
+    let created = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| {
+            file.write_all(hex::encode(&salt).as_bytes())?;
+            file.sync_all()
+        });
+    match created {
+        Ok(()) => Ok(salt),
+        // Created concurrently; use that one
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => load_salt(path),
+        Err(e) => Err(format!("Failed to write redaction salt: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::test_support::entry;
+
+    const TAG: &str = "04:D6:94:82:97:6A:80";
+
+    fn salted() -> Redactor {
+        Redactor::new(RedactionRules::default(), Some(b"test-salt".to_vec()))
+    }
+
+    /// The sample entry the frontend sends for a scan timeout (see logging tests)
+    fn frontend_entry() -> LogEntry {
+        serde_json::from_str(
+            r#"{
+                "timestamp": "2024-06-15T10:30:00.000Z",
+                "level": "WARN",
+                "source": "RFIDService",
+                "message": "Scan timeout",
+                "data": {"tagId": "04:D6:94:82:97:6A:80", "retries": 3},
+                "sessionId": "abc123_def456",
+                "userId": "staff-42"
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn entry_with(message: &str, data: Value) -> LogEntry {
+        entry(message).source("Test").data(data).build()
+    }
+
+    #[test]
+    fn hashes_tag_id_field_and_keeps_other_data() {
+        let mut entry = frontend_entry();
+        salted().redact(&mut entry);
+
+        let data = entry.data.unwrap();
+        let tag = data["tagId"].as_str().unwrap();
+        assert!(tag.starts_with("[h:"), "{tag}");
+        assert!(!tag.contains("94:82"));
+        assert_eq!(data["retries"], 3);
+        assert_eq!(entry.message, "Scan timeout");
+        assert_eq!(entry.user_id.as_deref(), Some("staff-42"));
+    }
+
+    #[test]
+    fn same_tag_hashes_identically_across_fields_and_formats() {
+        let redactor = salted();
+        let mut entry = entry_with(
+            &format!("Scanned {TAG} at door"),
+            serde_json::json!({"rfid": "04-d6-94-82-97-6a-80", "tag_id": TAG}),
+        );
+        redactor.redact(&mut entry);
+
+        let data = entry.data.unwrap();
+        let hashed = data["tag_id"].as_str().unwrap();
+        assert_eq!(data["rfid"], hashed);
+        assert_eq!(entry.message, format!("Scanned {hashed} at door"));
+    }
+
+    #[test]
+    fn hash_depends_on_device_salt() {
+        let other = Redactor::new(RedactionRules::default(), Some(b"other-salt".to_vec()));
+        let mut a = frontend_entry();
+        let mut b = frontend_entry();
+        salted().redact(&mut a);
+        other.redact(&mut b);
+        assert_ne!(a.data.unwrap()["tagId"], b.data.unwrap()["tagId"]);
+    }
+
+    #[test]
+    fn secrets_are_always_masked() {
+        let mut entry = entry_with(
+            "Login",
+            serde_json::json!({"pin": "1234", "auth": {"Authorization": "Bearer abc"}}),
+        );
+        salted().redact(&mut entry);
+
+        let data = entry.data.unwrap();
+        assert_eq!(data["pin"], REDACTED);
+        assert_eq!(data["auth"]["Authorization"], REDACTED);
+    }
+
+    #[test]
+    fn personal_fields_are_redacted_recursively() {
+        let mut entry = entry_with(
+            "Session started",
+            serde_json::json!({
+                "supervisorNames": ["Herr Müller", "Frau Schmidt"],
+                "students": [{"studentName": "Max", "studentId": 17}],
+                "roomName": "Turnhalle",
+            }),
+        );
+        salted().redact(&mut entry);
+
+        let data = entry.data.unwrap();
+        for name in data["supervisorNames"].as_array().unwrap() {
+            assert!(name.as_str().unwrap().starts_with("[h:"));
+        }
+        assert!(data["students"][0]["studentName"]
+            .as_str()
+            .unwrap()
+            .starts_with("[h:"));
+        assert!(data["students"][0]["studentId"]
+            .as_str()
+            .unwrap()
+            .starts_with("[h:"));
+        assert_eq!(data["roomName"], "Turnhalle");
+    }
+
+    #[test]
+    fn masks_instead_of_hashing_without_salt_or_in_mask_mode() {
+        let mut unsalted = frontend_entry();
+        Redactor::default().redact(&mut unsalted);
+        assert_eq!(unsalted.data.unwrap()["tagId"], REDACTED);
+
+        let rules = RedactionRules {
+            mode: TagRedaction::Mask,
+            ..RedactionRules::default()
+        };
+        let mut masked = entry_with(&format!("Tag {TAG}"), serde_json::json!({}));
+        Redactor::new(rules, Some(b"salt".to_vec())).redact(&mut masked);
+        assert_eq!(masked.message, "Tag [REDACTED]");
+    }
+
+    #[test]
+    fn leaves_entries_without_personal_data_untouched() {
+        let mut entry = entry_with(
+            "Request took 120ms",
+            serde_json::json!({"endpoint": "/api/rooms", "status": 200, "activityId": 42}),
+        );
+        let before = serde_json::to_string(&entry).unwrap();
+        salted().redact(&mut entry);
+        assert_eq!(serde_json::to_string(&entry).unwrap(), before);
+    }
+
+    #[test]
+    fn normalize_field_ignores_case_and_separators() {
+        assert_eq!(normalize_field("tagId"), "tagid");
+        assert_eq!(normalize_field("tag_id"), "tagid");
+        assert_eq!(normalize_field(" TAG-ID "), "tagid");
+    }
+
+    #[test]
+    fn salt_is_created_once_and_reused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("nested").join("log-redaction-salt");
+
+        let first = load_or_create_salt(&path).unwrap();
+        let second = load_or_create_salt(&path).unwrap();
+        assert_eq!(first.len(), 32);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn corrupt_salt_is_reported_not_replaced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("log-redaction-salt");
+        fs::write(&path, "not hex").unwrap();
+
+        let err = load_or_create_salt(&path).unwrap_err();
+        assert!(err.contains("Invalid redaction salt"), "{err}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not hex");
+    }
+
+    #[test]
+    fn free_text_is_redacted_like_messages() {
+        let redactor = salted();
+        let mut entry = entry_with(&format!("Unknown tag {TAG}"), serde_json::json!({}));
+        redactor.redact(&mut entry);
+
+        let text = redactor.redact_text(&format!("panicked at 'Unknown tag {TAG}'"));
+        assert!(!text.contains(TAG));
+        assert_eq!(text, format!("panicked at '{}'", entry.message));
+    }
+
+    #[test]
+    fn contiguous_tag_ids_are_redacted() {
+        let redactor = salted();
+        let mut entry = entry_with(
+            "Unknown tag 04D69482976A80 scanned",
+            serde_json::json!({
+                "note": "uid 04d69482976a80",
+                "single": "Tag A1B2C3D4",
+                "triple": "0102030405060708090A",
+            }),
+        );
+        redactor.redact(&mut entry);
+
+        // Same hash as the colon-separated form
+        let mut separated =
+            entry_with(&format!("Unknown tag {TAG} scanned"), serde_json::json!({}));
+        redactor.redact(&mut separated);
+        assert_eq!(entry.message, separated.message);
+
+        let data = entry.data.unwrap();
+        assert!(!data["note"].as_str().unwrap().contains("04d694"));
+        assert!(data["single"].as_str().unwrap().starts_with("Tag [h:"));
+        assert!(data["triple"].as_str().unwrap().starts_with("[h:"));
+
+        // Other lengths of hex or digits are left alone
+        let mut other = entry_with("Request 0123456789 took 120ms", serde_json::json!({}));
+        redactor.redact(&mut other);
+        assert_eq!(other.message, "Request 0123456789 took 120ms");
+    }
+}
@@ -1,6 +1,19 @@
+use crate::encrypted_storage;
+use crate::local_time::local_now;
+use crate::logging::env_or;
+use crate::storage_guard;
+use chrono::{DateTime, Datelike, Duration, SecondsFormat, Timelike, Utc};
+use chrono_tz::Tz;
+use fs4::fs_std::FileExt;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use tauri::{AppHandle, Manager, Runtime};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,13 +30,349 @@ pub struct LastSessionConfig {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SessionSettings {
+    /// Shape of the stored file; always `SESSION_SCHEMA_VERSION` when written
+    #[serde(default = "current_schema_version")]
+    pub schema_version: u32,
     pub use_last_session: bool,  // Toggle state
     pub auto_save_enabled: bool, // Always true for now
     pub last_session: Option<LastSessionConfig>,
+    /// Managed by the preset commands; kept as stored when the settings are saved
+    #[serde(default)]
+    pub presets: Vec<SessionPreset>,
+    /// Sessions saved so far, oldest first; kept as stored when the settings are saved
+    #[serde(default)]
+    pub session_history: Vec<LastSessionConfig>,
+    /// Id of the next preset created, so ids of deleted presets are never given out again;
+    /// kept as stored when the settings are saved
+    #[serde(default = "first_preset_id")]
+    pub next_preset_id: u32,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            schema_version: SESSION_SCHEMA_VERSION,
+            use_last_session: false,
+            auto_save_enabled: true,
+            last_session: None,
+            presets: Vec::new(),
+            session_history: Vec::new(),
+            next_preset_id: first_preset_id(),
+        }
+    }
+}
+
+/// A named activity/room/supervisor combination staff can start again with one tap.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionPreset {
+    pub id: u32,
+    pub name: String,
+    pub session: LastSessionConfig,
+    /// How often the preset has been applied
+    pub use_count: u32,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+/// A likely session for the current weekday and time, returned by `suggest_sessions`.
+#[derive(Debug, Serialize, Clone)]
+pub struct SessionSuggestion {
+    /// The most recent use of this combination (for up-to-date display names)
+    pub session: LastSessionConfig,
+    /// Higher is more likely; only meaningful relative to other suggestions
+    pub score: f64,
+    /// How often the combination appears in the history
+    pub times_used: u32,
+    /// Whether it was used on this weekday around this time before
+    pub matches_time_slot: bool,
+}
+
+/// Saved sessions kept in the history; older ones are dropped first
+const MAX_SESSION_HISTORY: usize = 500;
+/// Sessions saved within this many minutes of the current time of day count as the same slot
+const TIME_SLOT_MINUTES: i64 = 90;
+const MINUTES_PER_DAY: i64 = 24 * 60;
+/// Weight of a past session halves every this many days
+const HISTORY_HALF_LIFE_DAYS: f64 = 56.0;
+/// Suggestions returned when the caller doesn't ask for a number
+const DEFAULT_SUGGESTIONS: usize = 3;
+const MAX_SUGGESTIONS: usize = 10;
+
+/// How old the remembered last session is, by `LastSessionConfig.saved_at`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionFreshness {
+    Fresh,
+    /// Older than `LAST_SESSION_STALE_DAYS`: offer it, but ask staff to check it
+    Stale,
+    /// Older than `LAST_SESSION_MAX_AGE_DAYS` or without a readable `saved_at`: not offered
+    Expired,
+}
+
+/// When the last session becomes stale and expires, read from `LAST_SESSION_STALE_DAYS`
+/// and `LAST_SESSION_MAX_AGE_DAYS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SessionAgeLimits {
+    pub stale_after: Duration,
+    pub max_age: Duration,
+}
+
+impl Default for SessionAgeLimits {
+    fn default() -> Self {
+        Self {
+            stale_after: Duration::days(7),
+            max_age: Duration::days(28),
+        }
+    }
+}
+
+impl SessionAgeLimits {
+    pub(crate) fn from_env() -> Self {
+        let defaults = Self::default();
+        Self::new(
+            Duration::days(env_or(
+                "LAST_SESSION_STALE_DAYS",
+                defaults.stale_after.num_days(),
+            )),
+            Duration::days(env_or(
+                "LAST_SESSION_MAX_AGE_DAYS",
+                defaults.max_age.num_days(),
+            )),
+        )
+    }
+
+    /// A session can't go stale after it expired: a stale limit that isn't below the
+    /// maximum age is lowered to it, so sessions expire without a stale phase.
+    fn new(stale_after: Duration, max_age: Duration) -> Self {
+        if stale_after >= max_age {
+            tracing::warn!(
+                stale_days = stale_after.num_days(),
+                max_age_days = max_age.num_days(),
+                "LAST_SESSION_STALE_DAYS is not below LAST_SESSION_MAX_AGE_DAYS, sessions expire without going stale"
+            );
+        }
+        Self {
+            stale_after: stale_after.min(max_age),
+            max_age,
+        }
+    }
+
+    fn classify(self, saved_at: &str, now: DateTime<Utc>) -> SessionFreshness {
+        let Ok(saved_at) = DateTime::parse_from_rfc3339(saved_at) else {
+            tracing::warn!(saved_at, "Last session has an unreadable saved_at");
+            return SessionFreshness::Expired;
+        };
+        let age = now - saved_at.with_timezone(&Utc);
+        if age > self.max_age {
+            SessionFreshness::Expired
+        } else if age > self.stale_after {
+            SessionFreshness::Stale
+        } else {
+            SessionFreshness::Fresh
+        }
+    }
+}
+
+/// Returned by `load_session_settings`: the settings plus how old the last session is.
+#[derive(Debug, Serialize)]
+pub struct LoadedSessionSettings {
+    /// An expired last session is withheld (`last_session` is `null`)
+    #[serde(flatten)]
+    pub settings: SessionSettings,
+    /// `None` when there is no last session
+    pub last_session_status: Option<SessionFreshness>,
+}
+
+impl LoadedSessionSettings {
+    fn new(mut settings: SessionSettings, limits: SessionAgeLimits, now: DateTime<Utc>) -> Self {
+        let last_session_status = settings
+            .last_session
+            .as_ref()
+            .map(|session| limits.classify(&session.saved_at, now));
+        if last_session_status == Some(SessionFreshness::Expired) {
+            let saved_at = settings.last_session.take().map(|session| session.saved_at);
+            tracing::info!(saved_at, "Last session expired, not offering it");
+        }
+        Self {
+            settings,
+            last_session_status,
+        }
+    }
+}
+
+/// Session settings inside the app data directory
+pub(crate) const SESSION_SETTINGS_FILE: &str = "session-settings.json";
+/// Appended to the settings file name for the copy of the previous good version
+const BACKUP_EXTENSION: &str = ".bak";
+/// Appended (after a unique suffix) to the settings file name while a new version is written
+const TEMP_EXTENSION: &str = ".tmp";
+/// Appended to the settings file name for the file locked while the settings are read or
+/// written, so `pyreportal admin` in another process waits for the app and vice versa
+const LOCK_EXTENSION: &str = ".lock";
+/// What the settings file is encrypted as (see `encrypted_storage::seal`)
+const ENCRYPTION_CONTEXT: &str = "session-settings";
+
+/// Current shape of the settings file; bump it together with a new entry in `MIGRATIONS`
+pub(crate) const SESSION_SCHEMA_VERSION: u32 = 4;
+
+/// `MIGRATIONS[n]` upgrades a settings file of schema version `n` to `n + 1`
+const MIGRATIONS: [fn(&mut Map<String, Value>); SESSION_SCHEMA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+];
+
+/// Held while the settings files are read or written, so concurrent commands don't
+/// overwrite each other's changes (see `lock_settings`)
+static SETTINGS_LOCK: Mutex<()> = Mutex::new(());
+/// Makes temp file names unique within the process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// `SETTINGS_LOCK` together with an advisory lock on the lock file next to the settings,
+/// released when dropped
+struct SettingsLock {
+    _file: Option<File>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+/// Take `SETTINGS_LOCK` and the lock file of `settings_path`. Without a directory there
+/// is nothing to protect, so only `SETTINGS_LOCK` is taken then.
+fn lock_settings(settings_path: &Path) -> Result<SettingsLock, String> {
+    let guard = SETTINGS_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let file = match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(with_extension_appended(settings_path, LOCK_EXTENSION))
+    {
+        Ok(file) => Some(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to open session settings lock: {e}")),
+    };
+    if let Some(file) = &file {
+        file.lock_exclusive()
+            .map_err(|e| format!("Failed to lock session settings: {e}"))?;
+    }
+    Ok(SettingsLock {
+        _file: file,
+        _guard: guard,
+    })
+}
+
+fn current_schema_version() -> u32 {
+    SESSION_SCHEMA_VERSION
+}
+
+fn first_preset_id() -> u32 {
+    1
+}
+
+/// Files written before versioning have the same fields as version 1
+fn migrate_v0_to_v1(_settings: &mut Map<String, Value>) {}
+
+/// Version 2 adds session presets
+fn migrate_v1_to_v2(settings: &mut Map<String, Value>) {
+    settings
+        .entry("presets")
+        .or_insert_with(|| Value::Array(Vec::new()));
+}
+
+/// Version 3 adds the history of saved sessions, seeded with the last session
+fn migrate_v2_to_v3(settings: &mut Map<String, Value>) {
+    let history = match settings.get("last_session") {
+        Some(session @ Value::Object(_)) => vec![session.clone()],
+        _ => Vec::new(),
+    };
+    settings
+        .entry("session_history")
+        .or_insert(Value::Array(history));
+}
+
+/// Version 4 persists the next preset id, continuing after the highest one in use
+fn migrate_v3_to_v4(settings: &mut Map<String, Value>) {
+    let highest = settings
+        .get("presets")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|preset| preset.get("id")?.as_u64())
+        .max()
+        .unwrap_or(0);
+    settings
+        .entry("next_preset_id")
+        .or_insert_with(|| Value::from(highest + 1));
+}
+
+/// Why a settings file couldn't be loaded
+#[derive(Debug)]
+enum ParseError {
+    /// Unreadable or not a settings file (e.g. truncated by a power cut)
+    Corrupt(String),
+    /// Written by a newer app version in a shape this one can't read
+    Unsupported(String),
+}
+
+impl ParseError {
+    fn into_message(self) -> String {
+        match self {
+            Self::Corrupt(message) | Self::Unsupported(message) => message,
+        }
+    }
+}
+
+/// Upgrade settings stored with any schema version to the current `SessionSettings`.
+///
+/// Files from a newer app version (e.g. after a rollback) are read as far as this version
+/// understands them; fields it doesn't know are ignored, so they are never written back
+/// (see `write_settings_file`).
+fn migrate_settings(value: Value) -> Result<SessionSettings, ParseError> {
+    let Value::Object(mut fields) = value else {
+        return Err(ParseError::Corrupt(
+            "Failed to parse session settings: not a JSON object".to_string(),
+        ));
+    };
+
+    let version = match fields.get("schema_version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| {
+                ParseError::Corrupt(format!(
+                    "Failed to parse session settings: invalid schema_version {version}"
+                ))
+            })?,
+    };
+
+    if version > SESSION_SCHEMA_VERSION {
+        tracing::warn!(
+            version,
+            supported = SESSION_SCHEMA_VERSION,
+            "Session settings were written by a newer app version"
+        );
+        return serde_json::from_value(Value::Object(fields)).map_err(|e| {
+            ParseError::Unsupported(format!(
+                "Session settings were written by a newer app version (schema {version}): {e}"
+            ))
+        });
+    }
+
+    for migrate in &MIGRATIONS[version as usize..] {
+        migrate(&mut fields);
+    }
+    fields.insert(
+        "schema_version".to_string(),
+        Value::from(SESSION_SCHEMA_VERSION),
+    );
+    serde_json::from_value(Value::Object(fields))
+        .map_err(|e| ParseError::Corrupt(format!("Failed to parse session settings: {e}")))
 }
 
 /// Get the path to the session settings file
-fn get_session_settings_path<R: Runtime>(app_handle: &AppHandle<R>) -> Result<PathBuf, String> {
+pub(crate) fn get_session_settings_path<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<PathBuf, String> {
     let app_data_dir = app_handle
         .path()
         .app_data_dir()
@@ -33,73 +382,544 @@ fn get_session_settings_path<R: Runtime>(app_handle: &AppHandle<R>) -> Result<Pa
     fs::create_dir_all(&app_data_dir)
         .map_err(|e| format!("Failed to create app data directory: {e}"))?;
 
-    Ok(app_data_dir.join("session-settings.json"))
+    Ok(app_data_dir.join(SESSION_SETTINGS_FILE))
 }
 
-#[tauri::command]
-pub async fn save_session_settings<R: Runtime>(
-    app_handle: AppHandle<R>,
-    settings: SessionSettings,
-) -> Result<(), String> {
-    let settings_path = get_session_settings_path(&app_handle)?;
+/// `path` with `extension` appended to its file name
+fn with_extension_appended(path: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(extension);
+    PathBuf::from(name)
+}
 
-    let json_data = serde_json::to_string_pretty(&settings)
-        .map_err(|e| format!("Failed to serialize session settings: {e}"))?;
+/// Directory of the device key the settings file at `path` is encrypted with
+fn key_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new("."))
+}
 
-    fs::write(&settings_path, json_data)
-        .map_err(|e| format!("Failed to write session settings file: {e}"))?;
+/// Decrypted contents of a settings file; files from before encryption are returned as is.
+pub(crate) fn read_settings_plaintext(path: &Path) -> Result<Vec<u8>, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read session settings file: {e}"))?;
+    encrypted_storage::open(key_dir(path), ENCRYPTION_CONTEXT, data)
+}
 
-    Ok(())
+fn parse_settings_file(path: &Path) -> Result<SessionSettings, ParseError> {
+    let json_data = read_settings_plaintext(path).map_err(ParseError::Corrupt)?;
+
+    let value = serde_json::from_slice(&json_data)
+        .map_err(|e| ParseError::Corrupt(format!("Failed to parse session settings: {e}")))?;
+    migrate_settings(value)
 }
 
-#[tauri::command]
-pub async fn load_session_settings<R: Runtime>(
-    app_handle: AppHandle<R>,
-) -> Result<Option<SessionSettings>, String> {
-    let settings_path = get_session_settings_path(&app_handle)?;
+/// Read the settings file, migrated to the current schema, `None` if it doesn't exist yet.
+///
+/// A file that can't be parsed (e.g. truncated by a power cut) is replaced by its backup.
+/// One from a newer app version that can't be read is left alone.
+pub(crate) fn read_settings_file(settings_path: &Path) -> Result<Option<SessionSettings>, String> {
+    let _lock = lock_settings(settings_path)?;
+    load_settings_file(settings_path)
+}
 
+/// `read_settings_file` for callers already holding `lock_settings`
+fn load_settings_file(settings_path: &Path) -> Result<Option<SessionSettings>, String> {
     // Check if file exists
     if !settings_path.exists() {
         return Ok(None);
     }
 
-    let json_data = fs::read_to_string(&settings_path)
-        .map_err(|e| format!("Failed to read session settings file: {e}"))?;
-
-    let settings: SessionSettings = serde_json::from_str(&json_data)
-        .map_err(|e| format!("Failed to parse session settings: {e}"))?;
+    let error = match parse_settings_file(settings_path) {
+        Ok(settings) => return Ok(Some(settings)),
+        Err(ParseError::Unsupported(e)) => return Err(e),
+        Err(ParseError::Corrupt(e)) => e,
+    };
+    tracing::error!(error = %error, "Session settings file is corrupt, restoring backup");
 
+    let backup_path = with_extension_appended(settings_path, BACKUP_EXTENSION);
+    let settings = parse_settings_file(&backup_path).map_err(|backup_error| {
+        format!(
+            "{error} (backup not usable: {})",
+            backup_error.into_message()
+        )
+    })?;
+    // Put the backup back in place, so the next save backs up a good version again
+    if let Err(e) = read_settings_plaintext(&backup_path).and_then(|data| {
+        write_sealed(
+            settings_path,
+            &data,
+            "Failed to write session settings file",
+        )
+    }) {
+        tracing::warn!(error = %e, "Failed to restore session settings from backup");
+    }
     Ok(Some(settings))
 }
 
+/// Remove the temp files of writes interrupted by a crash and encrypt the settings files
+/// stored in plaintext by older versions (called at startup, once the device key exists).
+/// Reading never rewrites them.
+pub fn init_session_storage<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), String> {
+    let settings_path = get_session_settings_path(app_handle)?;
+    let _lock = lock_settings(&settings_path)?;
+    remove_leftover_temp_files(&settings_path);
+    seal_plaintext_files(&settings_path);
+    Ok(())
+}
+
+/// Delete temp files of the settings (and backup) file left behind by writes that never
+/// finished. Callers hold `lock_settings`, so no write is in progress.
+fn remove_leftover_temp_files(settings_path: &Path) {
+    let (Some(dir), Some(name)) = (settings_path.parent(), settings_path.file_name()) else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    for entry in entries.filter_map(Result::ok) {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if !file_name.starts_with(&prefix) || !file_name.ends_with(TEMP_EXTENSION) {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(()) => tracing::info!(file = %file_name, "Removed leftover settings temp file"),
+            Err(e) => tracing::warn!(error = %e, "Failed to remove leftover settings temp file"),
+        }
+    }
+}
+
+/// Encrypt settings files (and their backup) stored in plaintext by older versions.
+/// Callers hold `lock_settings`.
+fn seal_plaintext_files(settings_path: &Path) {
+    let backup_path = with_extension_appended(settings_path, BACKUP_EXTENSION);
+    for path in [settings_path, backup_path.as_path()] {
+        let Ok(data) = fs::read(path) else {
+            continue;
+        };
+        if encrypted_storage::is_sealed(&data) {
+            continue;
+        }
+        match write_sealed(path, &data, "Failed to encrypt session settings file") {
+            Ok(()) => {
+                tracing::info!(file = %path.display(), "Encrypted plaintext session settings");
+            }
+            Err(e) => tracing::warn!(error = %e, "Failed to encrypt plaintext session settings"),
+        }
+    }
+}
+
+/// Encrypt `plaintext` and write it atomically to `path`; `context` prefixes write errors.
+fn write_sealed(path: &Path, plaintext: &[u8], context: &str) -> Result<(), String> {
+    let sealed = encrypted_storage::seal(key_dir(path), ENCRYPTION_CONTEXT, plaintext)?;
+    write_atomically(path, &sealed).map_err(|e| storage_guard::write_error(context, &e))
+}
+
+/// Write `data` to a temp file next to `path`, flush it to disk and rename it over `path`,
+/// so a crash leaves either the old or the new content behind.
+///
+/// The temp file name is unique, so writers in other processes (e.g. `pyreportal admin`)
+/// can't clobber it.
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let suffix = format!(
+        ".{}-{}{TEMP_EXTENSION}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let temp_path = with_extension_appended(path, &suffix);
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Schema version of the file at `path`, `None` if it is missing or unreadable.
+fn stored_schema_version(path: &Path) -> Option<u64> {
+    let data = read_settings_plaintext(path).ok()?;
+    let value: Value = serde_json::from_slice(&data).ok()?;
+    value.get("schema_version")?.as_u64()
+}
+
+/// Save `settings` with the current schema version, keeping the previous file as backup.
+///
+/// A file written by a newer app version is never overwritten: this version would drop
+/// the fields it doesn't know and downgrade the schema, and the backup would follow.
+/// Callers hold `lock_settings`.
+fn write_settings_file(settings_path: &Path, settings: &SessionSettings) -> Result<(), String> {
+    if let Some(version) = stored_schema_version(settings_path)
+        .filter(|&version| version > u64::from(SESSION_SCHEMA_VERSION))
+    {
+        return Err(format!(
+            "Session settings were written by a newer app version (schema {version}), \
+             not overwriting them"
+        ));
+    }
+
+    let settings = SessionSettings {
+        schema_version: SESSION_SCHEMA_VERSION,
+        ..settings.clone()
+    };
+    let json_data = serde_json::to_string_pretty(&settings)
+        .map_err(|e| format!("Failed to serialize session settings: {e}"))?;
+
+    // Keep the current version as backup, unless it is corrupt itself
+    if !matches!(
+        parse_settings_file(settings_path),
+        Err(ParseError::Corrupt(_))
+    ) {
+        let backup_path = with_extension_appended(settings_path, BACKUP_EXTENSION);
+        read_settings_plaintext(settings_path).and_then(|current| {
+            write_sealed(
+                &backup_path,
+                &current,
+                "Failed to back up session settings file",
+            )
+        })?;
+    }
+
+    write_sealed(
+        settings_path,
+        json_data.as_bytes(),
+        "Failed to write session settings file",
+    )
+}
+
 #[tauri::command]
-pub async fn clear_last_session<R: Runtime>(app_handle: AppHandle<R>) -> Result<(), String> {
-    let settings_path = get_session_settings_path(&app_handle)?;
+pub async fn save_session_settings<R: Runtime>(
+    app_handle: AppHandle<R>,
+    settings: SessionSettings,
+) -> Result<(), String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| {
+            update_settings_in(&path, |stored| {
+                // The frontend doesn't send presets and history, so keep the stored ones
+                let mut settings = SessionSettings {
+                    presets: std::mem::take(&mut stored.presets),
+                    session_history: std::mem::take(&mut stored.session_history),
+                    next_preset_id: stored.next_preset_id,
+                    ..settings
+                };
+                if let Some(session) = settings.last_session.clone() {
+                    let saved_before = stored
+                        .last_session
+                        .as_ref()
+                        .is_some_and(|last| last.saved_at == session.saved_at);
+                    if !saved_before {
+                        settings.record_session(session);
+                    }
+                }
+                *stored = settings;
+                Ok(())
+            })
+        })
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to save session settings"))
+}
+
+#[tauri::command]
+pub async fn load_session_settings<R: Runtime>(
+    app_handle: AppHandle<R>,
+) -> Result<Option<LoadedSessionSettings>, String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| read_settings_file(&path))
+        .map(|settings| {
+            settings.map(|settings| {
+                LoadedSessionSettings::new(settings, SessionAgeLimits::from_env(), Utc::now())
+            })
+        })
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to load session settings"))
+}
 
+/// Forget the last session stored in `settings_path` and turn the toggle off.
+pub(crate) fn clear_last_session_in(settings_path: &Path) -> Result<(), String> {
+    let _lock = lock_settings(settings_path)?;
     // Load existing settings if available
-    if settings_path.exists() {
-        let json_data = fs::read_to_string(&settings_path)
-            .map_err(|e| format!("Failed to read session settings file: {e}"))?;
+    let Some(mut settings) = load_settings_file(settings_path)? else {
+        return Ok(());
+    };
 
-        let mut settings: SessionSettings = serde_json::from_str(&json_data)
-            .map_err(|e| format!("Failed to parse session settings: {e}"))?;
+    // Clear only the last session data, keep toggle state
+    settings.last_session = None;
+    settings.use_last_session = false; // Also turn off toggle when clearing
 
-        // Clear only the last session data, keep toggle state
-        settings.last_session = None;
-        settings.use_last_session = false; // Also turn off toggle when clearing
+    // Save updated settings
+    write_settings_file(settings_path, &settings)
+}
 
-        // Save updated settings
-        let json_data = serde_json::to_string_pretty(&settings)
-            .map_err(|e| format!("Failed to serialize session settings: {e}"))?;
+#[tauri::command]
+pub async fn clear_last_session<R: Runtime>(app_handle: AppHandle<R>) -> Result<(), String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| clear_last_session_in(&path))
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to clear last session"))
+}
+
+fn timestamp(now: DateTime<Utc>) -> String {
+    now.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
 
-        fs::write(&settings_path, json_data)
-            .map_err(|e| format!("Failed to write session settings file: {e}"))?;
+impl SessionSettings {
+    /// Presets with the most used first, then the most recently used
+    fn favourite_presets(&self) -> Vec<SessionPreset> {
+        let mut presets = self.presets.clone();
+        presets.sort_by(|a, b| {
+            b.use_count
+                .cmp(&a.use_count)
+                .then_with(|| b.last_used_at.cmp(&a.last_used_at))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        presets
     }
 
-    Ok(())
+    fn preset_mut(&mut self, id: u32) -> Result<&mut SessionPreset, String> {
+        self.presets
+            .iter_mut()
+            .find(|preset| preset.id == id)
+            .ok_or_else(|| format!("Unknown session preset: {id}"))
+    }
+
+    /// Trimmed `name`, if it is non-empty and not used by another preset
+    fn check_preset_name(&self, name: &str, id: Option<u32>) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Preset name must not be empty".to_string());
+        }
+        let taken = self.presets.iter().any(|preset| {
+            Some(preset.id) != id && preset.name.to_lowercase() == name.to_lowercase()
+        });
+        if taken {
+            return Err(format!("A preset named \"{name}\" already exists"));
+        }
+        Ok(name.to_string())
+    }
+
+    fn create_preset(
+        &mut self,
+        name: &str,
+        session: LastSessionConfig,
+        now: DateTime<Utc>,
+    ) -> Result<SessionPreset, String> {
+        let name = self.check_preset_name(name, None)?;
+        // Also past the highest id in use, should the counter ever lag behind
+        let id = self
+            .presets
+            .iter()
+            .map(|preset| preset.id + 1)
+            .fold(self.next_preset_id, u32::max);
+        self.next_preset_id = id + 1;
+        let preset = SessionPreset {
+            id,
+            name,
+            session,
+            use_count: 0,
+            last_used_at: None,
+            created_at: timestamp(now),
+        };
+        self.presets.push(preset.clone());
+        Ok(preset)
+    }
+
+    fn rename_preset(&mut self, id: u32, name: &str) -> Result<SessionPreset, String> {
+        let name = self.check_preset_name(name, Some(id))?;
+        let preset = self.preset_mut(id)?;
+        preset.name = name;
+        Ok(preset.clone())
+    }
+
+    fn delete_preset(&mut self, id: u32) -> Result<(), String> {
+        let before = self.presets.len();
+        self.presets.retain(|preset| preset.id != id);
+        if self.presets.len() == before {
+            return Err(format!("Unknown session preset: {id}"));
+        }
+        Ok(())
+    }
+
+    /// Count a use of the preset and make it the last session.
+    fn apply_preset(&mut self, id: u32, now: DateTime<Utc>) -> Result<SessionPreset, String> {
+        let preset = self.preset_mut(id)?;
+        preset.use_count = preset.use_count.saturating_add(1);
+        preset.last_used_at = Some(timestamp(now));
+        let preset = preset.clone();
+        let session = LastSessionConfig {
+            saved_at: timestamp(now),
+            ..preset.session.clone()
+        };
+        self.last_session = Some(session.clone());
+        self.record_session(session);
+        Ok(preset)
+    }
+
+    /// Add a saved session to the history used for suggestions.
+    fn record_session(&mut self, session: LastSessionConfig) {
+        self.session_history.push(session);
+        let excess = self
+            .session_history
+            .len()
+            .saturating_sub(MAX_SESSION_HISTORY);
+        self.session_history.drain(..excess);
+    }
+
+    /// Rank the activity/room/supervisor combinations in the history for `now`.
+    ///
+    /// Each past session adds to its combination's score, most when it was saved on the
+    /// same weekday within `TIME_SLOT_MINUTES` of the time of day, and less the older it is.
+    fn suggest_sessions(&self, now: DateTime<Tz>, limit: usize) -> Vec<SessionSuggestion> {
+        let mut candidates: BTreeMap<(i32, i32, Vec<i32>), SessionSuggestion> = BTreeMap::new();
+        let now_minutes = i64::from(now.hour() * 60 + now.minute());
+
+        for session in &self.session_history {
+            let Ok(saved_at) = DateTime::parse_from_rfc3339(&session.saved_at) else {
+                continue;
+            };
+            let saved_at = saved_at.with_timezone(&now.timezone());
+            let minutes = i64::from(saved_at.hour() * 60 + saved_at.minute());
+            let same_weekday = saved_at.weekday() == now.weekday();
+            // 23:50 and 00:10 are 20 minutes apart, not 1420
+            let distance = (minutes - now_minutes).abs();
+            let same_time = distance.min(MINUTES_PER_DAY - distance) <= TIME_SLOT_MINUTES;
+
+            let weight = match (same_weekday, same_time) {
+                (true, true) => 4.0,
+                (false, true) => 1.5,
+                (true, false) => 1.0,
+                (false, false) => 0.25,
+            };
+            let age_days = i32::try_from((now - saved_at).num_days().max(0)).unwrap_or(i32::MAX);
+            let score = weight * 0.5_f64.powf(f64::from(age_days) / HISTORY_HALF_LIFE_DAYS);
+
+            let mut supervisor_ids = session.supervisor_ids.clone();
+            supervisor_ids.sort_unstable();
+            let candidate = candidates
+                .entry((session.activity_id, session.room_id, supervisor_ids))
+                .or_insert_with(|| SessionSuggestion {
+                    session: session.clone(),
+                    score: 0.0,
+                    times_used: 0,
+                    matches_time_slot: false,
+                });
+            candidate.score += score;
+            candidate.times_used += 1;
+            candidate.matches_time_slot |= same_weekday && same_time;
+            // History is oldest first, so the last one seen is the most recent
+            candidate.session = session.clone();
+        }
+
+        let mut suggestions: Vec<SessionSuggestion> = candidates.into_values().collect();
+        suggestions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.session.saved_at.cmp(&a.session.saved_at))
+        });
+        suggestions.truncate(limit);
+        suggestions
+    }
+}
+
+/// Load the settings in `settings_path` (or defaults), change them with `f` and save them.
+///
+/// Nothing is saved if the stored settings can't be read, so they are never replaced
+/// by defaults.
+fn update_settings_in<T>(
+    settings_path: &Path,
+    f: impl FnOnce(&mut SessionSettings) -> Result<T, String>,
+) -> Result<T, String> {
+    let _lock = lock_settings(settings_path)?;
+    let mut settings = load_settings_file(settings_path)?.unwrap_or_default();
+    let result = f(&mut settings)?;
+    write_settings_file(settings_path, &settings)?;
+    Ok(result)
+}
+
+/// List session presets, favourites (most used, then most recently used) first
+#[tauri::command]
+pub async fn list_session_presets<R: Runtime>(
+    app_handle: AppHandle<R>,
+) -> Result<Vec<SessionPreset>, String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| read_settings_file(&path))
+        .map(|settings| settings.unwrap_or_default().favourite_presets())
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to list session presets"))
+}
+
+#[tauri::command]
+pub async fn create_session_preset<R: Runtime>(
+    app_handle: AppHandle<R>,
+    name: String,
+    session: LastSessionConfig,
+) -> Result<SessionPreset, String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| {
+            update_settings_in(&path, |settings| {
+                settings.create_preset(&name, session, Utc::now())
+            })
+        })
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to create session preset"))
+}
+
+#[tauri::command]
+pub async fn rename_session_preset<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: u32,
+    name: String,
+) -> Result<SessionPreset, String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| update_settings_in(&path, |settings| settings.rename_preset(id, &name)))
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to rename session preset"))
+}
+
+#[tauri::command]
+pub async fn delete_session_preset<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: u32,
+) -> Result<(), String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| update_settings_in(&path, |settings| settings.delete_preset(id)))
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to delete session preset"))
+}
+
+/// Suggest the most likely sessions for the current local weekday and time
+#[tauri::command]
+pub async fn suggest_sessions<R: Runtime>(
+    app_handle: AppHandle<R>,
+    limit: Option<usize>,
+) -> Result<Vec<SessionSuggestion>, String> {
+    let limit = limit.unwrap_or(DEFAULT_SUGGESTIONS).min(MAX_SUGGESTIONS);
+    get_session_settings_path(&app_handle)
+        .and_then(|path| read_settings_file(&path))
+        .map(|settings| {
+            settings
+                .unwrap_or_default()
+                .suggest_sessions(local_now(), limit)
+        })
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to suggest sessions"))
+}
+
+/// Make a preset the last session and count its use; returns the updated preset
+#[tauri::command]
+pub async fn apply_session_preset<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: u32,
+) -> Result<SessionPreset, String> {
+    get_session_settings_path(&app_handle)
+        .and_then(|path| {
+            update_settings_in(&path, |settings| settings.apply_preset(id, Utc::now()))
+        })
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to apply session preset"))
 }
 
 #[cfg(test)]
+// `APP_DIR_LOCK` is held across awaits on purpose: it only serializes tests, each of
+// which runs on its own runtime
+#[allow(clippy::await_holding_lock)]
 mod tests {
     use super::*;
     use std::sync::Mutex;
@@ -118,6 +938,7 @@ mod tests {
         let handle = app.handle().clone();
         // Clean session file from previous test
         if let Ok(path) = get_session_settings_path(&handle) {
+            let _ = fs::remove_file(with_extension_appended(&path, BACKUP_EXTENSION));
             let _ = fs::remove_file(&path);
         }
         (handle, guard)
@@ -137,9 +958,13 @@ mod tests {
 
     fn sample_settings() -> SessionSettings {
         SessionSettings {
+            schema_version: SESSION_SCHEMA_VERSION,
             use_last_session: true,
             auto_save_enabled: true,
             last_session: Some(sample_last_session()),
+            presets: Vec::new(),
+            session_history: Vec::new(),
+            next_preset_id: 1,
         }
     }
 
@@ -165,9 +990,13 @@ mod tests {
     #[test]
     fn session_settings_without_last_session() {
         let settings = SessionSettings {
+            schema_version: SESSION_SCHEMA_VERSION,
             use_last_session: false,
             auto_save_enabled: true,
             last_session: None,
+            presets: Vec::new(),
+            session_history: Vec::new(),
+            next_preset_id: 1,
         };
         let d: SessionSettings =
             serde_json::from_str(&serde_json::to_string(&settings).unwrap()).unwrap();
@@ -217,7 +1046,8 @@ mod tests {
     #[tokio::test]
     async fn save_and_load_session_settings_roundtrip() {
         let (handle, _guard) = mock_app_handle();
-        let settings = sample_settings();
+        let mut settings = sample_settings();
+        settings.last_session = Some(saved(42, 7, &timestamp(Utc::now())));
 
         save_session_settings(handle.clone(), settings)
             .await
@@ -226,6 +1056,8 @@ mod tests {
         let loaded = load_session_settings(handle).await.unwrap();
         assert!(loaded.is_some());
         let loaded = loaded.unwrap();
+        assert_eq!(loaded.last_session_status, Some(SessionFreshness::Fresh));
+        let loaded = loaded.settings;
         assert!(loaded.use_last_session);
         assert!(loaded.auto_save_enabled);
         assert_eq!(loaded.last_session.unwrap().activity_id, 42);
@@ -250,15 +1082,23 @@ mod tests {
 
         // Save different settings
         let new_settings = SessionSettings {
+            schema_version: SESSION_SCHEMA_VERSION,
             use_last_session: false,
             auto_save_enabled: false,
             last_session: None,
+            presets: Vec::new(),
+            session_history: Vec::new(),
+            next_preset_id: 1,
         };
         save_session_settings(handle.clone(), new_settings)
             .await
             .unwrap();
 
-        let loaded = load_session_settings(handle).await.unwrap().unwrap();
+        let loaded = load_session_settings(handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .settings;
         assert!(!loaded.use_last_session);
         assert!(!loaded.auto_save_enabled);
         assert!(loaded.last_session.is_none());
@@ -277,7 +1117,11 @@ mod tests {
         clear_last_session(handle.clone()).await.unwrap();
 
         // Verify
-        let loaded = load_session_settings(handle).await.unwrap().unwrap();
+        let loaded = load_session_settings(handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .settings;
         assert!(!loaded.use_last_session);
         assert!(loaded.last_session.is_none());
         assert!(loaded.auto_save_enabled); // Preserved
@@ -295,17 +1139,716 @@ mod tests {
         let (handle, _guard) = mock_app_handle();
 
         let settings = SessionSettings {
+            schema_version: SESSION_SCHEMA_VERSION,
             use_last_session: true,
             auto_save_enabled: true,
             last_session: Some(sample_last_session()),
+            presets: Vec::new(),
+            session_history: Vec::new(),
+            next_preset_id: 1,
         };
         save_session_settings(handle.clone(), settings)
             .await
             .unwrap();
         clear_last_session(handle.clone()).await.unwrap();
 
-        let loaded = load_session_settings(handle).await.unwrap().unwrap();
+        let loaded = load_session_settings(handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .settings;
         assert!(loaded.auto_save_enabled);
         assert!(!loaded.use_last_session);
     }
+
+    // ====================================================================
+    // Crash-safe write tests (using tempdir, no AppHandle)
+    // ====================================================================
+
+    #[test]
+    fn write_keeps_previous_version_as_backup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+
+        write_settings_file(&path, &sample_settings()).unwrap();
+        let mut updated = sample_settings();
+        updated.use_last_session = false;
+        write_settings_file(&path, &updated).unwrap();
+
+        let backup =
+            parse_settings_file(&with_extension_appended(&path, BACKUP_EXTENSION)).unwrap();
+        assert!(backup.use_last_session);
+        assert!(!read_settings_file(&path).unwrap().unwrap().use_last_session);
+        // No temp files are left behind, only the key and the lock file
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 4);
+    }
+
+    #[test]
+    fn truncated_file_is_restored_from_backup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        write_settings_file(&path, &sample_settings()).unwrap();
+        write_settings_file(&path, &sample_settings()).unwrap();
+
+        // Simulate a write torn by a power cut
+        let json = fs::read(&path).unwrap();
+        fs::write(&path, &json[..json.len() / 2]).unwrap();
+
+        let loaded = read_settings_file(&path).unwrap().unwrap();
+        assert!(loaded.use_last_session);
+        assert_eq!(loaded.last_session.unwrap().activity_id, 42);
+        // The primary file is usable again
+        assert!(parse_settings_file(&path).is_ok());
+    }
+
+    #[test]
+    fn corrupt_file_does_not_replace_backup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        write_settings_file(&path, &sample_settings()).unwrap();
+        write_settings_file(&path, &sample_settings()).unwrap();
+
+        fs::write(&path, "{\"use_last_se").unwrap();
+        let mut updated = sample_settings();
+        updated.use_last_session = false;
+        write_settings_file(&path, &updated).unwrap();
+
+        let backup =
+            parse_settings_file(&with_extension_appended(&path, BACKUP_EXTENSION)).unwrap();
+        assert!(backup.use_last_session);
+    }
+
+    #[test]
+    fn corrupt_file_without_backup_is_an_error() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        fs::write(&path, "{").unwrap();
+
+        let err = read_settings_file(&path).unwrap_err();
+        assert!(err.contains("Failed to parse session settings"), "{err}");
+
+        // Changes aren't saved over it with defaults
+        assert!(update_settings_in(&path, |settings| {
+            settings.use_last_session = true;
+            Ok(())
+        })
+        .is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{");
+    }
+
+    #[test]
+    fn concurrent_updates_are_all_kept() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    update_settings_in(path, |settings| {
+                        settings.create_preset(&format!("Gruppe {i}"), sample_last_session(), at(9))
+                    })
+                    .unwrap();
+                });
+            }
+        });
+
+        let settings = read_settings_file(&path).unwrap().unwrap();
+        assert_eq!(settings.presets.len(), 8);
+    }
+
+    #[test]
+    fn leftover_temp_files_are_removed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        write_settings_file(&path, &sample_settings()).unwrap();
+        let leftovers = [
+            with_extension_appended(&path, ".4242-0.tmp"),
+            with_extension_appended(&path, ".bak.4242-1.tmp"),
+        ];
+        for leftover in &leftovers {
+            fs::write(leftover, "{").unwrap();
+        }
+        let unrelated = tmp.path().join("other.4242-0.tmp");
+        fs::write(&unrelated, "").unwrap();
+
+        remove_leftover_temp_files(&path);
+
+        assert!(leftovers.iter().all(|leftover| !leftover.exists()));
+        assert!(unrelated.exists());
+        assert!(read_settings_file(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn updates_wait_for_the_lock_of_another_process() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        // Stands in for `pyreportal admin` holding the lock
+        let held = File::create(with_extension_appended(&path, LOCK_EXTENSION)).unwrap();
+        held.lock_exclusive().unwrap();
+
+        let updater = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                update_settings_in(&path, |settings| {
+                    settings.use_last_session = true;
+                    Ok(())
+                })
+            })
+        };
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!updater.is_finished());
+        assert!(!path.exists());
+
+        drop(held);
+        updater.join().unwrap().unwrap();
+        assert!(read_settings_file(&path).unwrap().unwrap().use_last_session);
+    }
+
+    // ====================================================================
+    // Schema migration tests (fixtures of every stored shape)
+    // ====================================================================
+
+    fn migrate_fixture(json: &str) -> Result<SessionSettings, ParseError> {
+        migrate_settings(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn migrations_cover_every_schema_version() {
+        assert_eq!(MIGRATIONS.len(), SESSION_SCHEMA_VERSION as usize);
+    }
+
+    #[test]
+    fn v0_file_is_migrated() {
+        let settings =
+            migrate_fixture(include_str!("../fixtures/session-settings/v0.json")).unwrap();
+        assert_eq!(settings.schema_version, SESSION_SCHEMA_VERSION);
+        assert!(settings.use_last_session);
+        let session = settings.last_session.unwrap();
+        assert_eq!(session.activity_id, 42);
+        assert_eq!(session.supervisor_names, ["Herr Müller", "Frau Schmidt"]);
+        // The last session seeds the history
+        assert_eq!(settings.session_history.len(), 1);
+    }
+
+    #[test]
+    fn v0_file_without_last_session_is_migrated() {
+        let settings = migrate_fixture(include_str!(
+            "../fixtures/session-settings/v0-without-last-session.json"
+        ))
+        .unwrap();
+        assert_eq!(settings.schema_version, SESSION_SCHEMA_VERSION);
+        assert!(!settings.use_last_session);
+        assert!(settings.last_session.is_none());
+    }
+
+    #[test]
+    fn v1_file_is_migrated() {
+        let settings =
+            migrate_fixture(include_str!("../fixtures/session-settings/v1.json")).unwrap();
+        assert_eq!(settings.schema_version, SESSION_SCHEMA_VERSION);
+        assert_eq!(settings.last_session.unwrap().room_name, "Turnhalle");
+        assert!(settings.presets.is_empty());
+    }
+
+    #[test]
+    fn v2_file_is_migrated() {
+        let settings =
+            migrate_fixture(include_str!("../fixtures/session-settings/v2.json")).unwrap();
+        assert_eq!(settings.schema_version, SESSION_SCHEMA_VERSION);
+        assert!(settings.session_history.is_empty());
+        let [preset] = settings.presets.as_slice() else {
+            panic!("expected one preset, got {:?}", settings.presets);
+        };
+        assert_eq!(preset.name, "Montag Fußball");
+        assert_eq!(preset.use_count, 3);
+        assert_eq!(preset.session.activity_id, 42);
+        assert_eq!(settings.next_preset_id, preset.id + 1);
+    }
+
+    #[test]
+    fn v3_file_is_migrated() {
+        let settings =
+            migrate_fixture(include_str!("../fixtures/session-settings/v3.json")).unwrap();
+        assert_eq!(settings.schema_version, SESSION_SCHEMA_VERSION);
+        assert_eq!(settings.session_history.len(), 2);
+        assert_eq!(settings.next_preset_id, 1);
+    }
+
+    #[test]
+    fn v4_file_is_loaded() {
+        let settings =
+            migrate_fixture(include_str!("../fixtures/session-settings/v4.json")).unwrap();
+        assert_eq!(settings.schema_version, 4);
+        assert_eq!(settings.presets[0].id, 3);
+        assert_eq!(settings.next_preset_id, 5);
+    }
+
+    #[test]
+    fn newer_file_is_read_as_far_as_understood() {
+        let settings = migrate_fixture(include_str!(
+            "../fixtures/session-settings/future-compatible.json"
+        ))
+        .unwrap();
+        assert_eq!(settings.schema_version, 99);
+        assert!(settings.use_last_session);
+    }
+
+    #[test]
+    fn unreadable_newer_file_is_left_alone() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        let future = include_str!("../fixtures/session-settings/future-incompatible.json");
+        fs::write(&path, future).unwrap();
+
+        let err = read_settings_file(&path).unwrap_err();
+        assert!(err.contains("newer app version"), "{err}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), future);
+
+        // Saving doesn't replace it either
+        let err = write_settings_file(&path, &sample_settings()).unwrap_err();
+        assert!(err.contains("newer app version"), "{err}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), future);
+        assert!(!with_extension_appended(&path, BACKUP_EXTENSION).exists());
+    }
+
+    #[test]
+    fn readable_newer_file_is_not_overwritten() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        let future = include_str!("../fixtures/session-settings/future-compatible.json");
+        fs::write(&path, future).unwrap();
+        let backup_path = with_extension_appended(&path, BACKUP_EXTENSION);
+        fs::write(&backup_path, future).unwrap();
+
+        assert!(read_settings_file(&path).unwrap().unwrap().use_last_session);
+        assert!(update_settings_in(&path, |settings| {
+            settings.create_preset("Gruppe A", sample_last_session(), at(9))
+        })
+        .unwrap_err()
+        .contains("newer app version"));
+        assert!(clear_last_session_in(&path).is_err());
+
+        // Schema version and unknown fields survive, in the file and its backup
+        for path in [&path, &backup_path] {
+            let json: Value =
+                serde_json::from_slice(&read_settings_plaintext(path).unwrap()).unwrap();
+            assert_eq!(json["schema_version"], 99);
+            assert_eq!(json["kiosk_theme"], "dark");
+        }
+    }
+
+    #[test]
+    fn invalid_schema_version_is_corrupt() {
+        let result = migrate_fixture(r#"{"schema_version":"one","use_last_session":true}"#);
+        assert!(matches!(result, Err(ParseError::Corrupt(_))));
+    }
+
+    #[test]
+    fn written_file_has_current_schema_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        let settings = SessionSettings {
+            schema_version: 0,
+            ..sample_settings()
+        };
+        write_settings_file(&path, &settings).unwrap();
+
+        let json: Value = serde_json::from_slice(&read_settings_plaintext(&path).unwrap()).unwrap();
+        assert_eq!(json["schema_version"], SESSION_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn settings_from_frontend_default_to_current_schema_version() {
+        let settings: SessionSettings = serde_json::from_str(
+            r#"{"use_last_session":true,"auto_save_enabled":true,"last_session":null}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.schema_version, SESSION_SCHEMA_VERSION);
+    }
+
+    // ====================================================================
+    // Session preset tests
+    // ====================================================================
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        format!("2024-06-17T{hour:02}:00:00Z").parse().unwrap()
+    }
+
+    #[test]
+    fn create_preset_assigns_ids_and_rejects_duplicate_names() {
+        let mut settings = SessionSettings::default();
+        let first = settings
+            .create_preset(" Montag Fußball ", sample_last_session(), at(8))
+            .unwrap();
+        assert_eq!(first.id, 1);
+        assert_eq!(first.name, "Montag Fußball");
+        assert_eq!(first.use_count, 0);
+        assert_eq!(first.created_at, "2024-06-17T08:00:00.000Z");
+
+        let second = settings
+            .create_preset("Dienstag Kunst", sample_last_session(), at(8))
+            .unwrap();
+        assert_eq!(second.id, 2);
+
+        assert!(settings
+            .create_preset("montag fußball", sample_last_session(), at(8))
+            .is_err());
+        assert!(settings
+            .create_preset("  ", sample_last_session(), at(8))
+            .is_err());
+    }
+
+    #[test]
+    fn rename_and_delete_preset() {
+        let mut settings = SessionSettings::default();
+        let a = settings
+            .create_preset("A", sample_last_session(), at(8))
+            .unwrap();
+        let b = settings
+            .create_preset("B", sample_last_session(), at(8))
+            .unwrap();
+
+        assert_eq!(settings.rename_preset(a.id, "a").unwrap().name, "a");
+        assert!(settings.rename_preset(a.id, "B").is_err());
+        assert!(settings.rename_preset(99, "C").is_err());
+
+        settings.delete_preset(b.id).unwrap();
+        assert!(settings.delete_preset(b.id).is_err());
+        assert_eq!(settings.presets.len(), 1);
+    }
+
+    #[test]
+    fn ids_of_deleted_presets_are_not_reused() {
+        let mut settings = SessionSettings::default();
+        settings
+            .create_preset("A", sample_last_session(), at(8))
+            .unwrap();
+        let b = settings
+            .create_preset("B", sample_last_session(), at(8))
+            .unwrap();
+        settings.delete_preset(b.id).unwrap();
+
+        let c = settings
+            .create_preset("C", sample_last_session(), at(8))
+            .unwrap();
+        assert_eq!(c.id, b.id + 1);
+    }
+
+    #[test]
+    fn apply_preset_counts_use_and_sets_last_session() {
+        let mut settings = SessionSettings::default();
+        let mut session = sample_last_session();
+        session.room_id = 9;
+        let preset = settings.create_preset("Werkraum", session, at(8)).unwrap();
+
+        let applied = settings.apply_preset(preset.id, at(10)).unwrap();
+        assert_eq!(applied.use_count, 1);
+        assert_eq!(
+            applied.last_used_at.as_deref(),
+            Some("2024-06-17T10:00:00.000Z")
+        );
+
+        let last = settings.last_session.unwrap();
+        assert_eq!(last.room_id, 9);
+        assert_eq!(last.saved_at, "2024-06-17T10:00:00.000Z");
+    }
+
+    #[test]
+    fn favourites_are_sorted_by_use_then_recency() {
+        let mut settings = SessionSettings::default();
+        for name in ["Selten", "Oft", "Neulich"] {
+            settings
+                .create_preset(name, sample_last_session(), at(8))
+                .unwrap();
+        }
+        settings.apply_preset(2, at(9)).unwrap();
+        settings.apply_preset(2, at(10)).unwrap();
+        settings.apply_preset(1, at(11)).unwrap();
+        settings.apply_preset(3, at(12)).unwrap();
+
+        let names: Vec<String> = settings
+            .favourite_presets()
+            .into_iter()
+            .map(|preset| preset.name)
+            .collect();
+        assert_eq!(names, ["Oft", "Neulich", "Selten"]);
+    }
+
+    #[tokio::test]
+    async fn saving_settings_keeps_presets() {
+        let (handle, _guard) = mock_app_handle();
+
+        let preset = create_session_preset(
+            handle.clone(),
+            "Montag Fußball".to_string(),
+            sample_last_session(),
+        )
+        .await
+        .unwrap();
+        save_session_settings(handle.clone(), sample_settings())
+            .await
+            .unwrap();
+        apply_session_preset(handle.clone(), preset.id)
+            .await
+            .unwrap();
+
+        let presets = list_session_presets(handle).await.unwrap();
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].use_count, 1);
+    }
+
+    // ====================================================================
+    // Session history and suggestion tests
+    // ====================================================================
+
+    fn saved(activity_id: i32, room_id: i32, saved_at: &str) -> LastSessionConfig {
+        LastSessionConfig {
+            activity_id,
+            room_id,
+            saved_at: saved_at.to_string(),
+            ..sample_last_session()
+        }
+    }
+
+    fn berlin(at: &str) -> DateTime<Tz> {
+        at.parse::<DateTime<Utc>>()
+            .unwrap()
+            .with_timezone(&chrono_tz::Europe::Berlin)
+    }
+
+    #[test]
+    fn suggestions_prefer_same_weekday_and_time() {
+        let mut settings = SessionSettings::default();
+        // Football on Mondays at 14:00 Berlin time
+        for day in ["2024-06-03", "2024-06-10"] {
+            settings.record_session(saved(42, 7, &format!("{day}T12:00:00Z")));
+        }
+        // Arts more often, but on Tuesday mornings
+        for day in ["2024-06-04", "2024-06-11", "2024-06-11", "2024-06-11"] {
+            settings.record_session(saved(5, 3, &format!("{day}T08:00:00Z")));
+        }
+
+        // Monday 14:10 in Berlin
+        let suggestions = settings.suggest_sessions(berlin("2024-06-17T12:10:00Z"), 3);
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[0].session.activity_id, 42);
+        assert_eq!(suggestions[0].times_used, 2);
+        assert!(suggestions[0].matches_time_slot);
+        assert_eq!(suggestions[1].session.activity_id, 5);
+        assert!(!suggestions[1].matches_time_slot);
+
+        // Tuesday 10:00 in Berlin
+        let suggestions = settings.suggest_sessions(berlin("2024-06-18T08:00:00Z"), 1);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].session.activity_id, 5);
+    }
+
+    #[test]
+    fn time_slot_wraps_around_midnight() {
+        let mut settings = SessionSettings::default();
+        // Late club on Monday at 23:50 Berlin time
+        settings.record_session(saved(42, 7, "2024-06-10T21:50:00Z"));
+        // Arts on Tuesday at noon
+        settings.record_session(saved(5, 3, "2024-06-11T10:00:00Z"));
+
+        // Tuesday 00:20 in Berlin
+        let suggestions = settings.suggest_sessions(berlin("2024-06-17T22:20:00Z"), 2);
+        assert_eq!(suggestions[0].session.activity_id, 42);
+        assert_eq!(suggestions[1].session.activity_id, 5);
+    }
+
+    #[test]
+    fn suggestions_group_supervisors_in_any_order() {
+        let mut settings = SessionSettings::default();
+        let mut first = saved(42, 7, "2024-06-10T12:00:00Z");
+        first.supervisor_ids = vec![3, 1];
+        let mut second = saved(42, 7, "2024-06-03T12:00:00Z");
+        second.supervisor_ids = vec![1, 3];
+        second.room_name = "Neue Turnhalle".to_string();
+        settings.record_session(first);
+        settings.record_session(second);
+        settings.record_session(saved(42, 7, "not a timestamp"));
+
+        let suggestions = settings.suggest_sessions(berlin("2024-06-17T12:00:00Z"), 3);
+        let [suggestion] = suggestions.as_slice() else {
+            panic!("expected one suggestion, got {suggestions:?}");
+        };
+        assert_eq!(suggestion.times_used, 2);
+        assert_eq!(suggestion.session.room_name, "Neue Turnhalle");
+    }
+
+    #[test]
+    fn history_keeps_newest_sessions() {
+        let mut settings = SessionSettings::default();
+        for i in 0..=MAX_SESSION_HISTORY {
+            settings.record_session(saved(i32::try_from(i).unwrap(), 1, "2024-06-17T12:00:00Z"));
+        }
+        assert_eq!(settings.session_history.len(), MAX_SESSION_HISTORY);
+        assert_eq!(settings.session_history[0].activity_id, 1);
+    }
+
+    #[tokio::test]
+    async fn saving_a_new_session_records_it_once() {
+        let (handle, _guard) = mock_app_handle();
+
+        // Saving the same session again (e.g. toggling) doesn't count twice
+        save_session_settings(handle.clone(), sample_settings())
+            .await
+            .unwrap();
+        save_session_settings(handle.clone(), sample_settings())
+            .await
+            .unwrap();
+        let mut next = sample_settings();
+        next.last_session = Some(saved(42, 7, "2024-06-22T10:30:00Z"));
+        save_session_settings(handle.clone(), next).await.unwrap();
+
+        let loaded = load_session_settings(handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .settings;
+        assert_eq!(loaded.session_history.len(), 2);
+    }
+
+    // ====================================================================
+    // Last session staleness tests
+    // ====================================================================
+
+    #[test]
+    fn last_session_ages_from_fresh_to_expired() {
+        let limits = SessionAgeLimits::default();
+        let saved_at = "2024-06-14T12:00:00Z";
+        let after = |days: i64| saved_at.parse::<DateTime<Utc>>().unwrap() + Duration::days(days);
+
+        assert_eq!(limits.classify(saved_at, after(0)), SessionFreshness::Fresh);
+        assert_eq!(limits.classify(saved_at, after(7)), SessionFreshness::Fresh);
+        assert_eq!(limits.classify(saved_at, after(8)), SessionFreshness::Stale);
+        assert_eq!(
+            limits.classify(saved_at, after(28)),
+            SessionFreshness::Stale
+        );
+        // After the summer holidays
+        assert_eq!(
+            limits.classify(saved_at, after(45)),
+            SessionFreshness::Expired
+        );
+        // Clock set back
+        assert_eq!(
+            limits.classify(saved_at, after(-1)),
+            SessionFreshness::Fresh
+        );
+    }
+
+    #[test]
+    fn stale_limit_is_kept_below_max_age() {
+        let limits = SessionAgeLimits::new(Duration::days(30), Duration::days(14));
+        assert_eq!(limits.stale_after, Duration::days(14));
+        assert_eq!(limits.max_age, Duration::days(14));
+
+        let saved_at = "2024-06-14T12:00:00Z";
+        let after = |days: i64| saved_at.parse::<DateTime<Utc>>().unwrap() + Duration::days(days);
+        assert_eq!(
+            limits.classify(saved_at, after(14)),
+            SessionFreshness::Fresh
+        );
+        assert_eq!(
+            limits.classify(saved_at, after(15)),
+            SessionFreshness::Expired
+        );
+
+        let limits = SessionAgeLimits::new(Duration::days(3), Duration::days(14));
+        assert_eq!(limits.stale_after, Duration::days(3));
+    }
+
+    #[test]
+    fn unreadable_saved_at_is_expired() {
+        let limits = SessionAgeLimits::default();
+        assert_eq!(
+            limits.classify("15.06.2024", Utc::now()),
+            SessionFreshness::Expired
+        );
+    }
+
+    #[test]
+    fn expired_last_session_is_withheld() {
+        let now = "2024-09-02T06:00:00Z".parse().unwrap();
+        let loaded =
+            LoadedSessionSettings::new(sample_settings(), SessionAgeLimits::default(), now);
+        assert_eq!(loaded.last_session_status, Some(SessionFreshness::Expired));
+        assert!(loaded.settings.last_session.is_none());
+
+        let json = serde_json::to_value(&loaded).unwrap();
+        assert_eq!(json["last_session_status"], "expired");
+        assert_eq!(json["use_last_session"], true);
+    }
+
+    #[test]
+    fn stale_last_session_is_returned_with_status() {
+        let now = "2024-06-25T06:00:00Z".parse().unwrap();
+        let loaded =
+            LoadedSessionSettings::new(sample_settings(), SessionAgeLimits::default(), now);
+        assert_eq!(loaded.last_session_status, Some(SessionFreshness::Stale));
+        assert!(loaded.settings.last_session.is_some());
+
+        let without_session = SessionSettings::default();
+        let loaded = LoadedSessionSettings::new(without_session, SessionAgeLimits::default(), now);
+        assert_eq!(loaded.last_session_status, None);
+    }
+
+    // ====================================================================
+    // Encryption at rest tests
+    // ====================================================================
+
+    #[test]
+    fn settings_file_is_encrypted() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        write_settings_file(&path, &sample_settings()).unwrap();
+        write_settings_file(&path, &sample_settings()).unwrap();
+
+        for file in [&path, &with_extension_appended(&path, BACKUP_EXTENSION)] {
+            let raw = fs::read(file).unwrap();
+            assert!(encrypted_storage::is_sealed(&raw));
+            assert!(!String::from_utf8_lossy(&raw).contains("Turnhalle"));
+        }
+        let loaded = read_settings_file(&path).unwrap().unwrap();
+        assert_eq!(loaded.last_session.unwrap().room_name, "Turnhalle");
+    }
+
+    #[test]
+    fn plaintext_files_are_encrypted_at_startup_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        let backup_path = with_extension_appended(&path, BACKUP_EXTENSION);
+        let v0 = include_str!("../fixtures/session-settings/v0.json");
+        fs::write(&path, v0).unwrap();
+        fs::write(&backup_path, v0).unwrap();
+
+        let loaded = read_settings_file(&path).unwrap().unwrap();
+        assert_eq!(loaded.last_session.unwrap().activity_id, 42);
+        assert_eq!(fs::read_to_string(&path).unwrap(), v0);
+
+        seal_plaintext_files(&path);
+        for file in [&path, &backup_path] {
+            assert!(encrypted_storage::is_sealed(&fs::read(file).unwrap()));
+            assert_eq!(read_settings_plaintext(file).unwrap(), v0.as_bytes());
+        }
+        assert!(read_settings_file(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn settings_sealed_on_another_device_are_not_readable() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SESSION_SETTINGS_FILE);
+        write_settings_file(&path, &sample_settings()).unwrap();
+
+        let other = tempfile::tempdir().unwrap();
+        let copied = other.path().join(SESSION_SETTINGS_FILE);
+        fs::copy(&path, &copied).unwrap();
+        encrypted_storage::seal(other.path(), "other", b"").unwrap();
+
+        let err = read_settings_file(&copied).unwrap_err();
+        assert!(err.contains("Failed to decrypt"), "{err}");
+    }
 }
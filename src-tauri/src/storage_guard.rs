@@ -0,0 +1,513 @@
+use crate::logging::{env_or, get_log_directory, prune_logs_for_space, LogLevel};
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+/// Free space is measured at most this often
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Event emitted whenever the storage state changes
+const STORAGE_EVENT: &str = "storage-status";
+
+/// How much free space is left in the app data directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageState {
+    Ok,
+    /// Below `STORAGE_LOW_BYTES`: DEBUG entries are no longer persisted
+    StorageLow,
+    /// Below `STORAGE_CRITICAL_BYTES` or a write hit a full disk: only WARN and ERROR
+    /// entries are persisted
+    StorageCritical,
+}
+
+/// Free-space limits, read from `STORAGE_LOW_BYTES` and `STORAGE_CRITICAL_BYTES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StorageThresholds {
+    pub low_bytes: u64,
+    pub critical_bytes: u64,
+}
+
+impl Default for StorageThresholds {
+    fn default() -> Self {
+        Self {
+            low_bytes: 200 * 1024 * 1024,
+            critical_bytes: 50 * 1024 * 1024,
+        }
+    }
+}
+
+impl StorageThresholds {
+    pub(crate) fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            low_bytes: env_or("STORAGE_LOW_BYTES", defaults.low_bytes),
+            critical_bytes: env_or("STORAGE_CRITICAL_BYTES", defaults.critical_bytes),
+        }
+    }
+
+    fn classify(self, available: u64) -> StorageState {
+        if available < self.critical_bytes {
+            StorageState::StorageCritical
+        } else if available < self.low_bytes {
+            StorageState::StorageLow
+        } else {
+            StorageState::Ok
+        }
+    }
+}
+
+/// Returned by `get_storage_status` and emitted as `storage-status` on every change.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageStatus {
+    pub state: StorageState,
+    /// Whether the UI should warn about low storage (any state but `ok`)
+    pub storage_low: bool,
+    /// `None` until the first measurement or if the file system can't be queried
+    pub available_bytes: Option<u64>,
+    pub low_threshold_bytes: u64,
+    /// Why the last background log write failed; `None` once a write succeeds again
+    pub last_write_error: Option<String>,
+}
+
+/// Measures the free bytes of the file system holding a directory
+type SpaceSource = Box<dyn Fn(&Path) -> io::Result<u64> + Send + Sync>;
+
+struct Guard {
+    data_dir: PathBuf,
+    log_dir: PathBuf,
+    thresholds: StorageThresholds,
+    available_space: SpaceSource,
+    notify: Box<dyn Fn(StorageStatus) + Send + Sync>,
+    measurement: Mutex<Measurement>,
+}
+
+#[derive(Default)]
+struct Measurement {
+    checked_at: Option<Instant>,
+    state: Option<StorageState>,
+    available_bytes: Option<u64>,
+    write_error: Option<String>,
+}
+
+/// Set up once the app knows its data directory; without it every write is allowed.
+static GUARD: OnceLock<Guard> = OnceLock::new();
+
+/// Start guarding writes to the app data directory (called at startup).
+pub fn init_storage_guard<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {e}"))?;
+    let log_dir = get_log_directory(app).map_err(|e| e.to_string())?;
+    let app = app.clone();
+    let guard = GUARD.get_or_init(|| {
+        Guard::new(
+            data_dir,
+            log_dir,
+            StorageThresholds::from_env(),
+            |dir| fs4::available_space(dir),
+            move |status| {
+                if let Err(e) = app.emit(STORAGE_EVENT, status) {
+                    eprintln!("Failed to emit storage status: {e}");
+                }
+            },
+        )
+    });
+    guard.state();
+    Ok(())
+}
+
+impl Guard {
+    /// `available_space` measures the free bytes of a directory, `notify` receives every
+    /// change of the status.
+    fn new(
+        data_dir: PathBuf,
+        log_dir: PathBuf,
+        thresholds: StorageThresholds,
+        available_space: impl Fn(&Path) -> io::Result<u64> + Send + Sync + 'static,
+        notify: impl Fn(StorageStatus) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            data_dir,
+            log_dir,
+            thresholds,
+            available_space: Box::new(available_space),
+            notify: Box::new(notify),
+            measurement: Mutex::new(Measurement::default()),
+        }
+    }
+
+    fn measurement(&self) -> MutexGuard<'_, Measurement> {
+        self.measurement
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Current storage state, re-measured at most every `CHECK_INTERVAL`.
+    ///
+    /// Falling into a worse state prunes old log files until the low threshold is met
+    /// again; the change is announced once pruning is done.
+    fn state(&self) -> StorageState {
+        let (previous, mut current, available) = {
+            let mut measurement = self.measurement();
+            let fresh = measurement
+                .checked_at
+                .is_some_and(|at| at.elapsed() < CHECK_INTERVAL);
+            if let (true, Some(state)) = (fresh, measurement.state) {
+                return state;
+            }
+            let previous = measurement.state;
+            let (current, available) = self.measure(&mut measurement);
+            (previous, current, available)
+        };
+
+        if current > previous.unwrap_or(StorageState::Ok) {
+            self.prune_logs(current, available);
+            // Report the space that is left after pruning
+            current = self.measure(&mut self.measurement()).0;
+        }
+        if previous != Some(current) {
+            (self.notify)(self.status());
+        }
+        current
+    }
+
+    /// Measure the free space now and classify it.
+    fn measure(&self, measurement: &mut Measurement) -> (StorageState, Option<u64>) {
+        let available = (self.available_space)(&self.data_dir).ok();
+        // If the file system can't be queried, stay with what we knew
+        let state = available.map_or(measurement.state.unwrap_or(StorageState::Ok), |available| {
+            self.thresholds.classify(available)
+        });
+        measurement.state = Some(state);
+        measurement.checked_at = Some(Instant::now());
+        measurement.available_bytes = available;
+        (state, available)
+    }
+
+    fn prune_logs(&self, state: StorageState, available: Option<u64>) {
+        let needed = available.map_or(self.thresholds.low_bytes, |available| {
+            self.thresholds.low_bytes.saturating_sub(available)
+        });
+        match prune_logs_for_space(&self.log_dir, needed) {
+            Ok(deleted) => tracing::warn!(
+                ?state,
+                available_bytes = available,
+                deleted,
+                "Storage is running low, pruned log files"
+            ),
+            Err(e) => tracing::warn!(error = %e, "Failed to prune log files for space"),
+        }
+    }
+
+    /// Make the next `state` call measure again.
+    fn recheck(&self) {
+        self.measurement().checked_at = None;
+    }
+
+    /// The last measurement, without measuring again.
+    fn status(&self) -> StorageStatus {
+        let measurement = self.measurement();
+        status_of(
+            measurement.state.unwrap_or(StorageState::Ok),
+            measurement.available_bytes,
+            self.thresholds,
+            measurement.write_error.clone(),
+        )
+    }
+
+    fn allows_log(&self, level: LogLevel) -> bool {
+        match self.state() {
+            StorageState::Ok => true,
+            StorageState::StorageLow => level > LogLevel::Debug,
+            StorageState::StorageCritical => level >= LogLevel::Warn,
+        }
+    }
+
+    fn record_write_outcome(&self, result: &Result<(), String>) {
+        let error = result.as_ref().err().cloned();
+        {
+            let mut measurement = self.measurement();
+            if measurement.write_error == error {
+                return;
+            }
+            measurement.write_error = error;
+        }
+        (self.notify)(self.status());
+    }
+}
+
+fn status_of(
+    state: StorageState,
+    available_bytes: Option<u64>,
+    thresholds: StorageThresholds,
+    last_write_error: Option<String>,
+) -> StorageStatus {
+    StorageStatus {
+        state,
+        storage_low: state != StorageState::Ok,
+        available_bytes,
+        low_threshold_bytes: thresholds.low_bytes,
+        last_write_error,
+    }
+}
+
+/// Remember the outcome of a background log write, whose caller has already returned, so
+/// a failure still reaches the UI through `storage-status`.
+pub(crate) fn record_write_outcome(result: &Result<(), String>) {
+    if let Some(guard) = GUARD.get() {
+        guard.record_write_outcome(result);
+    }
+}
+
+/// Whether an entry of `level` may still be persisted; DEBUG is shed first.
+pub(crate) fn allows_log(level: LogLevel) -> bool {
+    GUARD.get().is_none_or(|guard| guard.allows_log(level))
+}
+
+/// Describe a failed write. A full disk is named as such and forces the next
+/// `storage_state` call to measure again.
+pub(crate) fn write_error(context: &str, e: &io::Error) -> String {
+    if matches!(
+        e.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+    ) {
+        if let Some(guard) = GUARD.get() {
+            guard.recheck();
+        }
+        return format!("{context}: not enough storage space left on the device");
+    }
+    format!("{context}: {e}")
+}
+
+/// Report how much storage is left, so the UI can warn before writes fail
+#[tauri::command]
+pub async fn get_storage_status() -> Result<StorageStatus, String> {
+    Ok(match GUARD.get() {
+        Some(guard) => {
+            guard.state();
+            guard.status()
+        }
+        None => status_of(StorageState::Ok, None, StorageThresholds::default(), None),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_time::local_today;
+    use std::fs;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    const THRESHOLDS: StorageThresholds = StorageThresholds {
+        low_bytes: 1000,
+        critical_bytes: 100,
+    };
+
+    /// A guard reading free space from `available` and collecting its events.
+    fn test_guard(
+        log_dir: &Path,
+        available: &Arc<AtomicU64>,
+    ) -> (Guard, Arc<Mutex<Vec<StorageStatus>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let available = Arc::clone(available);
+        let guard = Guard::new(
+            log_dir.to_path_buf(),
+            log_dir.to_path_buf(),
+            THRESHOLDS,
+            move |_| Ok(available.load(Ordering::SeqCst)),
+            move |status| sink.lock().unwrap().push(status),
+        );
+        (guard, events)
+    }
+
+    fn states(events: &Mutex<Vec<StorageStatus>>) -> Vec<StorageState> {
+        events.lock().unwrap().iter().map(|s| s.state).collect()
+    }
+
+    #[test]
+    fn low_storage_sheds_debug_then_info() {
+        let tmp = tempfile::tempdir().unwrap();
+        let available = Arc::new(AtomicU64::new(5000));
+        let (guard, events) = test_guard(tmp.path(), &available);
+        assert!(guard.allows_log(LogLevel::Debug));
+
+        available.store(500, Ordering::SeqCst);
+        guard.recheck();
+        assert!(!guard.allows_log(LogLevel::Debug));
+        assert!(guard.allows_log(LogLevel::Info));
+
+        available.store(50, Ordering::SeqCst);
+        guard.recheck();
+        assert!(!guard.allows_log(LogLevel::Info));
+        assert!(guard.allows_log(LogLevel::Warn));
+
+        available.store(5000, Ordering::SeqCst);
+        guard.recheck();
+        assert!(guard.allows_log(LogLevel::Debug));
+
+        assert_eq!(
+            states(&events),
+            [
+                StorageState::Ok,
+                StorageState::StorageLow,
+                StorageState::StorageCritical,
+                StorageState::Ok
+            ]
+        );
+    }
+
+    #[test]
+    fn measurement_is_reused_within_check_interval() {
+        let tmp = tempfile::tempdir().unwrap();
+        let available = Arc::new(AtomicU64::new(5000));
+        let (guard, events) = test_guard(tmp.path(), &available);
+        guard.state();
+
+        available.store(50, Ordering::SeqCst);
+        assert_eq!(guard.state(), StorageState::Ok);
+        assert_eq!(states(&events), [StorageState::Ok]);
+    }
+
+    #[test]
+    fn worse_state_prunes_old_logs_before_announcing_it() {
+        let tmp = tempfile::tempdir().unwrap();
+        let old = tmp.path().join("pyre-portal-2024-01-01.log");
+        let current = tmp.path().join(format!(
+            "pyre-portal-{}.log",
+            local_today().format("%Y-%m-%d")
+        ));
+        fs::write(&old, "x".repeat(100)).unwrap();
+        fs::write(&current, "x".repeat(100)).unwrap();
+
+        let available = Arc::new(AtomicU64::new(50));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let source = Arc::clone(&available);
+        let old_path = old.clone();
+        let guard = Guard::new(
+            tmp.path().to_path_buf(),
+            tmp.path().to_path_buf(),
+            THRESHOLDS,
+            move |_| Ok(source.load(Ordering::SeqCst)),
+            move |status| sink.lock().unwrap().push((status.state, old_path.exists())),
+        );
+
+        assert_eq!(guard.state(), StorageState::StorageCritical);
+        assert!(!old.exists());
+        assert!(current.exists());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [(StorageState::StorageCritical, false)]
+        );
+
+        // Getting better prunes nothing
+        fs::write(&old, "x").unwrap();
+        available.store(500, Ordering::SeqCst);
+        guard.recheck();
+        assert_eq!(guard.state(), StorageState::StorageLow);
+        assert!(old.exists());
+    }
+
+    #[test]
+    fn pruning_that_frees_enough_space_reports_the_new_state() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("pyre-portal-2024-01-01.log"), "x").unwrap();
+        let available = Arc::new(AtomicU64::new(50));
+        let source = Arc::clone(&available);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let guard = Guard::new(
+            tmp.path().to_path_buf(),
+            tmp.path().to_path_buf(),
+            THRESHOLDS,
+            // Every measurement after the first sees the space freed by pruning
+            move |_| Ok(source.swap(5000, Ordering::SeqCst)),
+            move |status: StorageStatus| sink.lock().unwrap().push(status.state),
+        );
+
+        assert_eq!(guard.state(), StorageState::Ok);
+        assert_eq!(*events.lock().unwrap(), [StorageState::Ok]);
+    }
+
+    #[test]
+    fn write_failures_are_announced_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let available = Arc::new(AtomicU64::new(5000));
+        let (guard, events) = test_guard(tmp.path(), &available);
+        let failed = Err("Failed to write to log file: disk full".to_string());
+
+        guard.record_write_outcome(&failed);
+        guard.record_write_outcome(&failed);
+        guard.record_write_outcome(&Ok(()));
+        guard.record_write_outcome(&Ok(()));
+
+        let errors: Vec<Option<String>> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| s.last_write_error.clone())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                Some("Failed to write to log file: disk full".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn classify_uses_both_thresholds() {
+        let thresholds = StorageThresholds {
+            low_bytes: 1000,
+            critical_bytes: 100,
+        };
+        assert_eq!(thresholds.classify(5000), StorageState::Ok);
+        assert_eq!(thresholds.classify(999), StorageState::StorageLow);
+        assert_eq!(thresholds.classify(99), StorageState::StorageCritical);
+    }
+
+    #[test]
+    fn states_are_ordered_by_severity() {
+        assert!(StorageState::Ok < StorageState::StorageLow);
+        assert!(StorageState::StorageLow < StorageState::StorageCritical);
+    }
+
+    #[test]
+    fn write_error_names_full_disk() {
+        let full = io::Error::from(io::ErrorKind::StorageFull);
+        assert_eq!(
+            write_error("Failed to write log file", &full),
+            "Failed to write log file: not enough storage space left on the device"
+        );
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(write_error("Failed to write log file", &denied).contains("permission denied"));
+    }
+
+    #[test]
+    fn status_serializes_storage_low_state() {
+        let status = status_of(
+            StorageState::StorageLow,
+            Some(10),
+            StorageThresholds::default(),
+            Some("Failed to write to log file: disk full".to_string()),
+        );
+        let json = serde_json::to_value(status).unwrap();
+        assert_eq!(json["state"], "storage_low");
+        assert_eq!(json["storageLow"], true);
+        assert_eq!(json["availableBytes"], 10);
+        assert_eq!(
+            json["lastWriteError"],
+            "Failed to write to log file: disk full"
+        );
+    }
+}
@@ -21,9 +21,12 @@ interface LastSessionConfig {
 }
 
 export interface SessionSettings {
+  schema_version?: number; // Set by the backend when stored
   use_last_session: boolean; // Toggle state
   auto_save_enabled: boolean; // Always true for now
   last_session: LastSessionConfig | null;
+  // Age of last_session, set by the backend when loading; expired sessions come back as null
+  last_session_status?: 'fresh' | 'stale' | 'expired' | null;
 }
 
 /**
@@ -59,6 +62,7 @@ export async function loadSessionSettings(): Promise<SessionSettings | null> {
         useLastSession: settings.use_last_session,
         hasLastSession: !!settings.last_session,
         savedAt: settings.last_session?.saved_at,
+        lastSessionStatus: settings.last_session_status,
       });
     } else {
       logger.debug('No session settings found');